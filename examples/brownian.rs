@@ -1,6 +1,3 @@
-extern crate smallvec;
-use smallvec::SmallVec;
-
 extern crate websim;
 
 use websim::container::Container;
@@ -18,13 +15,13 @@ use websim::gfx::Graphic;
 use websim::simple_vec::Vec2 as Point;
 use websim::simple_color::Color::{Rgb};
 
-use websim::simulation::get_time;
 use websim::simple_rng::NormalDist;
-
-use std::ops::{
-	Index,
-	IndexMut,
+use websim::thermostat::{
+	Thermostat,
+	Berendsen,
 };
+use websim::neighbors::VerletList;
+use websim::boundary::Boundary;
 
 // use std::rc::Rc;
 // use std::cell::RefCell;
@@ -32,6 +29,15 @@ use std::ops::{
 use websim::simulation::{
 	SimStep,
 	Simloop,
+	SimControls,
+};
+use websim::history::Timeline;
+use websim::snapshot::{
+	Snapshot,
+	SnapshotError,
+	SnapshotWriter,
+	SnapshotReader,
+	SnapshotControls,
 };
 
 #[allow(non_upper_case_globals)]
@@ -81,21 +87,12 @@ struct State {
 
 /// Size of screen
 const SIZE : f64 = 15.0;
-/// Maximum number of bins across
-const BIMAX : usize = 15;
-
-#[inline]
-fn wrap( r: &mut Point) {
-	if r.x > SIZE {
-		r.x -= SIZE;
-	} else if r.x < 0.0 {
-		r.x += SIZE;
-	}
-	if r.y > SIZE {
-		r.y -= SIZE;
-	} else if r.y < 0.0 {
-		r.y += SIZE;
-	}
+/// Skin distance of the solvent neighbor list (nm)
+const SKIN : f64 = 0.15;
+
+/// The periodic box the particles move in
+fn boundary() -> Boundary {
+	Boundary::periodic(Point{x:SIZE, y:SIZE})
 }
 
 impl State {
@@ -114,11 +111,9 @@ impl State {
 		let mut rsol = Vec::new();
 		let mut vsol = Vec::new();
 
-		let s0 = get_time();
-		let s1 = 0_u64;
 		let vstd = 1000.0*(kB*temp/msol).sqrt(); // ns/nm
 		let mut maxwell_dist = NormalDist::new(0.0, vstd);
- 		maxwell_dist.seed(s0,s1);
+ 		maxwell_dist.seed_from_entropy();
 
 		let mut r = Point{x:spacing/2.0, y:spacing/2.0};
 		while r.y < SIZE-spacing/2.0 {
@@ -145,28 +140,15 @@ impl State {
 		}
 	}
 
-	fn step(&mut self, dt: f64, p: Params) {
+	fn step(&mut self, dt: f64, p: Params, neighbors: &mut VerletList) {
 		self.t += dt;
 
-		let (fpar0, fsol0) = force_calc(p, self.rpar, &self.rsol);
+		let (fpar0, fsol0) = force_calc(p, self.rpar, &self.rsol, neighbors);
 		let fpar0 = fpar0 + Point{x:p.force, y:0.0};
 
 		let apar0 = fpar0/p.mpar*1.0E+6_f64;
 		self.rpar = self.rpar + self.vpar*dt + 0.5*apar0*dt*dt;
-		if self.rpar.x >= SIZE {
-			self.rpar.x -= SIZE;
-			self.offset.x += SIZE;
-		} else if self.rpar.x < 0.0 {
-			self.rpar.x += SIZE;
-			self.offset.x -= SIZE;
-		}
-		if self.rpar.y >= SIZE {
-			self.rpar.y -= SIZE;
-			self.offset.y += SIZE;
-		} else if self.rpar.y < 0.0 {
-			self.rpar.y += SIZE;
-			self.offset.y -= SIZE;
-		}
+		boundary().wrap_tracked(&mut self.rpar, &mut self.offset);
 
 		{
 			let pos_vel_force = self.rsol.iter_mut()
@@ -176,12 +158,11 @@ impl State {
 
 			for (pos,&vel,&force) in pos_vel_force {
 				let a = force/p.msol*1.0E+6_f64;
-				*pos = *pos + vel*dt + 0.5*a*dt*dt;
-				wrap(pos);
+				*pos = boundary().wrap(*pos + vel*dt + 0.5*a*dt*dt);
 			}
 		}
 
-		let (fpar1, fsol1) = force_calc(p, self.rpar, &self.rsol);
+		let (fpar1, fsol1) = force_calc(p, self.rpar, &self.rsol, neighbors);
 		let fpar1 = fpar1 + Point{x:p.force, y:0.0};
 
 		let apar1 = fpar1/p.mpar*1.0E+6_f64;
@@ -201,57 +182,21 @@ impl State {
 
 	fn draw(&self, p: Params, canvas: &Canvas) {
  		let Params{rad_par, rad_sol, ..} = p;
+		let boundary = boundary();
 		canvas.clear();
-		let mut par = Graphic::circle(self.rpar, rad_par);
-		par.set_color(Rgb{r:255,g:0,b:0});
-		canvas.draw(&par);
-		for pos in self.rsol.iter().cloned() {
-			let sol = Graphic::circle(pos, rad_sol);
-			canvas.draw(&sol);
+		// Draw the periodic images of particles straddling an edge too
+		for pos in boundary.images(self.rpar, rad_par) {
+			let mut par = Graphic::circle(pos, rad_par);
+			par.set_color(Rgb{r:255,g:0,b:0});
+			canvas.draw(&par);
 		}
-	}
-}
-
-/// Structure the divides the space into square regions
-/// called 'bins'. Each bin contains a smallvec (vector
-/// on the stack - provided with the smallvec crate) that
-/// holds the solvent particles in that set. Currently
-/// it holds the indices.
-type Bin = SmallVec<[usize;10]>;
-struct BinList {
-	bins: Vec<Bin>,
-}
-
-impl Index<[usize;2]> for BinList{
-	type Output = Bin;
-
-	fn index<'a>(&'a self, idx: [usize;2]) -> &'a Bin {
-		&self.bins[idx[0]*BIMAX+idx[1]]
-	}
-}
-
-impl IndexMut<[usize;2]> for BinList{
-	fn index_mut<'a>(&'a mut self, idx: [usize;2]) -> &'a mut Bin {
-		&mut self.bins[idx[0]*BIMAX+idx[1]]
-	}
-}
-
-impl BinList{
-	fn new() -> BinList {
-		let mut bins : Vec<Bin> = Vec::with_capacity(BIMAX*BIMAX);
-		for _i in 0..BIMAX {
-			for _j in 0..BIMAX{
-				bins.push(SmallVec::<[usize;10]>::new());
+		for &pos in self.rsol.iter() {
+			for image in boundary.images(pos, rad_sol) {
+				let sol = Graphic::circle(image, rad_sol);
+				canvas.draw(&sol);
 			}
 		}
-		BinList{bins}
 	}
-
-	// fn clear(&mut self) {
-	// 	for bin in self.bins.iter_mut() {
-	// 		bin.clear();
-	// 	}
-	// }
 }
 
 /// Force calculation pieces
@@ -271,149 +216,41 @@ fn lj(r: f64, p: Params) -> f64 {
 	}
 }
 
-/// force between two solvent particles with positions ri and rj
-fn forceij(ri: Point, rj: Point, p: Params) -> Point {
-	let rij = ri-rj;
-	let r = rij.norm();
-	rij/r*lj(r, p)
-}
-
 /// force between the large particle and a solvent particle.
 fn forcepar(rpar: Point, rsol: Point, p: Params) -> Point {
 	let Params{rad_par, rad_sol, ..} = p;
 	let shift = rad_par-rad_sol;
-	let mut rij = rpar-rsol;
-	// This checks if we need to wrap around our screen
-	if rij.x < -SIZE+rad_par+rad_sol {
-		rij.x += SIZE;
-	} else if rij.x > SIZE-rad_par-rad_sol {
-		rij.x -= SIZE;
-	}
-	if rij.y < -SIZE+rad_par+rad_sol {
-		rij.y += SIZE;
-	} else if rij.y > SIZE-rad_par-rad_sol {
-		rij.y -= SIZE;
-	}
+	let rij = boundary().displacement(rpar, rsol);
 	let r = rij.norm();
 	let rprime = r-shift;
 	rij/r*lj(rprime, p)
 }
 
-use std::iter;
-fn force_calc( p: Params, rpar: Point, rsol: &[Point]) -> (Point, Vec<Point>) {
-	// Initialize the forces
+fn force_calc( p: Params, rpar: Point, rsol: &[Point], neighbors: &mut VerletList) -> (Point, Vec<Point>) {
 	let mut fpar = Point{x:0.0, y:0.0};
-	let mut fsol : Vec<Point> = Vec::new();
-	let len = rsol.iter().count();
-	fsol.extend(iter::repeat(Point{x:0.0, y:0.0}).take(len));
-
-	// Loop over all particle and stick them into bins
-	let size = SIZE/BIMAX as f64;
-	let mut binlist = BinList::new();
-	for (idx,pos) in rsol.iter().enumerate() {
-		let i = (pos.y/size) as usize;
-		let j = (pos.x/size) as usize;
-		binlist[[i,j]].push(idx);
-	}
-// 	// Loop over the bins and calculate the forces
-	for i in 0..BIMAX {
-		for j in 0..BIMAX {
-			let bin = &binlist[[i,j]];
-			match bin.len() {
-				0 => {}, // Nothing here to see!
-				1 => { // calc forces in neighboring cells
-					let idx_i = bin[0];
-					let ri = rsol[idx_i];
-					let im = if i != 0 { i-1 } else { BIMAX-1 };
-					let jm = if j != 0 { j-1 } else { BIMAX-1 };
-					let jp = if j != BIMAX-1 { j+1 } else { 0 };
-					let neighbors = [ (im, jm), (im, j), (im, jp), (i, jm) ];
-					for &(ii,jj) in neighbors.iter() {
-						let other_bin = &binlist[[ii,jj]];
-						for &idx_j in other_bin.iter() {
-							let mut rj = rsol[idx_j];
-
-							if im == BIMAX-1 && ii == im {
-								rj.y -= SIZE;
-							}
-							if jm == BIMAX-1 && jj == jm {
-								rj.x -= SIZE;	
-							}
-							if jp == 0 && jj == jp {
-								rj.x += SIZE;
-							}
-
-							let f = forceij(ri, rj, p);
-							fsol[idx_i] = fsol[idx_i] + f;
-							fsol[idx_j] = fsol[idx_j] - f;
-						}
-					}
-					// calc_big_particle_force;
-					let f = forcepar(rpar, ri, p);
-					fpar = fpar + f;
-					fsol[idx_i] = fsol[idx_i] - f;
-				},
-				_ => { // calc forces between particles in same bin
-					for k in 0..bin.len()-1 {
-						for l in k+1..bin.len() {
-							let rk = rsol[bin[k]];
-							let rl = rsol[bin[l]];
-							let f = forceij(rk,rl,p);
-							fsol[bin[k]] = fsol[bin[k]] + f;
-							fsol[bin[l]] = fsol[bin[l]] - f;
-						}
-					}
-					for &idx_i in bin.iter() {
-						// calc forces in neighboring cells
-						let ri = rsol[idx_i];
-						let im = if i != 0 { i-1 } else { BIMAX-1 };
-						let jm = if j != 0 { j-1 } else { BIMAX-1 };
-						let jp = if j != BIMAX-1 { j+1 } else { 0 };
-						let neighbors = [ (im, jm), (im, j), (im, jp), (i, jm) ];
-						for &(ii,jj) in neighbors.iter() {
-							let other_bin = &binlist[[ii,jj]];
-							for &idx_j in other_bin.iter() {
-								let mut rj = rsol[idx_j];
-
-								if im == BIMAX-1 && ii == im {
-									rj.y -= SIZE;
-								}
-								if jm == BIMAX-1 && jj == jm {
-									rj.x -= SIZE;	
-								}
-								if jp == 0 && jj == jp {
-									rj.x += SIZE;
-								}
-
-								let f = forceij(ri, rj, p);
-								fsol[idx_i] = fsol[idx_i] + f;
-								fsol[idx_j] = fsol[idx_j] - f;
-							}
-						}
-						// calc forces on the big particle
-						let f = forcepar(rpar, ri, p);
-						fpar = fpar + f;
-						fsol[idx_i] = fsol[idx_i] - f;
-					}
-				}
-			}
-		}
+	let mut fsol = vec!(Point{x:0.0, y:0.0}; rsol.len());
+
+	// Forces between pairs of solvent particles
+	neighbors.update(rsol);
+	neighbors.for_each_pair(rsol, |pair| {
+		let f = pair.rij/pair.distance()*lj(pair.distance(), p);
+		fsol[pair.i] = fsol[pair.i] + f;
+		fsol[pair.j] = fsol[pair.j] - f;
+	});
+
+	// Forces between the large particle and the solvent
+	for (&ri, fi) in rsol.iter().zip(fsol.iter_mut()) {
+		let f = forcepar(rpar, ri, p);
+		fpar = fpar + f;
+		*fi = *fi - f;
 	}
 	(fpar, fsol)
 }
 
-fn reset_temp( vsol: &mut [Point], p: Params) {
-	let Params{msol, temp, ..} = p;
-	let mut v2avg = 0.0;
-	for vel in vsol.iter() {
-		v2avg += vel.norm_squared();
-	}
-	v2avg /= vsol.len() as f64;
-	let temp_current = msol/2.0/kB*v2avg*1.0e-6_f64;
-	let alpha = (temp/temp_current).sqrt();
-	for vel in vsol.iter_mut() {
-		*vel = *vel*alpha;
-	}
+/// Thermostat holding the solvent at the temperature, with kT in
+/// units of the solvent mass times (nm/ns)^2
+fn thermostat(p: Params) -> Berendsen {
+	Berendsen{kt: kB*p.temp*1.0e+6_f64, tau: THERMOSTAT_TAU}
 }
 
 #[derive(Debug,Clone)]
@@ -421,6 +258,10 @@ struct FullSim{
 	writing: bool,
 	step_count: u32,
 	state: State,
+	neighbors: VerletList,
+	thermostat: Berendsen,
+	masses: Vec<f64>,
+	rng: NormalDist,
 	p: Params,
 	canvas: Canvas,
 	textarea: TextArea,
@@ -445,24 +286,92 @@ impl FullSim {
 	}
 }
 
+/// Wall-clock time (ms) per physics step, four steps per 60Hz frame
+const WALL_DT : f64 = 4.166666666666666666666666666666666666666666666666_f64;
+/// Physics time step (ns)
+const PHYSICS_DT : f64 = 0.000025;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 8;
+/// Physics steps between lines of output
+const STEPS_PER_OUTPUT : u32 = 80;
+/// Relaxation time of the solvent temperature (ns), 80 physics steps
+const THERMOSTAT_TAU : f64 = 0.002;
+/// Physics steps between recorded history snapshots
+const STEPS_PER_HISTORY : u64 = 20;
+/// Snapshots kept in the history, about 20 seconds of running
+const HISTORY_LENGTH : usize = 240;
+
 impl SimStep for FullSim {
-	fn step( &mut self, _dt: f64) {
-		if self.step_count == 19 {
-			reset_temp(&mut self.state.vsol, self.p);
+	fn update( &mut self, _dt: f64) {
+		self.step_count += 1;
+		if self.step_count == STEPS_PER_OUTPUT {
 			if self.writing { 
 				self.writeln(); 
 			}
 			self.step_count = 0;
-		} else {
-			self.step_count += 1;
 		}
-		let dt = 0.000025;
-		self.state.step(dt, self.p);
-		self.state.step(dt, self.p);
-		self.state.step(dt, self.p);
-		self.state.step(dt, self.p);
+		self.state.step(PHYSICS_DT, self.p, &mut self.neighbors);
+		self.thermostat.apply(&mut self.state.vsol, &self.masses, PHYSICS_DT, &mut self.rng);
+	}
+
+	fn render(&mut self, _alpha: f64) {
 		self.state.draw(self.p, &self.canvas);
 	}
+
+	fn on_start(&mut self) {
+		if self.writing {
+			self.writehead();
+		}
+	}
+
+	fn on_stop(&mut self) {
+		if self.writing {
+			self.writefoot();
+		}
+	}
+
+	fn on_reset(&mut self) {
+		self.state = State::init(0.6, self.p);
+	}
+}
+
+/// Snapshots hold the particles and the step count, but not the
+/// parameters, which stay set by the sliders.
+impl Snapshot for FullSim {
+	fn snapshot(&self) -> String {
+		SnapshotWriter::new()
+			.f64("t", self.state.t)
+			.u64("step_count", self.step_count as u64)
+			.vec2("rpar", self.state.rpar)
+			.vec2("vpar", self.state.vpar)
+			.vec2("offset", self.state.offset)
+			.vec2s("rsol", &self.state.rsol)
+			.vec2s("vsol", &self.state.vsol)
+			.finish()
+	}
+
+	fn restore(&mut self, snapshot: &str) -> Result<(), SnapshotError> {
+		let reader = SnapshotReader::new(snapshot);
+		let rsol = reader.vec2s("rsol")?;
+		let vsol = reader.vec2s("vsol")?;
+		if rsol.len() != vsol.len() {
+			return Err(SnapshotError::Mismatch(String::from("solvent positions and velocities differ in number")));
+		}
+		if rsol.len() != self.masses.len() {
+			return Err(SnapshotError::Mismatch(format!("snapshot has {} solvent particles, not {}", rsol.len(), self.masses.len())));
+		}
+		let state = State{
+			t: reader.f64("t")?,
+			rpar: reader.vec2("rpar")?,
+			vpar: reader.vec2("vpar")?,
+			offset: reader.vec2("offset")?,
+			rsol,
+			vsol,
+		};
+		self.step_count = (reader.u64("step_count")? as u32) % STEPS_PER_OUTPUT;
+		self.state = state;
+		Ok(())
+	}
 }
 
 fn main() {
@@ -473,15 +382,14 @@ fn main() {
 	let vis = Container::new("vis");
 	let mut canvas = Canvas::new("canvas");
 	vis.add( &canvas);
-	let sim_control = Toggle::new("start_stop", "Start", "Stop");
-	vis.add( &sim_control);
-	let sim_reset = Button::new("reset", "Reset");
-	vis.add( &sim_reset);
 	app.add( &vis);
 
 	let controls = Container::new("controls");
 	let force_slider = Range::new("f_slider", "Force (pN) : ", 0.0, 100.0, 1.0, 10.0);
 	controls.add( &force_slider);
+	let speed_slider = Range::new("speed_slider", "Speed : ", 0.1, 4.0, 0.1, 0.5);
+	speed_slider.set(1.0);
+	controls.add( &speed_slider);
 	app.add( &controls);
 
 	let output = Container::new("output");
@@ -500,72 +408,59 @@ fn main() {
 	let p = Params::init();
 	let state = State::init(0.6,p);
 	state.draw( p, &canvas);
+	let masses = vec!(p.msol; state.rsol.len());
 
 	let sim = FullSim{
 		writing: false,
 		step_count: 0,
 		p,
 		state,
+		neighbors: VerletList::new(boundary(), 2.0*p.rad_sol, SKIN),
+		thermostat: thermostat(p),
+		masses,
+		rng: NormalDist::new(0.0, 1.0),
 		canvas: canvas.clone(),
 		textarea: textarea.clone(),
 	};
 
 	let ref_sim = Simloop::new_ref(sim);
-	// Simloop::start_loop(ref_sim.clone());
+	{
+		let mut simloop = ref_sim.borrow_mut();
+		simloop.set_fixed_step(WALL_DT, MAX_STEPS_PER_FRAME);
+		simloop.record_history(HISTORY_LENGTH, STEPS_PER_HISTORY);
+	}
 
-	sim_control.add_toggle_function({
-		let ref_sim = ref_sim.clone();
-		move | status:bool | {
-			if status {
-				Simloop::start_loop(ref_sim.clone());
-				let sim = &mut ref_sim.borrow_mut().state;
-				if sim.writing {
-					sim.writehead();
-				}
-			} else {
-				ref_sim.borrow_mut().stop_loop();
-				let sim = &mut ref_sim.borrow_mut().state;
-				if sim.writing {
-					sim.writefoot();
-				}
-			}
-		}
-	});
+	let sim_controls = SimControls::new("sim_controls", ref_sim.clone());
+	vis.add( &sim_controls);
+	let snapshot_controls = SnapshotControls::new("snapshot", "brownian.txt", ref_sim.clone());
+	vis.add( &snapshot_controls);
+	let timeline = Timeline::new("timeline", "History (%) : ", ref_sim.clone());
+	vis.add( &timeline);
 
-	sim_reset.add_button_function({
+	force_slider.add_continuous_range_function({
 		let ref_sim = ref_sim.clone();
-		let sim_control = sim_control.clone();
-		let force_slider = force_slider.clone();
-		move | _:bool | {
-			if sim_control.query() {
-				ref_sim.borrow_mut().stop_loop();
-				let sim = &mut ref_sim.borrow_mut().state;
-				sim_control.set(false);
-				sim.writefoot();
-			}
+		move | val:f64 | {
 			let sim = &mut ref_sim.borrow_mut().state;
-			sim.state = State::init(0.6, sim.p);
-			sim.p.force = force_slider.query();
-			sim.state.draw( sim.p, &sim.canvas);
+			sim.p.force = val;
 		}
 	});
 
-	force_slider.add_continuous_range_function({
+	speed_slider.add_continuous_range_function({
 		let ref_sim = ref_sim.clone();
 		move | val:f64 | {
-			let sim = &mut ref_sim.borrow_mut().state;
-			sim.p.force = val;
+			ref_sim.borrow_mut().set_time_scale(val);
 		}
 	});
 
 	output_control.add_toggle_function({
-		let sim_control = sim_control.clone();
 		let ref_sim = ref_sim.clone();
 		move | status:bool | {
-			let sim = &mut ref_sim.borrow_mut().state;
+			let mut simloop = ref_sim.borrow_mut();
+			let running = simloop.is_running();
+			let sim = &mut simloop.state;
 			sim.writing = status;
 			sim.step_count = 0;
-			if sim_control.query() {
+			if running {
 				if status {
 					sim.writehead();
 				} else {
@@ -577,12 +472,13 @@ fn main() {
 
 	output_clear.add_button_function({
 		let ref_sim = ref_sim.clone();
-		let sim_control = sim_control.clone();
 		let textarea = textarea.clone();
 		move | _:bool | {
 			textarea.clear();
-			let sim = &mut ref_sim.borrow_mut().state;
-			if sim_control.query() && sim.writing {
+			let mut simloop = ref_sim.borrow_mut();
+			let running = simloop.is_running();
+			let sim = &mut simloop.state;
+			if running && sim.writing {
 				sim.writehead();
 			}
 		}
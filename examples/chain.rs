@@ -0,0 +1,121 @@
+///Normal modes of a taut chain of masses joined by springs
+
+extern crate websim;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::control::Range;
+use websim::integrate::Rk4;
+use websim::simple_vec::Vec2 as Point;
+use websim::diagnostics::{Diagnostics, Readout};
+
+use websim::springs::SpringNetwork;
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+};
+
+/// Masses that are free to move
+const N : usize = 8;
+/// Mass of each node (kg)
+const M : f64 = 0.1;
+/// Spring constant of each spring (N/m)
+const K : f64 = 200.0;
+/// Length of the chain between the pinned ends (m)
+const LENGTH : f64 = 9.0;
+/// Amplitude of the starting mode (m)
+const AMPLITUDE : f64 = 0.8;
+/// Physics time step (ms)
+const PHYSICS_DT : f64 = 1.0;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 40;
+
+/// The chain stretched to twice its rest length, displaced into the
+/// shape of normal mode `mode`
+fn init_chain(mode: usize) -> SpringNetwork {
+	let mut chain = SpringNetwork::chain(Point{x:0.0, y:0.0}, Point{x:LENGTH, y:0.0}, N + 2, M, K, 0.0);
+	for s in chain.springs.iter_mut() {
+		s.rest_length *= 0.5;
+	}
+	chain.node_radius = 0.15;
+	for j in 1..=N {
+		let phase = (mode*j) as f64*::std::f64::consts::PI/(N + 1) as f64;
+		chain.nodes[j].r.y = AMPLITUDE*phase.sin();
+	}
+	chain
+}
+
+#[derive(Debug,Clone)]
+struct Chain {
+	mode: usize,
+	chain: SpringNetwork,
+	diagnostics: Diagnostics,
+	canvas: Canvas,
+	readout: Readout,
+}
+
+impl Chain {
+	fn draw(&self) {
+		self.canvas.clear();
+		self.canvas.draw(&self.chain.graphic());
+		self.readout.show(&self.diagnostics);
+	}
+}
+
+impl SimStep for Chain {
+	fn update(&mut self, dt: f64) {
+		self.chain.step(&Rk4, dt/1000.0); // convert to seconds
+		self.diagnostics.record(&self.chain);
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		self.chain = init_chain(self.mode);
+		self.diagnostics.reset();
+	}
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+
+	let mut canvas = Canvas::new("chain");
+	canvas.set_width_height(600, 300);
+	canvas.set_window(((-0.5, -2.5), (LENGTH+1.0, 5.0)));
+	app.add( &canvas);
+
+	let mode_slider = Range::new("mode", "Mode : ", 1.0, N as f64, 1.0, 1.0);
+	app.add( &mode_slider);
+	let readout = Readout::new("diagnostics");
+	app.add( &readout);
+
+	let sim = Chain{
+		mode: 1,
+		chain: init_chain(1),
+		diagnostics: Diagnostics::new(),
+		canvas,
+		readout,
+	};
+	sim.draw();
+
+	let ref_sim = Simloop::new_ref(sim);
+	ref_sim.borrow_mut().set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+	let controls = SimControls::new("controls", ref_sim.clone());
+	app.add( &controls);
+
+	mode_slider.add_range_function({
+		let ref_sim = ref_sim.clone();
+		move | val:f64 | {
+			let sim = &mut ref_sim.borrow_mut().state;
+			sim.mode = val as usize;
+			sim.chain = init_chain(sim.mode);
+			sim.diagnostics.reset();
+			sim.draw();
+		}
+	});
+}
@@ -0,0 +1,146 @@
+///Damped and undamped oscillators side by side
+
+extern crate websim;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::control::{
+	Button,
+	Range,
+};
+
+use websim::extra;
+use websim::gfx::Graphic;
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+	Scheduler,
+};
+
+use websim::simple_vec::Vec2 as Point;
+
+/// Spring rest length (m)
+const L0 : f64 = 0.5;
+/// Mass (kg)
+const M : f64 = 1.0;
+/// Spring constant (N/m)
+const K : f64 = 10.0;
+/// Initial stretch of the spring (m)
+const A0 : f64 = 0.3;
+/// Physics time step (ms)
+const PHYSICS_DT : f64 = 1.0;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 40;
+
+/// A mass on a horizontal spring with linear drag
+#[derive(Debug,Clone)]
+struct Oscillator {
+	x: f64, // stretch of the spring
+	v: f64, // velocity
+	b: f64, // drag coefficient
+	canvas: Canvas,
+}
+
+impl Oscillator {
+	fn new(b: f64, canvas: Canvas) -> Oscillator {
+		Oscillator{x: A0, v: 0.0, b, canvas}
+	}
+
+	fn accel(&self, x: f64, v: f64) -> f64 {
+		(-K*x - self.b*v)/M
+	}
+
+	fn draw(&self) {
+		let wall = Graphic::line(&[Point{x: 0.0, y: -0.2}, Point{x: 0.0, y: 0.2}]);
+		let r = Point{x: L0 + self.x, y: 0.0};
+		let spring = extra::spring(8, L0, Point{x: 0.0, y: 0.0}, r);
+		let mass = Graphic::circle(r + Point{x: 0.1, y: 0.0}, 0.1);
+		self.canvas.clear();
+		self.canvas.draw(&wall);
+		self.canvas.draw(&spring);
+		self.canvas.draw(&mass);
+	}
+}
+
+impl SimStep for Oscillator {
+	/// Velocity Verlet, with the drag evaluated at the half step
+	fn update(&mut self, dt: f64) {
+		let dt = dt/1000.0; // convert to seconds
+		let a0 = self.accel(self.x, self.v);
+		let v_half = self.v + 0.5*a0*dt;
+		self.x += v_half*dt;
+		let a1 = self.accel(self.x, v_half);
+		self.v = v_half + 0.5*a1*dt;
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		self.x = A0;
+		self.v = 0.0;
+	}
+}
+
+fn oscillator( name: &str, b: f64, vis: &Container, scheduler: &Scheduler) -> Rc<RefCell<Simloop<Oscillator>>> {
+	let mut canvas = Canvas::new(&format!("{}_canvas", name));
+	canvas.set_width_height(400, 200);
+	canvas.set_window(((-0.1, -0.5), (1.5, 1.0)));
+	vis.add( &canvas);
+	let osc = Oscillator::new(b, canvas);
+	osc.draw();
+	let simloop = Simloop::new_ref(osc);
+	simloop.borrow_mut().set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+	scheduler.add( &simloop);
+	let controls = SimControls::new(&format!("{}_controls", name), simloop.clone());
+	vis.add( &controls);
+	simloop
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+	let left = Container::new("undamped");
+	let right = Container::new("damped");
+	let controls = Container::new("controls");
+	app.add( &left);
+	app.add( &right);
+	app.add( &controls);
+
+	let scheduler = Scheduler::new();
+	let _undamped = oscillator("undamped", 0.0, &left, &scheduler);
+	let damped = oscillator("damped", 0.5, &right, &scheduler);
+
+	let drag_slider = Range::new("b", "b (kg/s) : ", 0.0, 5.0, 0.1, 0.5);
+	drag_slider.set(0.5);
+	controls.add( &drag_slider);
+	drag_slider.add_continuous_range_function({
+		let damped = damped.clone();
+		move | val:f64 | {
+			damped.borrow_mut().state.b = val;
+		}
+	});
+
+	let run_all = Button::new("run_all", "Run Both");
+	let pause_all = Button::new("pause_all", "Pause Both");
+	controls.add( &run_all);
+	controls.add( &pause_all);
+	run_all.add_button_function({
+		let scheduler = scheduler.clone();
+		move | _:bool | {
+			scheduler.start_all();
+		}
+	});
+	pause_all.add_button_function({
+		let scheduler = scheduler.clone();
+		move | _:bool | {
+			scheduler.stop_all();
+		}
+	});
+}
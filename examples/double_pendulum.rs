@@ -0,0 +1,110 @@
+///A double pendulum built from constraints, tracing the path of its lower bob
+
+extern crate websim;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::gfx::Graphic;
+use websim::simple_vec::Vec2 as Point;
+use websim::simple_color::Color::{Rgb};
+
+use websim::constraints::{
+	Constraints,
+	Constraint,
+	Gravity,
+};
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+};
+
+/// Length of each rod (m)
+const L : f64 = 1.0;
+/// Mass of each bob (kg)
+const M : f64 = 1.0;
+/// Physics time step (ms)
+const PHYSICS_DT : f64 = 1.0;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 40;
+/// Points kept in the trace of the lower bob
+const TRACE_LENGTH : usize = 2000;
+
+#[derive(Debug,Clone)]
+struct Pendulum {
+	links: Constraints,
+	gravity: Gravity,
+	r: Vec<Point>,
+	v: Vec<Point>,
+	trace: Vec<Point>,
+	canvas: Canvas,
+}
+
+impl Pendulum {
+	/// Both rods horizontal, at rest
+	fn new(canvas: Canvas) -> Pendulum {
+		let mut links = Constraints::new();
+		links.add(Constraint::Pin{i: 0, point: Point{x:0.0, y:0.0}, length: L})
+			.add(Constraint::Rod{i: 0, j: 1, length: L});
+		Pendulum{
+			links,
+			gravity: Gravity{g: Point{x:0.0, y:-9.8}, masses: vec!(M, M)},
+			r: vec!(Point{x:L, y:0.0}, Point{x:2.0*L, y:0.0}),
+			v: vec!(Point{x:0.0, y:0.0}; 2),
+			trace: Vec::new(),
+			canvas,
+		}
+	}
+
+	fn draw(&self) {
+		self.canvas.clear();
+		if self.trace.len() > 1 {
+			let mut trace = Graphic::line(&self.trace);
+			trace.set_color(Rgb{r:200, g:80, b:80});
+			self.canvas.draw(&trace);
+		}
+		let rods = Graphic::line(&[Point{x:0.0, y:0.0}, self.r[0], self.r[1]]);
+		self.canvas.draw(&rods);
+		for &r in self.r.iter() {
+			self.canvas.draw(&Graphic::circle(r, 0.08));
+		}
+	}
+}
+
+impl SimStep for Pendulum {
+	fn update(&mut self, dt: f64) {
+		let dt = dt/1000.0; // convert to seconds
+		self.links.step(&self.gravity, &mut self.r, &mut self.v, dt);
+		self.trace.push(self.r[1]);
+		if self.trace.len() > TRACE_LENGTH {
+			self.trace.remove(0);
+		}
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		*self = Pendulum::new(self.canvas.clone());
+	}
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+
+	let mut canvas = Canvas::new("pendulum");
+	canvas.set_width_height(500, 500);
+	canvas.set_window(((-2.2*L, -2.2*L), (4.4*L, 4.4*L)));
+	app.add( &canvas);
+
+	let pendulum = Pendulum::new(canvas);
+	pendulum.draw();
+
+	let ref_pendulum = Simloop::new_ref(pendulum);
+	ref_pendulum.borrow_mut().set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+	let controls = SimControls::new("controls", ref_pendulum.clone());
+	app.add( &controls);
+}
@@ -0,0 +1,126 @@
+///Two disk galaxies colliding, with Barnes-Hut gravity
+
+extern crate websim;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::gfx::Graphic;
+use websim::simple_vec::Vec2 as Point;
+use websim::simple_color::Color::{Rgb};
+use websim::simple_rng::Rng;
+use websim::symplectic::StormerVerlet;
+
+use websim::nbody::{
+	NBody,
+	Gravitation,
+	Method,
+};
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+};
+
+/// Mass of the core of each galaxy, in units where G = 1
+const CORE_MASS : f64 = 1.0;
+/// Stars in each galaxy
+const N_STARS : usize = 1000;
+/// Mass of each star
+const STAR_MASS : f64 = 1.0e-4;
+/// Radius of each galactic disk
+const DISK_RADIUS : f64 = 1.0;
+/// Softening length
+const SOFTENING : f64 = 0.05;
+/// Barnes-Hut opening angle
+const THETA : f64 = 0.7;
+/// Simulation time units per second
+const TIME_SCALE : f64 = 1.0;
+/// Physics time step (ms)
+const PHYSICS_DT : f64 = 5.0;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 4;
+
+/// Add a galaxy centered at `center` moving with `velocity`: a core
+/// with a disk of stars on circular orbits, turning counterclockwise
+/// or clockwise
+fn add_galaxy(system: &mut NBody, rng: &mut Rng, center: Point, velocity: Point, counterclockwise: bool) {
+	system.add(CORE_MASS, center, velocity);
+	let turn = if counterclockwise { 1.0 } else { -1.0 };
+	for _ in 0..N_STARS {
+		let radius = DISK_RADIUS*(0.1 + 0.9*rng.uniform());
+		let angle = 2.0*std::f64::consts::PI*rng.uniform();
+		let direction = Point{x:angle.cos(), y:angle.sin()};
+		// Circular speed about the core, ignoring the other stars
+		let r2 = radius*radius + SOFTENING*SOFTENING;
+		let speed = (CORE_MASS*radius*radius/(r2*r2.sqrt())).sqrt();
+		let v = velocity + turn*speed*Point{x:-direction.y, y:direction.x};
+		system.add(STAR_MASS, center + radius*direction, v);
+	}
+}
+
+fn init_system() -> NBody {
+	let mut rng = Rng::from_entropy();
+	let method = Method::BarnesHut{theta: THETA};
+	let mut system = NBody::new(Gravitation::new(1.0, SOFTENING, method));
+	add_galaxy(&mut system, &mut rng, Point{x:-2.5, y:-0.8}, Point{x:0.35, y:0.0}, true);
+	add_galaxy(&mut system, &mut rng, Point{x:2.5, y:0.8}, Point{x:-0.35, y:0.0}, false);
+	system.to_center_of_mass_frame();
+	system
+}
+
+#[derive(Debug,Clone)]
+struct Galaxies {
+	system: NBody,
+	canvas: Canvas,
+}
+
+impl Galaxies {
+	fn draw(&self) {
+		self.canvas.clear();
+		// Each galaxy is its core followed by its stars
+		for (i, &r) in self.system.q.iter().enumerate() {
+			let (mut dot, color) = if i % (N_STARS + 1) == 0 {
+				(Graphic::circle(r, 0.06), Rgb{r:220, g:60, b:60})
+			} else if i <= N_STARS {
+				(Graphic::circle(r, 0.015), Rgb{r:60, g:90, b:200})
+			} else {
+				(Graphic::circle(r, 0.015), Rgb{r:200, g:140, b:30})
+			};
+			dot.set_color(color);
+			self.canvas.draw(&dot);
+		}
+	}
+}
+
+impl SimStep for Galaxies {
+	fn update(&mut self, dt: f64) {
+		self.system.step(&StormerVerlet, TIME_SCALE*dt/1000.0); // convert to seconds
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		self.system = init_system();
+	}
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+
+	let mut canvas = Canvas::new("galaxies");
+	canvas.set_width_height(600, 600);
+	canvas.set_window(((-5.0, -5.0), (10.0, 10.0)));
+	app.add( &canvas);
+
+	let galaxies = Galaxies{system: init_system(), canvas};
+	galaxies.draw();
+
+	let ref_galaxies = Simloop::new_ref(galaxies);
+	ref_galaxies.borrow_mut().set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+	let controls = SimControls::new("controls", ref_galaxies.clone());
+	app.add( &controls);
+}
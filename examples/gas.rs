@@ -0,0 +1,150 @@
+///An ideal gas of hard disks relaxing to the Maxwell-Boltzmann distribution
+
+extern crate websim;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::gfx::Graphic;
+use websim::simple_vec::Vec2 as Point;
+use websim::simple_color::Color::{Rgb};
+use websim::simple_rng::Rng;
+
+use websim::boundary::Boundary;
+use websim::diagnostics::{Diagnostics, Readout};
+use websim::hard_disks::{
+	HardDisks,
+	Disk,
+};
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+};
+
+/// Size of the box
+const SIZE : f64 = 20.0;
+/// Disks along each side of the starting grid
+const N_SIDE : usize = 12;
+/// Disk radius
+const RADIUS : f64 = 0.4;
+/// Starting speed of every disk
+const SPEED : f64 = 4.0;
+/// Width of the bins of the speed histogram
+const BIN : f64 = 0.5;
+/// Number of bins in the speed histogram
+const N_BINS : usize = 24;
+
+/// Disks on a grid, all with the same speed in random directions
+fn init_disks() -> HardDisks {
+	let mut rng = Rng::from_entropy();
+	let mut disks = HardDisks::new(Boundary::reflecting(Point{x:SIZE, y:SIZE}));
+	let spacing = SIZE/N_SIDE as f64;
+	for i in 0..N_SIDE {
+		for j in 0..N_SIDE {
+			let angle = 2.0*std::f64::consts::PI*rng.uniform();
+			let r = Point{x:(i as f64 + 0.5)*spacing, y:(j as f64 + 0.5)*spacing};
+			let v = SPEED*Point{x:angle.cos(), y:angle.sin()};
+			disks.add(Disk{r, v, radius: RADIUS, mass: 1.0});
+		}
+	}
+	disks
+}
+
+#[derive(Debug,Clone)]
+struct Gas {
+	disks: HardDisks,
+	// Counts of speeds in each bin, sampled every frame
+	histogram: Vec<u64>,
+	diagnostics: Diagnostics,
+	canvas: Canvas,
+	plot: Canvas,
+	readout: Readout,
+}
+
+impl Gas {
+	fn draw(&self) {
+		self.canvas.clear();
+		for disk in self.disks.disks() {
+			let mut circle = Graphic::circle(disk.r, disk.radius);
+			// Fast disks are red, slow ones blue
+			let s = (disk.v.norm()/(2.0*SPEED)).min(1.0);
+			circle.set_color(Rgb{r:(255.0*s) as u8, g:0, b:(255.0*(1.0-s)) as u8});
+			self.canvas.draw(&circle);
+		}
+
+		// Histogram of the speeds against the Maxwell-Boltzmann
+		// distribution at the same temperature, kT = m <v²>/2
+		self.plot.clear();
+		let total = self.histogram.iter().sum::<u64>().max(1) as f64;
+		for (i, &count) in self.histogram.iter().enumerate() {
+			let height = count as f64/(total*BIN);
+			let bar = Graphic::rect(Point{x:i as f64*BIN, y:0.0}, Point{x:BIN, y:height});
+			self.plot.draw(&bar);
+		}
+		let kt = SPEED*SPEED/2.0;
+		let curve : Vec<Point> = (0..=100).map( |i| {
+			let v = i as f64*(N_BINS as f64*BIN)/100.0;
+			Point{x:v, y:v/kt*(-v*v/(2.0*kt)).exp()}
+		}).collect();
+		let mut mb = Graphic::line(&curve);
+		mb.set_color(Rgb{r:255, g:0, b:0});
+		self.plot.draw(&mb);
+		self.readout.show(&self.diagnostics);
+	}
+}
+
+impl SimStep for Gas {
+	fn update(&mut self, dt: f64) {
+		self.disks.advance(dt/1000.0); // convert to seconds
+		for disk in self.disks.disks() {
+			let bin = (disk.v.norm()/BIN) as usize;
+			if bin < N_BINS {
+				self.histogram[bin] += 1;
+			}
+		}
+		self.diagnostics.record(&self.disks);
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		self.disks = init_disks();
+		self.histogram = vec!(0; N_BINS);
+		self.diagnostics.reset();
+	}
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+	let vis = Container::new("vis");
+	app.add( &vis);
+
+	let mut canvas = Canvas::new("gas");
+	canvas.set_width_height(400, 400);
+	canvas.set_window(((0.0, 0.0), (SIZE, SIZE)));
+	vis.add( &canvas);
+	let mut plot = Canvas::new("speeds");
+	plot.set_width_height(400, 400);
+	plot.set_window(((0.0, 0.0), (N_BINS as f64*BIN, 0.5)));
+	vis.add( &plot);
+	let readout = Readout::new("diagnostics");
+	app.add( &readout);
+
+	let gas = Gas{
+		disks: init_disks(),
+		histogram: vec!(0; N_BINS),
+		diagnostics: Diagnostics::new(),
+		canvas,
+		plot,
+		readout,
+	};
+	gas.draw();
+
+	let ref_gas = Simloop::new_ref(gas);
+	let controls = SimControls::new("controls", ref_gas.clone());
+	app.add( &controls);
+}
@@ -0,0 +1,136 @@
+///Planets orbiting a star, with direct summation of their gravity
+
+extern crate websim;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::gfx::Graphic;
+use websim::simple_vec::Vec2 as Point;
+use websim::simple_color::Color::{Rgb};
+use websim::symplectic::Yoshida4;
+use websim::diagnostics::{Diagnostics, Readout};
+
+use websim::nbody::{
+	NBody,
+	Gravitation,
+	Method,
+};
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+};
+
+/// Mass of the star, in units where G = 1
+const STAR_MASS : f64 = 1.0;
+/// Mass, orbital radius and eccentricity of each planet
+const PLANETS : [(f64, f64, f64); 4] = [
+	(2.0e-4, 0.4, 0.2),
+	(1.0e-4, 0.7, 0.0),
+	(2.0e-3, 1.2, 0.05),
+	(5.0e-4, 1.8, 0.1),
+];
+/// Simulation time units per second
+const TIME_SCALE : f64 = 2.0;
+/// Physics time step (ms)
+const PHYSICS_DT : f64 = 2.0;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 40;
+/// Points kept in the trace of each planet
+const TRACE_LENGTH : usize = 500;
+
+/// The planets starting at the far point of their orbits, all lined
+/// up, in the center of mass frame
+fn init_system() -> NBody {
+	let mut system = NBody::new(Gravitation::new(1.0, 0.0, Method::Direct));
+	system.add(STAR_MASS, Point{x:0.0, y:0.0}, Point{x:0.0, y:0.0});
+	for &(mass, a, e) in PLANETS.iter() {
+		// Speed at aphelion from the vis-viva equation
+		let r = a*(1.0 + e);
+		let speed = (STAR_MASS*(2.0/r - 1.0/a)).sqrt();
+		system.add(mass, Point{x:r, y:0.0}, Point{x:0.0, y:speed});
+	}
+	system.to_center_of_mass_frame();
+	system
+}
+
+#[derive(Debug,Clone)]
+struct Planets {
+	system: NBody,
+	traces: Vec<Vec<Point>>,
+	diagnostics: Diagnostics,
+	canvas: Canvas,
+	readout: Readout,
+}
+
+impl Planets {
+	fn new(canvas: Canvas, readout: Readout) -> Planets {
+		Planets{
+			system: init_system(),
+			traces: vec!(Vec::new(); PLANETS.len()),
+			diagnostics: Diagnostics::new(),
+			canvas,
+			readout,
+		}
+	}
+
+	fn draw(&self) {
+		self.canvas.clear();
+		for trace in self.traces.iter().filter( |t| t.len() > 1) {
+			let mut line = Graphic::line(trace);
+			line.set_color(Rgb{r:150, g:150, b:150});
+			self.canvas.draw(&line);
+		}
+		let mut star = Graphic::circle(self.system.q[0], 0.08);
+		star.set_color(Rgb{r:230, g:180, b:0});
+		self.canvas.draw(&star);
+		for &r in self.system.q.iter().skip(1) {
+			let mut planet = Graphic::circle(r, 0.03);
+			planet.set_color(Rgb{r:50, g:100, b:200});
+			self.canvas.draw(&planet);
+		}
+		self.readout.show(&self.diagnostics);
+	}
+}
+
+impl SimStep for Planets {
+	fn update(&mut self, dt: f64) {
+		self.system.step(&Yoshida4, TIME_SCALE*dt/1000.0); // convert to seconds
+		for (trace, &r) in self.traces.iter_mut().zip(self.system.q.iter().skip(1)) {
+			trace.push(r);
+			if trace.len() > TRACE_LENGTH {
+				trace.remove(0);
+			}
+		}
+		self.diagnostics.record(&self.system);
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		*self = Planets::new(self.canvas.clone(), self.readout.clone());
+	}
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+
+	let mut canvas = Canvas::new("planets");
+	canvas.set_width_height(500, 500);
+	canvas.set_window(((-2.2, -2.2), (4.4, 4.4)));
+	app.add( &canvas);
+	let readout = Readout::new("diagnostics");
+	app.add( &readout);
+
+	let planets = Planets::new(canvas, readout);
+	planets.draw();
+
+	let ref_planets = Simloop::new_ref(planets);
+	ref_planets.borrow_mut().set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+	let controls = SimControls::new("controls", ref_planets.clone());
+	app.add( &controls);
+}
@@ -17,15 +17,22 @@ use websim::gfx::Graphic;
 use websim::simple_vec::Vec2 as Point;
 use websim::simple_color::Color::{Rgb,Rgba};
 use websim::simple_rng::{NormalDist};
+use websim::potentials::{
+	PairPotential,
+	Harmonic,
+};
+use websim::sde::{
+	Sde,
+	SdeIntegrator,
+	EulerMaruyama,
+};
 
-use websim::simulation::get_time;
 use websim::simulation::{
 	SimStep,
 	Simloop,
+	SimControls,
 };
 
-use std::iter;
-
 #[allow(non_upper_case_globals)]
 const kB : f64 = 0.0138064838709677419355_f64;
 const DIFF : f64 = 1.0e+13_f64; // nm^2/ns
@@ -34,19 +41,12 @@ const SPRINGK : f64 = 3.0; // pN/nm
 const NN : usize = 512;
 const MAX_SEP : f64 = 768.0; // nm
 
-fn rand_vel( rng: &mut NormalDist, dt: f64 ) -> Point {
-	let scale = (2.0*DIFF*dt).sqrt();
-	let fx = scale*rng.next();
-	let fy = scale*rng.next();
-	Point{x: fx, y:fy}
-}
-
 fn bond_force( ri: Point, rj: Point, temp: f64 ) -> Point {
-	let k = 3.0*kB*temp/(2.0*RADIUS).powi(2);
-	let rij = ri-rj;
-	let r = rij.norm();
-	let f = -k*(r-2.0*RADIUS);
-	rij/r*f
+	let bond = Harmonic{
+		k: 3.0*kB*temp/(2.0*RADIUS).powi(2),
+		r0: 2.0*RADIUS,
+	};
+	bond.force_on(ri-rj)
 }
 
 #[derive(Debug,Clone)]
@@ -87,20 +87,10 @@ impl Polymer {
 	}
 
 	fn step(&mut self, rng: &mut NormalDist, dt: f64) {
-		let mut vel : Vec<Point> = Vec::new();
-		vel.extend(iter::repeat(Point{x:0.0, y:0.0}).take(NN));
-		let mu = DIFF/(kB*self.temp);
-		for i in 1..NN {
-			let ri = self.monomers[i];
-			let rj = self.monomers[i-1];
-			let f = bond_force(ri, rj, self.temp);
-			vel[i] = vel[i] + mu*f + rand_vel(rng, dt);
-			vel[i-1] = vel[i-1] - mu*f;
-		}
-		for i in 1..NN-1 {
-			self.monomers[i] = self.monomers[i] + vel[i]*dt;
-		}
-		self.force = Some(vel[NN-1]/mu);
+		let chain = Chain{temp: self.temp};
+		EulerMaruyama.step(&chain, 0.0, &mut self.monomers, dt, rng);
+		let f = bond_force(self.monomers[NN-1], self.monomers[NN-2], self.temp);
+		self.force = Some(f);
 	}
 
 	fn scale(&mut self, new_sep: f64) {
@@ -112,6 +102,32 @@ impl Polymer {
 	}
 }
 
+/// Overdamped motion of the monomers along the chain of bonds, with
+/// the two ends held fixed.
+struct Chain {
+	temp: f64,
+}
+
+impl Sde for Chain {
+	fn drift(&self, _t: f64, x: &[Point], a: &mut [Point]) {
+		let mu = DIFF/(kB*self.temp);
+		for a in a.iter_mut() {
+			*a = Point{x:0.0, y:0.0};
+		}
+		for i in 1..NN {
+			let f = bond_force(x[i], x[i-1], self.temp);
+			a[i] = a[i] + mu*f;
+			a[i-1] = a[i-1] - mu*f;
+		}
+		a[0] = Point{x:0.0, y:0.0};
+		a[NN-1] = Point{x:0.0, y:0.0};
+	}
+
+	fn diffusion(&self, _t: f64, i: usize, _x: Point) -> f64 {
+		if i == 0 || i == NN-1 { 0.0 } else { DIFF }
+	}
+}
+
 #[derive(Debug,Clone)]
 struct FullSim{
 	writing: bool,
@@ -134,9 +150,20 @@ impl FullSim{
 	}
 }
 
+/// Physics time step
+const DT : f64 = 0.0001;
+
 impl SimStep for FullSim {
-	fn step(&mut self, dt: f64) {
-		self.poly.step(&mut self.rng, dt);
+	fn update(&mut self, _dt: f64) {
+		self.poly.step(&mut self.rng, DT);
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		self.poly = Polymer::init(self.poly.temp, self.poly.sep);
 	}
 }
 
@@ -147,10 +174,6 @@ fn main() {
 	let vis = Container::new("vis");
 	let mut canvas = Canvas::new("canvas");
 	vis.add( &canvas);
-	let sim_control = Toggle::new("start_stop", "Start", "Stop");
-	vis.add( &sim_control);
-	let sim_reset = Button::new("reset", "Reset");
-	vis.add( &sim_reset);
 	app.add( &vis);
 
 	canvas.set_window(((-MAX_SEP/2.0,-MAX_SEP/2.0),(MAX_SEP,MAX_SEP)));
@@ -180,10 +203,8 @@ fn main() {
 	let mut poly = Polymer::init(temp_slider.query(), sep_slider.query());
 	poly.draw( &canvas);
 
-	let s0 = get_time();
-	let s1 = 0_u64;
 	let mut rng = NormalDist::new(0.0, 1.0);
-	rng.seed(s0,s1);
+	rng.seed_from_entropy();
 
 	let fullsim = FullSim{
 		writing: false,
@@ -196,13 +217,7 @@ fn main() {
 
 	let ref_sim = Simloop::new_ref(fullsim);
 
-	sim_reset.add_button_function({
-		let ref_sim = ref_sim.clone();
-		move | _:bool | {
-			let sim = &mut ref_sim.borrow_mut().state;
-			sim.step(0.0001);
-			sim.draw();
-		}
-	});
+	let sim_controls = SimControls::new("sim_controls", ref_sim.clone());
+	vis.add( &sim_controls);
 
 }
@@ -0,0 +1,115 @@
+///Boxes, triangles and balls tumbling down a ramp onto the floor
+
+extern crate websim;
+
+use websim::container::Container;
+use websim::output::Canvas;
+use websim::simple_vec::Vec2 as Point;
+use websim::simple_color::Color::{Rgb};
+use websim::diagnostics::{Diagnostics, Readout};
+
+use websim::rigid::{
+	World,
+	Body,
+	Shape,
+};
+
+use websim::simulation::{
+	SimStep,
+	Simloop,
+	SimControls,
+};
+
+/// Physics time step (ms)
+const PHYSICS_DT : f64 = 2.0;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 20;
+/// Width of the scene (m)
+const WIDTH : f64 = 10.0;
+
+/// The floor, walls and ramp, with a pile of bodies above the ramp
+fn init_world() -> World {
+	let mut world = World::new(Point{x:0.0, y:-9.8});
+	world.add(Body::fixed(Shape::rectangle(WIDTH, 0.5)).at(Point{x:0.5*WIDTH, y:-0.25}));
+	world.add(Body::fixed(Shape::rectangle(0.5, 8.0)).at(Point{x:-0.25, y:4.0}));
+	world.add(Body::fixed(Shape::rectangle(0.5, 8.0)).at(Point{x:WIDTH+0.25, y:4.0}));
+	world.add(Body::fixed(Shape::rectangle(5.0, 0.3)).at(Point{x:3.0, y:3.0}).turned(-0.4));
+	for i in 0..4 {
+		let x = 1.0 + 0.9*i as f64;
+		world.add(Body::new(Shape::rectangle(0.6, 0.4), 1.0).at(Point{x, y:5.5}));
+		let mut ball = Body::new(Shape::circle(0.25), 1.0).at(Point{x:x + 0.3, y:6.5});
+		ball.restitution = 0.7;
+		world.add(ball);
+		let triangle = Shape::polygon(&[
+			Point{x:0.0, y:0.0},
+			Point{x:0.6, y:0.0},
+			Point{x:0.3, y:0.5},
+		]);
+		world.add(Body::new(triangle, 1.0).at(Point{x, y:7.5}));
+	}
+	world
+}
+
+#[derive(Debug,Clone)]
+struct Scene {
+	world: World,
+	diagnostics: Diagnostics,
+	canvas: Canvas,
+	readout: Readout,
+}
+
+impl Scene {
+	fn draw(&self) {
+		self.canvas.clear();
+		for body in self.world.bodies.iter() {
+			let color = if body.is_fixed() {
+				Rgb{r:120, g:120, b:120}
+			} else {
+				Rgb{r:70, g:130, b:200}
+			};
+			self.canvas.draw(&body.graphic(color));
+		}
+		self.readout.show(&self.diagnostics);
+	}
+}
+
+impl SimStep for Scene {
+	fn update(&mut self, dt: f64) {
+		self.world.step(dt/1000.0); // convert to seconds
+		self.diagnostics.record(&self.world);
+	}
+
+	fn render(&mut self, _alpha: f64) {
+		self.draw();
+	}
+
+	fn on_reset(&mut self) {
+		self.world = init_world();
+		self.diagnostics.reset();
+	}
+}
+
+fn main() {
+	let app = Container::new("app");
+	app.add_to_body();
+
+	let mut canvas = Canvas::new("scene");
+	canvas.set_width_height(500, 500);
+	canvas.set_window(((-0.5, -0.5), (WIDTH+1.0, WIDTH+1.0)));
+	app.add( &canvas);
+	let readout = Readout::new("diagnostics");
+	app.add( &readout);
+
+	let scene = Scene{
+		world: init_world(),
+		diagnostics: Diagnostics::new(),
+		canvas,
+		readout,
+	};
+	scene.draw();
+
+	let ref_scene = Simloop::new_ref(scene);
+	ref_scene.borrow_mut().set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+	let controls = SimControls::new("controls", ref_scene.clone());
+	app.add( &controls);
+}
@@ -19,6 +19,15 @@ use websim::gfx::Graphic;
 use websim::simulation::{
 	SimStep,
 	Simloop,
+	SimControls,
+	ClockHandle,
+};
+
+use websim::integrate::{
+	NewtonSystem,
+	FirstOrder,
+	DormandPrince,
+	StepError,
 };
 
 use websim::simple_vec::Vec2 as Point;
@@ -32,12 +41,9 @@ struct Screen {
 	amp_slider: Range,
 	angle_slider: Range,
 
-	/// Simulation Controls
-	sim_control: Toggle,
-	sim_reset: Button,
-
 	/// Outputs
 	canvas: Canvas,
+	stats: TextArea,
 	textarea: TextArea,
 
 	/// Output Controls
@@ -59,11 +65,11 @@ impl Screen {
 
 		let mut canvas = Canvas::new("canvas");
 		canvas.set_window(((-0.7,-1.3), (1.4, 1.4)));
-		let sim_control = Toggle::new("sim_control", "Start", "Stop");
-		let sim_reset = Button::new("sim_reset", "Reset");
 		vis.add( &canvas);
-		vis.add( &sim_control);
-		vis.add( &sim_reset);
+		let stats = TextArea::new("stats");
+		stats.set_cols(60);
+		stats.set_rows(1);
+		vis.add( &stats);
 
 		let mass_slider = Range::new("m", "m (g) : ", 50.0, 500.0, 1.0, 10.0);
 		mass_slider.set(250.0);
@@ -98,8 +104,7 @@ impl Screen {
 
 		Screen{
 			canvas,
-			sim_control,
-			sim_reset,
+			stats,
 
 			mass_slider,
 			spring_slider,
@@ -119,7 +124,6 @@ impl Screen {
 #[derive(Debug,Clone)]
 struct ShoState {
 	/// Simulation varaibles
-	t: f64, // time
 	r: Point, // position
 	v: Point, // velocity
 
@@ -139,10 +143,8 @@ fn spring_force( k: f64, r: Point) -> Point {
 	fhat*fmag
 }
 
-/// Velocity Verlet Step
 impl ShoState{
 	fn new(screen: &Screen) -> ShoState {
-		let t = 0.0;
 		let a_0 = L0 + screen.amp_slider.query()/100.0;
 		let th_0 = screen.angle_slider.query().to_radians();
 		let (s, c) = th_0.sin_cos();
@@ -153,11 +155,10 @@ impl ShoState{
 		let k = screen.spring_slider.query();
 		let g = screen.gravity_slider.query();
 
-		ShoState{t, r, v, m, k, g, a_0, th_0}
+		ShoState{r, v, m, k, g, a_0, th_0}
 	}
 
 	fn reset(&mut self) {
-		self.t = 0.0;
 		let (s, c) = self.th_0.sin_cos();
 		self.r = Point{x: self.a_0*s, y: -self.a_0*c};
 		self.v = Point{x: 0.0, y:0.0};
@@ -167,13 +168,6 @@ impl ShoState{
 		spring_force( self.k, r) + self.m*Point{x:0.0, y:-self.g}
 	}
 
-	fn vv_step(&mut self, dt: f64) {
-		let a_0 = self.force(self.r)/self.m;
-		self.r = self.r + self.v*dt + a_0*0.5*dt*dt;
-		let a_1 = self.force(self.r)/self.m;
-		self.v = self.v + (a_0 + a_1)*0.5*dt;
-	}
-
 	fn draw(&self, canvas : &Canvas) {
 		let spring = extra::spring(
 			5, // Number of loops
@@ -201,53 +195,131 @@ impl ShoState{
 		textarea.writeln("time (s), x pos (cm), y pos (cm), x vel (m/s), y vel (m/s)");
 	}
 
-	fn write(&self, textarea: &TextArea) {
+	fn write(&self, t: f64, textarea: &TextArea) {
 		textarea.write(&format!(
 			"{{ {}, {}, {}, {}, {} }}", 
-			self.t, self.r.x*100.0, (self.r.y-L0)*100.0, self.v.x, self.v.y
+			t, self.r.x*100.0, (self.r.y-L0)*100.0, self.v.x, self.v.y
 		));
 	}
 }
 
+impl NewtonSystem for ShoState {
+	type State = Point;
+
+	fn acceleration(&self, _t: f64, r: &Point) -> Point {
+		self.force(*r)/self.m
+	}
+}
+
 #[derive(Debug,Clone)]
 struct FullSim{
 	active: bool,
 	writing: bool,
 	step_count: u32,
+	clock: ClockHandle,
 	screen: Screen,
 	sho_state: ShoState,
+	/// Adaptive integrator, holding its own copy of the position and
+	/// velocity
+	integrator: DormandPrince<(Point, Point)>,
+	/// Why the integrator stopped, if it did
+	stalled: Option<StepError>,
 }
 
-const STEPS_PER_STEP : i32 = 10;
+/// Physics time step (ms), ten steps per 60Hz frame
+const PHYSICS_DT : f64 = 1.666666666666666666666666666666666666666666666666_f64;
+/// Most physics steps taken in a single frame
+const MAX_STEPS_PER_FRAME : u32 = 20;
+/// Physics steps between lines of output
+const STEPS_PER_WRITE : u32 = 60;
+/// Absolute and relative error tolerance of the integrator
+const TOLERANCE : f64 = 1.0e-8;
 
 impl FullSim {
 	fn draw(&self) {
 		self.sho_state.draw( &self.screen.canvas);
+		let stats = self.integrator.stats();
+		let text = match self.stalled {
+			Some(error) => format!("Integrator stopped: {}", error),
+			None => format!("Steps: {} accepted, {} rejected", stats.accepted, stats.rejected),
+		};
+		self.screen.stats.clear();
+		self.screen.stats.write(&text);
+	}
+
+	/// Start the integrator again from the current state, after the
+	/// state or the parameters were changed by hand.
+	fn restart(&mut self) {
+		let t = self.clock.get().seconds();
+		self.integrator.set_state(t, (self.sho_state.r, self.sho_state.v));
+	}
+
+	fn write_start(&mut self) {
+		self.sho_state.write_header( &self.screen.textarea);
+		self.screen.textarea.write("{");
+		self.write();
+		self.step_count = 0;
+	}
+
+	fn write(&self) {
+		let t = self.clock.get().seconds();
+		self.sho_state.write( t, &self.screen.textarea);
 	}
 }
 
 impl SimStep for FullSim {
-	fn step(&mut self, dt: f64) {
-		let dt = 0.01666666666666666666666666666666666666666666666666_f64; // convert to seconds
-		self.sho_state.t += dt;
-
-		let dt = dt/(STEPS_PER_STEP as f64);
-		for _ in 0..STEPS_PER_STEP {
-			self.sho_state.vv_step(dt);
+	fn update(&mut self, _dt: f64) {
+		if self.stalled.is_some() {
+			return;
+		}
+		// The clock already counts this step
+		let t = self.clock.get().seconds();
+		match self.integrator.advance_to(&FirstOrder(&self.sho_state), t) {
+			Ok((r, v)) => {
+				self.sho_state.r = r;
+				self.sho_state.v = v;
+			},
+			Err(error) => self.stalled = Some(error),
 		}
 
 		if self.writing {
-			if self.step_count == 5 {
+			self.step_count += 1;
+			if self.step_count == STEPS_PER_WRITE {
 				self.screen.textarea.writeln(",");
-				self.sho_state.write( &self.screen.textarea);
+				self.write();
 				self.step_count = 0;
-			} else {
-				self.step_count += 1;
 			}
 		}
+	}
 
+	fn render(&mut self, _alpha: f64) {
 		self.draw();
 	}
+
+	fn on_start(&mut self) {
+		if self.writing {
+			self.write_start();
+		}
+		self.active = true;
+	}
+
+	fn on_stop(&mut self) {
+		if self.writing {
+			self.screen.textarea.writeln("}\n");
+		}
+	}
+
+	fn on_reset(&mut self) {
+		if self.writing {
+			self.writing = false;
+			self.screen.output_control.set(false);
+		}
+		self.active = false;
+		self.sho_state.reset();
+		self.stalled = None;
+		self.integrator.reset_stats();
+		self.restart();
+	}
 }
 
 fn main() {
@@ -255,69 +327,33 @@ fn main() {
 	let writing = false;
 	let screen = Screen::new();
 	let mut sho_state = ShoState::new(&screen);
+	let integrator = DormandPrince::new(0.0, (sho_state.r, sho_state.v), TOLERANCE, TOLERANCE);
 	let mut sim = FullSim{
 		active,
 		writing,
 		step_count: 0, 
+		clock: ClockHandle::default(),
 		screen: screen.clone(), 
-		sho_state};
+		sho_state,
+		integrator,
+		stalled: None};
 	sim.draw();
 	let state = Simloop::new_ref( sim);
-	screen.sim_control.add_toggle_function({
-		let state = state.clone();
-		move | status:bool | {
-			if status {
-				{
-					let mut ref_state = state.borrow_mut();
-					if ref_state.state.writing {
-						ref_state.state.sho_state.write_header( &ref_state.state.screen.textarea);
-						ref_state.state.screen.textarea.write("{");
-						ref_state.state.sho_state.write( &ref_state.state.screen.textarea);
-						ref_state.state.step_count = 0;
-					}
-					ref_state.state.active = true;
-				}
-				Simloop::start_loop(state.clone());
-			} else {
-				let mut ref_state = state.borrow_mut();
-				if ref_state.state.writing {
-					ref_state.state.screen.textarea.writeln("}\n");
-				}
-				ref_state.stop_loop();
-			}
-		}
-	});
-	screen.sim_reset.add_button_function({
-		let sim_control = screen.sim_control.clone();
-		let output_control = screen.output_control.clone();
-		let state = state.clone();
-		move | _:bool | {
-			let mut ref_state = state.borrow_mut();
-			if sim_control.query() {
-				ref_state.stop_loop();
-				sim_control.set(false);
-				if ref_state.state.writing {
-					ref_state.state.screen.textarea.writeln("}\n");
-					ref_state.state.writing = false;
-					output_control.set(false);					
-				}
-			}
-			ref_state.state.active = false;
-			ref_state.state.sho_state.reset();
-			ref_state.state.draw();
-		}
-	});
+	{
+		let mut ref_state = state.borrow_mut();
+		ref_state.set_fixed_step(PHYSICS_DT, MAX_STEPS_PER_FRAME);
+		ref_state.state.clock = ref_state.clock_handle();
+	}
+	let sim_controls = SimControls::new("sim_controls", state.clone());
+	Container::get("vis").add( &sim_controls);
 	screen.output_control.add_toggle_function({
 		let state = state.clone();
 		move | status:bool | {
 			let mut ref_state = state.borrow_mut();
 			ref_state.state.writing = status;
-			if ref_state.state.screen.sim_control.query() {
-				if status {			
-					ref_state.state.sho_state.write_header( &ref_state.state.screen.textarea);
-					ref_state.state.screen.textarea.write("{");
-					ref_state.state.sho_state.write( &ref_state.state.screen.textarea);
-					ref_state.state.step_count = 0;
+			if ref_state.is_running() {
+				if status {
+					ref_state.state.write_start();
 				} else {
 					ref_state.state.screen.textarea.writeln("}\n");					
 				}
@@ -328,38 +364,35 @@ fn main() {
 		let state = state.clone();
 		move | _:bool| {
 			let mut ref_state = state.borrow_mut();
-			let running = ref_state.state.screen.sim_control.query();
+			let running = ref_state.is_running();
 			if running && ref_state.state.writing {
-					ref_state.state.sho_state.write_header( &ref_state.state.screen.textarea);
-					ref_state.state.screen.textarea.write("{");
-					ref_state.state.sho_state.write( &ref_state.state.screen.textarea);
-					ref_state.state.step_count = 0;
+				ref_state.state.write_start();
 			}
 		}
 	});
 
 
 	screen.amp_slider.add_continuous_range_function({
-		let sim_control = screen.sim_control.clone();
 		let state = state.clone();
 		move | val:f64 | {
 			let mut ref_state = state.borrow_mut();
 			ref_state.state.sho_state.a_0 = L0 + val/100.0;
 			if !ref_state.state.active {
 				ref_state.state.sho_state.reset();
+				ref_state.state.restart();
 				ref_state.state.draw();
 			}
 		}
 	});
 
 	screen.angle_slider.add_continuous_range_function({
-		let sim_control = screen.sim_control.clone();
 		let state = state.clone();
 		move | val:f64 | {
 			let mut ref_state = state.borrow_mut();
 			ref_state.state.sho_state.th_0 = val.to_radians();
 			if !ref_state.state.active {
 				ref_state.state.sho_state.reset();
+				ref_state.state.restart();
 				ref_state.state.draw();
 			}
 		}
@@ -370,6 +403,7 @@ fn main() {
 		move | val:f64 | {
 			let mut ref_state = state.borrow_mut();
 			ref_state.state.sho_state.m = val/1000.0;
+			ref_state.state.restart();
 			ref_state.state.draw();
 		}
 	});
@@ -377,14 +411,18 @@ fn main() {
 	screen.spring_slider.add_continuous_range_function({
 		let state = state.clone();
 		move | val:f64 | {
-			state.borrow_mut().state.sho_state.k = val;
+			let mut ref_state = state.borrow_mut();
+			ref_state.state.sho_state.k = val;
+			ref_state.state.restart();
 		}
 	});
 
 	screen.gravity_slider.add_continuous_range_function({
 		let state = state.clone();
 		move | val:f64 | {
-			state.borrow_mut().state.sho_state.g = val;
+			let mut ref_state = state.borrow_mut();
+			ref_state.state.sho_state.g = val;
+			ref_state.state.restart();
 		}
 	});
 
@@ -0,0 +1,236 @@
+/// Boundary conditions
+/// ===================
+///
+/// A `Boundary` describes a rectangular box from the origin to
+/// `size`, and what happens to particles at its edges, separately
+/// along x and y:
+///
+/// - `Edge::Periodic`: particles leaving one side come back in on the
+///   other, and distances use the nearest periodic image
+/// - `Edge::Reflecting`: particles bounce off the walls
+/// - `Edge::Open`: particles are free to leave
+///
+/// The same boundary is used to move particles, to find neighbors
+/// with `neighbors::CellList`, and to draw the periodic images of
+/// particles straddling an edge.
+use ::simple_vec::Vec2;
+
+/// What happens at the edges of the box along one axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+	Periodic,
+	Reflecting,
+	Open,
+}
+
+/// A box from the origin to `size`, with the behavior at its edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Boundary {
+	size: Vec2,
+	pub x: Edge,
+	pub y: Edge,
+}
+
+/// Wrap `x` into [0, `length`), returning it and the number of box
+/// lengths it was moved by.
+fn wrap_axis(x: f64, length: f64) -> (f64, f64) {
+	let images = (x/length).floor();
+	let wrapped = x - images*length;
+	// Round-off can turn a tiny negative `x` into exactly `length`
+	if wrapped >= length {
+		(0_f64, images + 1_f64)
+	} else {
+		(wrapped, images)
+	}
+}
+
+/// Reflect `x` and its velocity `v` back into [0, `length`].
+fn reflect_axis(x: &mut f64, v: &mut f64, length: f64) {
+	// Fold the position onto a box twice the length, then mirror
+	let (folded, _) = wrap_axis(*x, 2_f64*length);
+	let mirrored = folded > length;
+	let flips = ((*x/length).floor() as i64).rem_euclid(2) == 1;
+	*x = if mirrored { 2_f64*length - folded } else { folded };
+	if flips {
+		*v = -*v;
+	}
+}
+
+impl Boundary {
+	pub fn new(size: Vec2, x: Edge, y: Edge) -> Boundary {
+		assert!(size.x > 0_f64 && size.y > 0_f64, "box size must be positive");
+		Boundary{size, x, y}
+	}
+
+	/// A box that is periodic along both axes.
+	pub fn periodic(size: Vec2) -> Boundary {
+		Boundary::new(size, Edge::Periodic, Edge::Periodic)
+	}
+
+	/// A box with reflecting walls on all sides.
+	pub fn reflecting(size: Vec2) -> Boundary {
+		Boundary::new(size, Edge::Reflecting, Edge::Reflecting)
+	}
+
+	/// A box that particles are free to leave.
+	pub fn open(size: Vec2) -> Boundary {
+		Boundary::new(size, Edge::Open, Edge::Open)
+	}
+
+	pub fn size(&self) -> Vec2 {
+		self.size
+	}
+
+	/// Resize the box, e.g. under pressure coupling. Particles are
+	/// left where they are.
+	pub fn set_size(&mut self, size: Vec2) {
+		assert!(size.x > 0_f64 && size.y > 0_f64, "box size must be positive");
+		self.size = size;
+	}
+
+	/// Whether a point is inside the box.
+	pub fn contains(&self, r: Vec2) -> bool {
+		r.x >= 0_f64 && r.x <= self.size.x && r.y >= 0_f64 && r.y <= self.size.y
+	}
+
+	/// The point `r` wrapped back into the box along the periodic axes.
+	pub fn wrap(&self, r: Vec2) -> Vec2 {
+		let mut r = r;
+		let mut offset = Vec2::zero();
+		self.wrap_tracked(&mut r, &mut offset);
+		r
+	}
+
+	/// Wrap `r` back into the box along the periodic axes, adding the
+	/// distance it was moved to `offset`, so that `r + offset` is
+	/// always the unwrapped position.
+	pub fn wrap_tracked(&self, r: &mut Vec2, offset: &mut Vec2) {
+		if self.x == Edge::Periodic {
+			let (x, images) = wrap_axis(r.x, self.size.x);
+			r.x = x;
+			offset.x += images*self.size.x;
+		}
+		if self.y == Edge::Periodic {
+			let (y, images) = wrap_axis(r.y, self.size.y);
+			r.y = y;
+			offset.y += images*self.size.y;
+		}
+	}
+
+	/// Keep a moving particle in the box: wrap it along periodic axes,
+	/// and bounce it off reflecting walls, reversing its velocity.
+	pub fn confine(&self, r: &mut Vec2, v: &mut Vec2) {
+		let mut offset = Vec2::zero();
+		self.confine_tracked(r, v, &mut offset);
+	}
+
+	/// As `confine`, adding the distance moved by wrapping to `offset`
+	/// as in `wrap_tracked`.
+	pub fn confine_tracked(&self, r: &mut Vec2, v: &mut Vec2, offset: &mut Vec2) {
+		self.wrap_tracked(r, offset);
+		if self.x == Edge::Reflecting {
+			reflect_axis(&mut r.x, &mut v.x, self.size.x);
+		}
+		if self.y == Edge::Reflecting {
+			reflect_axis(&mut r.y, &mut v.y, self.size.y);
+		}
+	}
+
+	/// The displacement `a - b`, using the nearest periodic image of
+	/// `b` along the periodic axes.
+	pub fn displacement(&self, a: Vec2, b: Vec2) -> Vec2 {
+		let mut d = a - b;
+		if self.x == Edge::Periodic {
+			d.x -= self.size.x*(d.x/self.size.x).round();
+		}
+		if self.y == Edge::Periodic {
+			d.y -= self.size.y*(d.y/self.size.y).round();
+		}
+		d
+	}
+
+	/// The distance between `a` and the nearest periodic image of `b`.
+	pub fn distance(&self, a: Vec2, b: Vec2) -> f64 {
+		self.displacement(a, b).norm()
+	}
+
+	/// The positions to draw a particle of radius `radius` at: the
+	/// particle itself plus its periodic images that overlap the box,
+	/// so a particle straddling a periodic edge shows on both sides.
+	pub fn images(&self, r: Vec2, radius: f64) -> Vec<Vec2> {
+		let shifts = |x: f64, length: f64, periodic: bool| {
+			let mut shifts = vec!(0_f64);
+			if periodic {
+				if x - radius < 0_f64 {
+					shifts.push(length);
+				}
+				if x + radius > length {
+					shifts.push(-length);
+				}
+			}
+			shifts
+		};
+		let xs = shifts(r.x, self.size.x, self.x == Edge::Periodic);
+		let ys = shifts(r.y, self.size.y, self.y == Edge::Periodic);
+		let mut images = Vec::with_capacity(xs.len()*ys.len());
+		for &dy in ys.iter() {
+			for &dx in xs.iter() {
+				images.push(r + Vec2{x: dx, y: dy});
+			}
+		}
+		images
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Wrapping keeps track of the unwrapped position
+	#[test]
+	fn test_wrap() {
+		let boundary = Boundary::new(Vec2{x: 10.0, y: 5.0}, Edge::Periodic, Edge::Open);
+		let mut r = Vec2{x: 23.5, y: -7.0};
+		let mut offset = Vec2::zero();
+		boundary.wrap_tracked(&mut r, &mut offset);
+		assert_eq!(r, Vec2{x: 3.5, y: -7.0});
+		assert_eq!(r + offset, Vec2{x: 23.5, y: -7.0});
+		assert_eq!(boundary.wrap(Vec2{x: -0.5, y: 1.0}), Vec2{x: 9.5, y: 1.0});
+	}
+
+	/// Reflecting walls mirror the position and flip the velocity
+	#[test]
+	fn test_reflect() {
+		let boundary = Boundary::reflecting(Vec2{x: 10.0, y: 10.0});
+		let mut r = Vec2{x: 10.5, y: -1.0};
+		let mut v = Vec2{x: 2.0, y: -3.0};
+		boundary.confine(&mut r, &mut v);
+		assert_eq!(r, Vec2{x: 9.5, y: 1.0});
+		assert_eq!(v, Vec2{x: -2.0, y: 3.0});
+		// Far enough to bounce off both walls
+		let mut r = Vec2{x: 21.0, y: 5.0};
+		let mut v = Vec2{x: 1.0, y: 0.0};
+		boundary.confine(&mut r, &mut v);
+		assert_eq!(r, Vec2{x: 1.0, y: 5.0});
+		assert_eq!(v, Vec2{x: 1.0, y: 0.0});
+	}
+
+	#[test]
+	fn test_minimum_image() {
+		let boundary = Boundary::new(Vec2{x: 10.0, y: 10.0}, Edge::Periodic, Edge::Reflecting);
+		let d = boundary.displacement(Vec2{x: 0.5, y: 0.5}, Vec2{x: 9.5, y: 9.5});
+		assert!((d - Vec2{x: 1.0, y: -9.0}).norm() < 1e-12);
+		assert_eq!(Boundary::open(Vec2{x: 1.0, y: 1.0}).distance(Vec2::zero(), Vec2{x: 3.0, y: 4.0}), 5.0);
+	}
+
+	/// A particle in a corner of a periodic box is drawn four times
+	#[test]
+	fn test_images() {
+		let boundary = Boundary::periodic(Vec2{x: 10.0, y: 10.0});
+		assert_eq!(boundary.images(Vec2{x: 5.0, y: 5.0}, 1.0).len(), 1);
+		assert_eq!(boundary.images(Vec2{x: 0.5, y: 5.0}, 1.0).len(), 2);
+		let images = boundary.images(Vec2{x: 0.5, y: 9.8}, 1.0);
+		assert_eq!(images.len(), 4);
+		assert!(images.iter().any( |&r| (r - Vec2{x: 10.5, y: -0.2}).norm() < 1e-12));
+	}
+}
@@ -0,0 +1,297 @@
+/// Constraints
+/// ===========
+///
+/// Holonomic constraints on particles stored as `Vec2`s: rods keeping
+/// two particles a fixed distance apart, pins holding a particle a
+/// fixed distance from a point in space, and particles fixed in place.
+/// Pendulums, chains and linkages can then be built from a list of
+/// constraints, instead of working out their equations of motion in
+/// generalized coordinates.
+///
+/// The constraints are kept with RATTLE, a velocity Verlet step where
+/// the new positions are corrected along the old rods until every
+/// constraint holds (SHAKE), and the new velocities are corrected so
+/// nothing moves along a rod. Like velocity Verlet it is symplectic
+/// and time reversible, so the energy stays close to its start.
+///
+/// A double pendulum hanging from the origin:
+///
+/// ```
+/// # use websim::simple_vec::Vec2;
+/// # use websim::constraints::*;
+/// let mut links = Constraints::new();
+/// links.add(Constraint::Pin{i: 0, point: Vec2::zero(), length: 1.0});
+/// links.add(Constraint::Rod{i: 0, j: 1, length: 1.0});
+/// let gravity = Gravity{g: Vec2{x: 0.0, y: -9.8}, masses: vec!(1.0, 1.0)};
+/// let mut r = [Vec2{x: 1.0, y: 0.0}, Vec2{x: 2.0, y: 0.0}];
+/// let mut v = [Vec2::zero(); 2];
+/// for _ in 0..1000 {
+///     links.step(&gravity, &mut r, &mut v, 0.001);
+/// }
+/// assert!(links.error(&r) < 1e-8);
+/// ```
+use ::sde::ForceField;
+use ::simple_vec::Vec2;
+
+/// A constraint on the positions of particles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constraint {
+	/// Particles `i` and `j` are kept `length` apart, as if joined by
+	/// a massless rod
+	Rod{i: usize, j: usize, length: f64},
+	/// Particle `i` is kept `length` from a fixed `point`, as if on a
+	/// rod pinned there
+	Pin{i: usize, point: Vec2, length: f64},
+	/// Particle `i` is held at `point`
+	Fixed{i: usize, point: Vec2},
+}
+
+/// A uniform gravitational field acting on particles of the given
+/// masses.
+#[derive(Debug, Clone)]
+pub struct Gravity {
+	/// Acceleration due to gravity
+	pub g: Vec2,
+	pub masses: Vec<f64>,
+}
+
+impl ForceField for Gravity {
+	fn force(&self, _x: &[Vec2], f: &mut [Vec2]) {
+		for (f, &m) in f.iter_mut().zip(self.masses.iter()) {
+			*f = m*self.g;
+		}
+	}
+
+	fn mass(&self, i: usize) -> f64 {
+		self.masses[i]
+	}
+}
+
+/// A set of constraints, and how closely they are kept.
+#[derive(Debug, Clone)]
+pub struct Constraints {
+	constraints: Vec<Constraint>,
+	/// Relative error allowed in the length of a rod
+	pub tolerance: f64,
+	/// Most passes over the constraints in each correction
+	pub max_iterations: usize,
+}
+
+impl Default for Constraints {
+	fn default() -> Constraints {
+		Constraints::new()
+	}
+}
+
+impl Constraints {
+	pub fn new() -> Constraints {
+		Constraints{constraints: Vec::new(), tolerance: 1.0e-10, max_iterations: 500}
+	}
+
+	pub fn add(&mut self, constraint: Constraint) -> &mut Self {
+		self.constraints.push(constraint);
+		self
+	}
+
+	pub fn len(&self) -> usize {
+		self.constraints.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.constraints.is_empty()
+	}
+
+	pub fn iter<'a>(&'a self) -> ::std::slice::Iter<'a, Constraint> {
+		self.constraints.iter()
+	}
+
+	/// The largest violation of a constraint at positions `r`: the
+	/// relative error in the length of a rod, or the distance of a
+	/// fixed particle from its point.
+	pub fn error(&self, r: &[Vec2]) -> f64 {
+		self.constraints.iter().map( |c| match *c {
+			Constraint::Rod{i, j, length} => ((r[i] - r[j]).norm() - length).abs()/length,
+			Constraint::Pin{i, point, length} => ((r[i] - point).norm() - length).abs()/length,
+			Constraint::Fixed{i, point} => (r[i] - point).norm(),
+		}).fold(0_f64, f64::max)
+	}
+
+	/// The inverse masses of the particles, zero for fixed ones.
+	fn inverse_masses<F: ForceField>(&self, field: &F, n: usize) -> Vec<f64> {
+		let mut w : Vec<f64> = (0..n).map( |i| 1_f64/field.mass(i)).collect();
+		for c in self.constraints.iter() {
+			if let Constraint::Fixed{i, ..} = *c {
+				w[i] = 0_f64;
+			}
+		}
+		w
+	}
+
+	/// SHAKE: correct the positions `r` reached from `r_old` in a
+	/// time `dt` until every constraint holds, moving the particles
+	/// along the old rods and changing the velocities `v` to match.
+	/// The inverse masses `w` set how much each particle moves.
+	pub fn shake(&self, r_old: &[Vec2], r: &mut [Vec2], v: &mut [Vec2], w: &[f64], dt: f64) {
+		for c in self.constraints.iter() {
+			if let Constraint::Fixed{i, point} = *c {
+				r[i] = point;
+				v[i] = Vec2::zero();
+			}
+		}
+		for _ in 0..self.max_iterations {
+			let mut done = true;
+			for c in self.constraints.iter() {
+				let (i, j, old, d, length) = match *c {
+					Constraint::Rod{i, j, length} => (i, Some(j), r_old[i] - r_old[j], r[i] - r[j], length),
+					Constraint::Pin{i, point, length} => (i, None, r_old[i] - point, r[i] - point, length),
+					Constraint::Fixed{..} => continue,
+				};
+				let wj = j.map_or(0_f64, |j| w[j]);
+				let diff = d.norm_squared() - length*length;
+				if diff.abs() <= 2_f64*self.tolerance*length*length || w[i] + wj == 0_f64 {
+					continue;
+				}
+				done = false;
+				let g = diff/(2_f64*d.dot(old)*(w[i] + wj));
+				r[i] = r[i] - (g*w[i])*old;
+				v[i] = v[i] - (g*w[i]/dt)*old;
+				if let Some(j) = j {
+					r[j] = r[j] + (g*w[j])*old;
+					v[j] = v[j] + (g*w[j]/dt)*old;
+				}
+			}
+			if done {
+				break;
+			}
+		}
+	}
+
+	/// RATTLE: correct the velocities `v` at positions `r` so that no
+	/// particle moves along a rod. The inverse masses `w` set how much
+	/// each particle's velocity changes.
+	pub fn rattle(&self, r: &[Vec2], v: &mut [Vec2], w: &[f64]) {
+		for _ in 0..self.max_iterations {
+			let mut done = true;
+			for c in self.constraints.iter() {
+				let (i, j, d, dv) = match *c {
+					Constraint::Rod{i, j, ..} => (i, Some(j), r[i] - r[j], v[i] - v[j]),
+					Constraint::Pin{i, point, ..} => (i, None, r[i] - point, v[i]),
+					Constraint::Fixed{..} => continue,
+				};
+				let wj = j.map_or(0_f64, |j| w[j]);
+				let rate = d.dot(dv);
+				if rate.abs() <= self.tolerance*d.norm_squared() || w[i] + wj == 0_f64 {
+					continue;
+				}
+				done = false;
+				let k = rate/(d.norm_squared()*(w[i] + wj));
+				v[i] = v[i] - (k*w[i])*d;
+				if let Some(j) = j {
+					v[j] = v[j] + (k*w[j])*d;
+				}
+			}
+			if done {
+				break;
+			}
+		}
+	}
+
+	/// Advance positions `r` and velocities `v` by a time `dt` under
+	/// the forces of `field`, keeping the constraints.
+	pub fn step<F: ForceField>(&self, field: &F, r: &mut [Vec2], v: &mut [Vec2], dt: f64) {
+		assert_eq!(r.len(), v.len(), "positions and velocities differ in length");
+		let w = self.inverse_masses(field, r.len());
+		let mut f = vec!(Vec2::zero(); r.len());
+		field.force(r, &mut f);
+		let r_old = r.to_vec();
+		for ((r, v), (f, &w)) in r.iter_mut().zip(v.iter_mut()).zip(f.iter().zip(w.iter())) {
+			*v = *v + (0.5*dt*w)*(*f);
+			*r = *r + dt*(*v);
+		}
+		self.shake(&r_old, r, v, &w, dt);
+		field.force(r, &mut f);
+		for ((v, f), &w) in v.iter_mut().zip(f.iter()).zip(w.iter()) {
+			*v = *v + (0.5*dt*w)*(*f);
+		}
+		self.rattle(r, v, &w);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const G : Vec2 = Vec2{x: 0.0, y: -9.8};
+
+	/// Kinetic plus gravitational potential energy
+	fn energy(gravity: &Gravity, r: &[Vec2], v: &[Vec2]) -> f64 {
+		r.iter().zip(v.iter()).zip(gravity.masses.iter())
+			.map( |((r, v), &m)| 0.5*m*v.norm_squared() - m*G.dot(*r))
+			.sum()
+	}
+
+	/// A small swing of a pendulum has period 2π sqrt(L/g)
+	#[test]
+	fn test_pendulum_period() {
+		let mut links = Constraints::new();
+		links.add(Constraint::Pin{i: 0, point: Vec2::zero(), length: 2.0});
+		let gravity = Gravity{g: G, masses: vec!(3.0)};
+		let angle = 0.01_f64;
+		let mut r = [Vec2{x: 2.0*angle.sin(), y: -2.0*angle.cos()}];
+		let mut v = [Vec2::zero()];
+		let dt = 1.0e-4;
+		// Time the first two crossings of the bottom, half a period apart
+		let mut crossings = Vec::new();
+		let mut t = 0_f64;
+		while crossings.len() < 2 {
+			let x = r[0].x;
+			links.step(&gravity, &mut r, &mut v, dt);
+			t += dt;
+			if x*r[0].x <= 0.0 {
+				crossings.push(t - dt*r[0].x/(r[0].x - x));
+			}
+		}
+		let period = 2.0*(crossings[1] - crossings[0]);
+		let expected = 2.0*::std::f64::consts::PI*(2.0/9.8_f64).sqrt();
+		assert!((period/expected - 1.0).abs() < 1e-4, "period {} expected {}", period, expected);
+	}
+
+	/// A chaotic double pendulum keeps its rods and its energy
+	#[test]
+	fn test_double_pendulum() {
+		let mut links = Constraints::new();
+		links.add(Constraint::Pin{i: 0, point: Vec2::zero(), length: 1.0})
+			.add(Constraint::Rod{i: 0, j: 1, length: 0.5});
+		let gravity = Gravity{g: G, masses: vec!(1.0, 2.0)};
+		let mut r = [Vec2{x: 1.0, y: 0.0}, Vec2{x: 1.0, y: 0.5}];
+		let mut v = [Vec2::zero(); 2];
+		let e0 = energy(&gravity, &r, &v);
+		let mut drift = 0_f64;
+		for _ in 0..20000 {
+			links.step(&gravity, &mut r, &mut v, 5.0e-4);
+			assert!(links.error(&r) < 1e-9);
+			let along = (v[0] - v[1]).dot(r[0] - r[1]);
+			assert!(along.abs() < 1e-9);
+			drift = drift.max((energy(&gravity, &r, &v) - e0).abs());
+		}
+		assert!(drift < 1e-3*gravity.masses.iter().sum::<f64>()*9.8, "energy drift {}", drift);
+	}
+
+	/// Fixed particles stay put, and rods to them act like pins
+	#[test]
+	fn test_fixed() {
+		let mut links = Constraints::new();
+		links.add(Constraint::Fixed{i: 0, point: Vec2{x: 1.0, y: 1.0}})
+			.add(Constraint::Rod{i: 0, j: 1, length: 1.0});
+		let gravity = Gravity{g: G, masses: vec!(1.0, 1.0)};
+		let mut r = [Vec2{x: 1.0, y: 1.0}, Vec2{x: 2.0, y: 1.0}];
+		let mut v = [Vec2::zero(); 2];
+		for _ in 0..1000 {
+			links.step(&gravity, &mut r, &mut v, 1.0e-3);
+		}
+		assert_eq!(r[0], Vec2{x: 1.0, y: 1.0});
+		assert_eq!(v[0], Vec2::zero());
+		assert!(links.error(&r) < 1e-9);
+		assert!(r[1].y < 1.0);
+	}
+}
@@ -0,0 +1,303 @@
+/// Conservation-law diagnostics
+/// ============================
+///
+/// The first check when a simulation looks wrong is whether the things
+/// that should be conserved are. A state that implements `Conserved`
+/// reports its kinetic and potential energy, momentum and angular
+/// momentum; `Diagnostics` records them every step, keeping track of
+/// their starting values, drift, and smallest and largest values. A
+/// `Readout` shows the diagnostics live on the page, in any
+/// `Container`.
+///
+/// ```
+/// # use websim::simple_vec::Vec2;
+/// # use websim::springs::SpringNetwork;
+/// # use websim::integrate::Rk4;
+/// # use websim::diagnostics::Diagnostics;
+/// let mut chain = SpringNetwork::chain(Vec2::zero(), Vec2{x: 4.0, y: 0.0}, 5, 1.0, 10.0, 0.0);
+/// chain.nodes[2].v.y = 1.0;
+/// let mut diagnostics = Diagnostics::new();
+/// for _ in 0..100 {
+///     chain.step(&Rk4, 0.01);
+///     diagnostics.record(&chain);
+/// }
+/// assert!(diagnostics.energy.relative_drift().abs() < 1e-6);
+/// ```
+use std::fmt;
+
+use stdweb::web::{
+	HtmlElement,
+	document,
+};
+use stdweb::traits::*;
+use stdweb::unstable::TryInto;
+
+use ::container::UiElement;
+use ::simple_vec::Vec2;
+
+/// A state with energies and momenta to check.
+pub trait Conserved {
+	fn kinetic_energy(&self) -> f64;
+
+	fn potential_energy(&self) -> f64 {
+		0_f64
+	}
+
+	/// The total energy, kinetic plus potential.
+	fn energy(&self) -> f64 {
+		self.kinetic_energy() + self.potential_energy()
+	}
+
+	fn momentum(&self) -> Vec2 {
+		Vec2::zero()
+	}
+
+	/// The angular momentum about the origin.
+	fn angular_momentum(&self) -> f64 {
+		0_f64
+	}
+}
+
+/// The history of one quantity: its first, last, smallest and largest
+/// recorded values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tracker {
+	initial: f64,
+	current: f64,
+	min: f64,
+	max: f64,
+	samples: u64,
+}
+
+impl Default for Tracker {
+	fn default() -> Tracker {
+		Tracker::new()
+	}
+}
+
+impl Tracker {
+	pub fn new() -> Tracker {
+		Tracker{
+			initial: 0_f64,
+			current: 0_f64,
+			min: f64::INFINITY,
+			max: f64::NEG_INFINITY,
+			samples: 0,
+		}
+	}
+
+	pub fn record(&mut self, value: f64) {
+		if self.samples == 0 {
+			self.initial = value;
+		}
+		self.current = value;
+		self.min = self.min.min(value);
+		self.max = self.max.max(value);
+		self.samples += 1;
+	}
+
+	/// Forget everything recorded, starting over with the next value.
+	pub fn reset(&mut self) {
+		*self = Tracker::new();
+	}
+
+	pub fn samples(&self) -> u64 { self.samples }
+
+	pub fn initial(&self) -> f64 { self.initial }
+
+	pub fn current(&self) -> f64 { self.current }
+
+	pub fn min(&self) -> f64 { self.min }
+
+	pub fn max(&self) -> f64 { self.max }
+
+	/// The change from the first value to the last.
+	pub fn drift(&self) -> f64 {
+		self.current - self.initial
+	}
+
+	/// The drift as a fraction of the first value, or the drift itself
+	/// when the first value is zero.
+	pub fn relative_drift(&self) -> f64 {
+		if self.initial == 0_f64 {
+			self.drift()
+		} else {
+			self.drift()/self.initial.abs()
+		}
+	}
+
+	/// The furthest any value has been from the first.
+	pub fn max_deviation(&self) -> f64 {
+		if self.samples == 0 {
+			0_f64
+		} else {
+			(self.max - self.initial).max(self.initial - self.min)
+		}
+	}
+}
+
+/// Trackers for the energies and momenta of a `Conserved` state.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+	pub kinetic: Tracker,
+	pub potential: Tracker,
+	pub energy: Tracker,
+	pub momentum_x: Tracker,
+	pub momentum_y: Tracker,
+	pub angular_momentum: Tracker,
+}
+
+impl Diagnostics {
+	pub fn new() -> Diagnostics {
+		Diagnostics::default()
+	}
+
+	/// Record the energies and momenta of `state`.
+	pub fn record<S: Conserved>(&mut self, state: &S) {
+		let (kinetic, potential) = (state.kinetic_energy(), state.potential_energy());
+		let momentum = state.momentum();
+		self.kinetic.record(kinetic);
+		self.potential.record(potential);
+		self.energy.record(kinetic + potential);
+		self.momentum_x.record(momentum.x);
+		self.momentum_y.record(momentum.y);
+		self.angular_momentum.record(state.angular_momentum());
+	}
+
+	/// Forget everything recorded, e.g. when the simulation is reset.
+	pub fn reset(&mut self) {
+		*self = Diagnostics::new();
+	}
+
+	/// The trackers along with their names.
+	pub fn trackers(&self) -> [(&'static str, &Tracker); 6] {
+		[
+			("kinetic", &self.kinetic),
+			("potential", &self.potential),
+			("energy", &self.energy),
+			("momentum x", &self.momentum_x),
+			("momentum y", &self.momentum_y),
+			("angular mom.", &self.angular_momentum),
+		]
+	}
+}
+
+/// A table of the current value, drift, and smallest and largest
+/// values of each quantity.
+impl fmt::Display for Diagnostics {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{:<13}{:>13}{:>13}{:>13}{:>13}", "", "current", "drift", "min", "max")?;
+		for &(name, tracker) in self.trackers().iter() {
+			if tracker.samples() == 0 {
+				writeln!(f, "{:<13}{:>13}", name, "-")?;
+			} else {
+				writeln!(f, "{:<13}{:>13.5e}{:>13.5e}{:>13.5e}{:>13.5e}",
+					name, tracker.current(), tracker.drift(), tracker.min(), tracker.max())?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// A live readout of `Diagnostics`, shown as a preformatted table.
+#[derive(Debug, Clone)]
+pub struct Readout {
+	elem: HtmlElement,
+	text: HtmlElement,
+}
+
+impl Readout {
+	pub fn new( name: &str) -> Readout {
+		let elem : HtmlElement = document().create_element( "div").unwrap().try_into().unwrap();
+		elem.class_list().add("output").unwrap();
+		elem.set_attribute("data-output-type", "readout").unwrap();
+		elem.set_attribute("id",name).unwrap();
+		let text : HtmlElement = document().create_element( "pre").unwrap().try_into().unwrap();
+		text.set_attribute("id", &format!("{}_text",name)).unwrap();
+		elem.append_child( &text);
+		Readout{ elem, text }
+	}
+
+	/// Show the diagnostics, replacing whatever was shown before.
+	pub fn show<'a>( &'a self, diagnostics: &Diagnostics) -> &'a Readout {
+		self.text.set_text_content( &diagnostics.to_string());
+		self
+	}
+}
+
+impl UiElement for Readout {
+	fn elem( &self) -> &HtmlElement { &self.elem }
+}
+
+impl<'a> UiElement for &'a Readout {
+	fn elem( &self) -> &HtmlElement { &self.elem }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_tracker() {
+		let mut tracker = Tracker::new();
+		assert_eq!(tracker.max_deviation(), 0.0);
+		for &value in [2.0, 2.5, 1.0, 2.2].iter() {
+			tracker.record(value);
+		}
+		assert_eq!(tracker.samples(), 4);
+		assert_eq!(tracker.initial(), 2.0);
+		assert_eq!(tracker.current(), 2.2);
+		assert_eq!((tracker.min(), tracker.max()), (1.0, 2.5));
+		assert!((tracker.drift() - 0.2).abs() < 1e-12);
+		assert!((tracker.relative_drift() - 0.1).abs() < 1e-12);
+		assert_eq!(tracker.max_deviation(), 1.0);
+		tracker.reset();
+		tracker.record(-3.0);
+		assert_eq!((tracker.initial(), tracker.min(), tracker.max()), (-3.0, -3.0, -3.0));
+	}
+
+	struct Particle {
+		r: Vec2,
+		v: Vec2,
+	}
+
+	impl Conserved for Particle {
+		fn kinetic_energy(&self) -> f64 {
+			0.5*self.v.norm_squared()
+		}
+
+		fn potential_energy(&self) -> f64 {
+			9.8*self.r.y
+		}
+
+		fn momentum(&self) -> Vec2 {
+			self.v
+		}
+
+		fn angular_momentum(&self) -> f64 {
+			self.r.cross(self.v)
+		}
+	}
+
+	/// A falling particle swaps potential for kinetic energy
+	#[test]
+	fn test_diagnostics() {
+		let mut diagnostics = Diagnostics::new();
+		let mut particle = Particle{r: Vec2{x: 1.0, y: 10.0}, v: Vec2{x: 1.0, y: 0.0}};
+		for _ in 0..4 {
+			diagnostics.record(&particle);
+			particle.v.y -= 9.8*0.1;
+			particle.r = particle.r + 0.1*particle.v;
+		}
+		assert!(diagnostics.kinetic.drift() > 0.0);
+		assert!(diagnostics.potential.drift() < 0.0);
+		// Semi-implicit Euler loses (g dt)²/2 of energy every step
+		assert!((diagnostics.energy.drift() + 1.5*0.98*0.98).abs() < 1e-9);
+		assert_eq!(diagnostics.momentum_x.drift(), 0.0);
+		assert!((diagnostics.momentum_y.min() + 3.0*0.98).abs() < 1e-12);
+		assert_eq!(diagnostics.angular_momentum.initial(), -10.0);
+		let table = diagnostics.to_string();
+		assert_eq!(table.lines().count(), 7);
+		assert!(table.lines().nth(3).unwrap().starts_with("energy"));
+	}
+}
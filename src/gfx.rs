@@ -549,6 +549,12 @@ impl Graphic {
  		self.add_transform( Transform::translate(dx, dy))
  	}
 
+ 	/// Apply a general transform after any already applied, e.g. to
+ 	/// place a shape drawn about the origin in a body's frame.
+ 	pub fn transform<'a>(&'a mut self, transform: Transform) -> &'a mut Self {
+ 		self.add_transform( transform)
+ 	}
+
  	fn tr_draw( &self, transform: Transform, canvas: &Canvas) {
 		let t = self.transform.combine_left(transform);
 		match &self.object {
@@ -0,0 +1,494 @@
+/// Event-driven hard disks
+/// =======================
+///
+/// Hard disks move in straight lines between collisions, so instead of
+/// taking small time steps `HardDisks` jumps from one collision to the
+/// next. Every disk's upcoming collisions, with the other disks and
+/// with the walls, are predicted and kept in a priority queue; the
+/// earliest is carried out exactly and the disks involved have their
+/// collisions predicted again. Events predicted for a disk that has
+/// collided since are dropped when they come up.
+///
+/// The box is a `boundary::Boundary`. Reflecting edges are walls the
+/// disks bounce off, periodic edges wrap around, and disks pass freely
+/// through open edges.
+///
+/// Between events the disks move freely, so their positions can be
+/// found at any time, giving smooth animation whatever the frame rate.
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use ::boundary::{Boundary, Edge};
+use ::diagnostics::Conserved;
+use ::simple_vec::Vec2;
+
+/// A hard disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disk {
+	pub r: Vec2,
+	pub v: Vec2,
+	pub radius: f64,
+	pub mass: f64,
+}
+
+/// The x or y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+	X,
+	Y,
+}
+
+/// The kinds of event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+	/// Two disks collide
+	Pair(usize, usize),
+	/// A disk hits a wall
+	Wall(usize, Axis),
+	/// A disk has travelled far enough across a periodic box that its
+	/// collisions need predicting again
+	Transit(usize),
+}
+
+/// A predicted event, with the collision counts of the disks involved
+/// when it was predicted.
+#[derive(Debug, Clone, Copy)]
+struct Event {
+	time: f64,
+	kind: Kind,
+	counts: (u64, u64),
+}
+
+/// Events are ordered so the earliest comes out of the heap first.
+impl Ord for Event {
+	fn cmp(&self, other: &Event) -> Ordering {
+		other.time.partial_cmp(&self.time).unwrap_or(Ordering::Equal)
+	}
+}
+
+impl PartialOrd for Event {
+	fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for Event {
+	fn eq(&self, other: &Event) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for Event {}
+
+/// The time until two disks at a separation `d`, with relative
+/// velocity `dv` and touching at a distance `sigma`, collide.
+fn contact_time(d: Vec2, dv: Vec2, sigma: f64) -> Option<f64> {
+	let b = d.dot(dv);
+	if b >= 0_f64 {
+		return None;
+	}
+	let dv2 = dv.norm_squared();
+	let disc = b*b - dv2*(d.norm_squared() - sigma*sigma);
+	if disc < 0_f64 {
+		return None;
+	}
+	Some((-(b + disc.sqrt())/dv2).max(0_f64))
+}
+
+/// A set of hard disks in a box, moved from collision to collision.
+#[derive(Debug, Clone)]
+pub struct HardDisks {
+	boundary: Boundary,
+	disks: Vec<Disk>,
+	/// The time each disk's position was last set
+	since: Vec<f64>,
+	/// The number of collisions of each disk
+	counts: Vec<u64>,
+	events: BinaryHeap<Event>,
+	time: f64,
+	collisions: u64,
+	wall_impulse: f64,
+}
+
+impl HardDisks {
+	/// An empty box.
+	pub fn new(boundary: Boundary) -> HardDisks {
+		HardDisks{
+			boundary,
+			disks: Vec::new(),
+			since: Vec::new(),
+			counts: Vec::new(),
+			events: BinaryHeap::new(),
+			time: 0_f64,
+			collisions: 0,
+			wall_impulse: 0_f64,
+		}
+	}
+
+	pub fn boundary(&self) -> Boundary {
+		self.boundary
+	}
+
+	pub fn len(&self) -> usize {
+		self.disks.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.disks.is_empty()
+	}
+
+	/// The current time.
+	pub fn time(&self) -> f64 {
+		self.time
+	}
+
+	/// The number of collisions between disks so far.
+	pub fn collisions(&self) -> u64 {
+		self.collisions
+	}
+
+	/// The total momentum given to the walls so far. Divided by the
+	/// elapsed time and the length of the walls, it is the pressure.
+	pub fn wall_impulse(&self) -> f64 {
+		self.wall_impulse
+	}
+
+	/// Whether a disk would fit: inside any walls and not overlapping
+	/// any of the disks.
+	pub fn fits(&self, disk: &Disk) -> bool {
+		let size = self.boundary.size();
+		let inside = |x: f64, length: f64, edge: Edge| {
+			edge != Edge::Reflecting || (x >= disk.radius && x <= length - disk.radius)
+		};
+		inside(disk.r.x, size.x, self.boundary.x)
+			&& inside(disk.r.y, size.y, self.boundary.y)
+			&& (0..self.len()).all( |i| {
+				let sigma = disk.radius + self.disks[i].radius;
+				self.boundary.displacement(disk.r, self.position(i)).norm_squared() >= sigma*sigma
+			})
+	}
+
+	/// Add a disk at the current time, returning its index. It should
+	/// fit, see `fits`.
+	pub fn add(&mut self, disk: Disk) -> usize {
+		assert!(disk.radius > 0_f64 && disk.mass > 0_f64, "disks need a positive radius and mass");
+		let i = self.disks.len();
+		self.disks.push(Disk{r: self.boundary.wrap(disk.r), ..disk});
+		self.since.push(self.time);
+		self.counts.push(0);
+		self.predict(i);
+		i
+	}
+
+	/// The position of disk `i` at time `t`, if it moves freely from
+	/// its last collision. This is exact for any time up to the next
+	/// event, see `next_event`.
+	pub fn position_at(&self, i: usize, t: f64) -> Vec2 {
+		let disk = &self.disks[i];
+		self.boundary.wrap(disk.r + (t - self.since[i])*disk.v)
+	}
+
+	/// The position of disk `i` now.
+	pub fn position(&self, i: usize) -> Vec2 {
+		self.position_at(i, self.time)
+	}
+
+	/// The positions of all the disks now.
+	pub fn positions(&self) -> Vec<Vec2> {
+		(0..self.len()).map( |i| self.position(i)).collect()
+	}
+
+	/// Disk `i` as it is now.
+	pub fn disk(&self, i: usize) -> Disk {
+		Disk{r: self.position(i), ..self.disks[i]}
+	}
+
+	/// All the disks as they are now.
+	pub fn disks(&self) -> Vec<Disk> {
+		(0..self.len()).map( |i| self.disk(i)).collect()
+	}
+
+	/// Change the velocity of disk `i`.
+	pub fn set_velocity(&mut self, i: usize, v: Vec2) {
+		let t = self.time;
+		self.sync(i, t);
+		self.disks[i].v = v;
+		self.counts[i] += 1;
+		self.predict(i);
+	}
+
+	/// The total kinetic energy.
+	pub fn kinetic_energy(&self) -> f64 {
+		self.disks.iter().map( |d| 0.5*d.mass*d.v.norm_squared()).sum()
+	}
+
+	/// The total momentum.
+	pub fn momentum(&self) -> Vec2 {
+		self.disks.iter().fold(Vec2::zero(), |p, d| p + d.mass*d.v)
+	}
+
+	/// The time of the next event, if there is one.
+	pub fn next_event(&mut self) -> Option<f64> {
+		while let Some(&event) = self.events.peek() {
+			if self.is_valid(&event) {
+				return Some(event.time);
+			}
+			self.events.pop();
+		}
+		None
+	}
+
+	/// Carry out every event up to time `t`, and move on to `t`.
+	pub fn advance_to(&mut self, t: f64) {
+		while let Some(next) = self.next_event() {
+			if next > t {
+				break;
+			}
+			let event = self.events.pop().unwrap();
+			self.time = event.time.max(self.time);
+			self.handle(event);
+		}
+		self.time = self.time.max(t);
+	}
+
+	/// Move on by a time `dt`.
+	pub fn advance(&mut self, dt: f64) {
+		let t = self.time + dt;
+		self.advance_to(t);
+	}
+
+	/// Whether none of the disks in an event have collided since it
+	/// was predicted.
+	fn is_valid(&self, event: &Event) -> bool {
+		match event.kind {
+			Kind::Pair(i, j) => self.counts[i] == event.counts.0 && self.counts[j] == event.counts.1,
+			Kind::Wall(i, _) | Kind::Transit(i) => self.counts[i] == event.counts.0,
+		}
+	}
+
+	/// Move disk `i` to where it is at time `t`.
+	fn sync(&mut self, i: usize, t: f64) {
+		self.disks[i].r = self.position_at(i, t);
+		self.since[i] = t;
+	}
+
+	fn handle(&mut self, event: Event) {
+		let t = event.time;
+		match event.kind {
+			Kind::Pair(i, j) => {
+				self.sync(i, t);
+				self.sync(j, t);
+				let (a, b) = (self.disks[i], self.disks[j]);
+				let d = self.boundary.displacement(a.r, b.r);
+				let dv = a.v - b.v;
+				// The impulse along the line of centers for an elastic collision
+				let impulse = (2_f64*a.mass*b.mass/(a.mass + b.mass))*d.dot(dv)/d.norm_squared()*d;
+				self.disks[i].v = a.v - impulse/a.mass;
+				self.disks[j].v = b.v + impulse/b.mass;
+				self.counts[i] += 1;
+				self.counts[j] += 1;
+				self.collisions += 1;
+				self.predict(i);
+				self.predict(j);
+			},
+			Kind::Wall(i, axis) => {
+				self.sync(i, t);
+				let disk = &mut self.disks[i];
+				let vn = match axis {
+					Axis::X => { disk.v.x = -disk.v.x; disk.v.x },
+					Axis::Y => { disk.v.y = -disk.v.y; disk.v.y },
+				};
+				self.wall_impulse += 2_f64*disk.mass*vn.abs();
+				self.counts[i] += 1;
+				self.predict(i);
+			},
+			Kind::Transit(i) => {
+				self.sync(i, t);
+				self.predict(i);
+			},
+		}
+	}
+
+	/// Predict the upcoming events for disk `i` from now.
+	fn predict(&mut self, i: usize) {
+		let now = self.time;
+		let count = self.counts[i];
+		let disk = self.disks[i];
+		let r = self.position(i);
+		for j in 0..self.len() {
+			if j == i {
+				continue;
+			}
+			let other = &self.disks[j];
+			let d = self.boundary.displacement(r, self.position(j));
+			if let Some(dt) = self.pair_time(d, disk.v - other.v, disk.radius + other.radius) {
+				let (a, b) = if i < j { (i, j) } else { (j, i) };
+				let counts = (self.counts[a], self.counts[b]);
+				self.events.push(Event{time: now + dt, kind: Kind::Pair(a, b), counts});
+			}
+		}
+		let size = self.boundary.size();
+		let walls = [
+			(Axis::X, self.boundary.x, r.x, disk.v.x, size.x),
+			(Axis::Y, self.boundary.y, r.y, disk.v.y, size.y),
+		];
+		for &(axis, edge, x, v, length) in walls.iter() {
+			if edge != Edge::Reflecting || v == 0_f64 {
+				continue;
+			}
+			let wall = if v > 0_f64 { length - disk.radius } else { disk.radius };
+			let dt = ((wall - x)/v).max(0_f64);
+			self.events.push(Event{time: now + dt, kind: Kind::Wall(i, axis), counts: (count, 0)});
+		}
+		// A disk crossing more than half a periodic box could meet a
+		// periodic image not checked above, so check again before then
+		let mut span = f64::INFINITY;
+		if self.boundary.x == Edge::Periodic {
+			span = span.min(0.5*size.x);
+		}
+		if self.boundary.y == Edge::Periodic {
+			span = span.min(0.5*size.y);
+		}
+		let speed = disk.v.norm();
+		if span.is_finite() && speed > 0_f64 {
+			self.events.push(Event{time: now + span/speed, kind: Kind::Transit(i), counts: (count, 0)});
+		}
+	}
+
+	/// The time until two disks collide, from their minimum image
+	/// separation `d`, trying the neighboring periodic images too.
+	fn pair_time(&self, d: Vec2, dv: Vec2, sigma: f64) -> Option<f64> {
+		let size = self.boundary.size();
+		let shifts = |edge: Edge, length: f64| {
+			if edge == Edge::Periodic { vec!(0_f64, -length, length) } else { vec!(0_f64) }
+		};
+		let mut earliest : Option<f64> = None;
+		for &sx in shifts(self.boundary.x, size.x).iter() {
+			for &sy in shifts(self.boundary.y, size.y).iter() {
+				let t = contact_time(d + Vec2{x: sx, y: sy}, dv, sigma);
+				earliest = match (earliest, t) {
+					(Some(a), Some(b)) => Some(a.min(b)),
+					(a, b) => a.or(b),
+				};
+			}
+		}
+		earliest
+	}
+}
+
+impl Conserved for HardDisks {
+	fn kinetic_energy(&self) -> f64 {
+		HardDisks::kinetic_energy(self)
+	}
+
+	fn momentum(&self) -> Vec2 {
+		HardDisks::momentum(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ::simple_rng::Rng;
+
+	fn disk(x: f64, y: f64, vx: f64, vy: f64) -> Disk {
+		Disk{r: Vec2{x, y}, v: Vec2{x: vx, y: vy}, radius: 0.5, mass: 1.0}
+	}
+
+	/// A gas of `n` by `n` disks on a grid, with random directions and
+	/// unit speed
+	fn gas(boundary: Boundary, n: usize) -> HardDisks {
+		let mut rng = Rng::new();
+		let mut disks = HardDisks::new(boundary);
+		let spacing = boundary.size().x/n as f64;
+		for i in 0..n {
+			for j in 0..n {
+				let angle = 2.0*::std::f64::consts::PI*rng.uniform();
+				let r = Vec2{x: (i as f64 + 0.5)*spacing, y: (j as f64 + 0.5)*spacing};
+				let d = Disk{r, v: Vec2{x: angle.cos(), y: angle.sin()}, radius: 0.3*spacing, mass: 1.0};
+				assert!(disks.fits(&d));
+				disks.add(d);
+			}
+		}
+		disks
+	}
+
+	/// Equal disks meeting head on swap velocities, at the right time
+	#[test]
+	fn test_head_on() {
+		let mut disks = HardDisks::new(Boundary::open(Vec2{x: 10.0, y: 10.0}));
+		disks.add(disk(2.0, 5.0, 1.0, 0.0));
+		disks.add(disk(8.0, 5.0, -1.0, 0.0));
+		assert_eq!(disks.next_event(), Some(2.5));
+		disks.advance_to(2.0);
+		assert_eq!(disks.position(0), Vec2{x: 4.0, y: 5.0});
+		disks.advance_to(4.0);
+		assert_eq!(disks.collisions(), 1);
+		assert_eq!(disks.disk(0).v, Vec2{x: -1.0, y: 0.0});
+		assert_eq!(disks.position(0), Vec2{x: 3.0, y: 5.0});
+		assert_eq!(disks.position(1), Vec2{x: 7.0, y: 5.0});
+	}
+
+	/// A disk bounces between reflecting walls, giving them momentum
+	#[test]
+	fn test_walls() {
+		let mut disks = HardDisks::new(Boundary::reflecting(Vec2{x: 4.0, y: 4.0}));
+		disks.add(disk(1.0, 2.0, 1.0, 0.0));
+		disks.advance_to(3.0);
+		assert_eq!(disks.position(0), Vec2{x: 3.0, y: 2.0});
+		assert_eq!(disks.disk(0).v, Vec2{x: -1.0, y: 0.0});
+		disks.advance_to(10.0);
+		assert_eq!(disks.wall_impulse(), 6.0);
+	}
+
+	/// Collisions across a periodic edge
+	#[test]
+	fn test_periodic() {
+		let mut disks = HardDisks::new(Boundary::periodic(Vec2{x: 10.0, y: 10.0}));
+		disks.add(disk(0.5, 5.0, -1.0, 0.0));
+		disks.add(disk(8.5, 5.0, 0.0, 0.0));
+		disks.advance_to(2.0);
+		assert_eq!(disks.collisions(), 1);
+		assert!((disks.position(0) - Vec2{x: 9.5, y: 5.0}).norm() < 1e-12);
+		assert!((disks.position(1) - Vec2{x: 7.5, y: 5.0}).norm() < 1e-12);
+	}
+
+	/// A gas keeps its energy and momentum, the disks never overlap,
+	/// and the speeds relax to the Maxwell-Boltzmann distribution,
+	/// for which <v⁴> = 2 <v²>² in two dimensions
+	#[test]
+	fn test_gas() {
+		let edges = [Boundary::periodic(Vec2{x: 20.0, y: 20.0}), Boundary::reflecting(Vec2{x: 20.0, y: 20.0})];
+		for &boundary in edges.iter() {
+			let mut disks = gas(boundary, 12);
+			let e0 = disks.kinetic_energy();
+			let p0 = disks.momentum();
+			let mut v2 = 0_f64;
+			let mut v4 = 0_f64;
+			for step in 0..200 {
+				disks.advance(0.5);
+				let all = disks.disks();
+				for (i, a) in all.iter().enumerate() {
+					for b in all[i+1..].iter() {
+						let d = boundary.distance(a.r, b.r);
+						assert!(d > a.radius + b.radius - 1e-9, "overlap of {}", d);
+					}
+				}
+				if step >= 100 {
+					for d in all.iter() {
+						let s2 = d.v.norm_squared();
+						v2 += s2;
+						v4 += s2*s2;
+					}
+				}
+			}
+			assert!((disks.kinetic_energy() - e0).abs() < 1e-9*e0);
+			if boundary.x == Edge::Periodic {
+				assert!((disks.momentum() - p0).norm() < 1e-9);
+			}
+			let n = (100*disks.len()) as f64;
+			let ratio = (v4/n)/(v2/n).powi(2);
+			assert!((ratio - 2.0).abs() < 0.1, "{:?}: <v⁴>/<v²>² = {}", boundary.x, ratio);
+		}
+	}
+}
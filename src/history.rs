@@ -0,0 +1,241 @@
+/// Simulation history
+/// ==================
+///
+/// A `History` is a ring buffer of snapshots taken every few steps
+/// while a `Simloop` runs. The loop can be rewound to any recorded
+/// snapshot, and running on from there records a new history that
+/// replaces the old one past that point. The `Timeline` control
+/// scrubs through the recorded history with a `Range` slider.
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use stdweb::web::HtmlElement;
+use stdweb::traits::*;
+
+use ::container::UiElement;
+use ::control::Range;
+use ::simulation::{
+	SimClock,
+	SimStep,
+	Simloop,
+};
+use ::snapshot::Snapshot;
+
+/// A recorded snapshot, along with the clock when it was taken.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+	pub clock: SimClock,
+	pub snapshot: String,
+}
+
+/// A ring buffer holding the last `capacity` snapshots, taken every
+/// `every` steps.
+#[derive(Debug, Clone)]
+pub struct History {
+	entries: VecDeque<HistoryEntry>,
+	capacity: usize,
+	every: u64,
+	/// The entry last scrubbed to, `None` when at the newest entry
+	cursor: Option<usize>,
+}
+
+impl History {
+	pub fn new(capacity: usize, every: u64) -> History {
+		assert!(capacity > 0, "history capacity must be positive");
+		assert!(every > 0, "history must record every one or more steps");
+		History{
+			entries: VecDeque::with_capacity(capacity),
+			capacity,
+			every,
+			cursor: None,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/// The number of steps between snapshots.
+	pub fn every(&self) -> u64 {
+		self.every
+	}
+
+	pub fn get(&self, index: usize) -> Option<&HistoryEntry> {
+		self.entries.get(index)
+	}
+
+	/// The recorded entries, oldest first.
+	pub fn iter(&self) -> impl Iterator<Item=&HistoryEntry> {
+		self.entries.iter()
+	}
+
+	/// The index of the entry the simulation is at: the one last
+	/// scrubbed to, or else the newest.
+	pub fn position(&self) -> Option<usize> {
+		match self.cursor {
+			Some(index) => Some(index),
+			None if self.entries.is_empty() => None,
+			None => Some(self.entries.len()-1),
+		}
+	}
+
+	/// Whether a snapshot is due at a clock with this many steps.
+	pub fn is_due(&self, steps: u64) -> bool {
+		match self.position() {
+			Some(index) => steps >= self.entries[index].clock.steps + self.every,
+			None => true,
+		}
+	}
+
+	/// Record a snapshot. Entries newer than the one last scrubbed to
+	/// are dropped first, and the oldest entry is dropped when full.
+	pub fn record(&mut self, clock: SimClock, snapshot: String) {
+		if let Some(index) = self.cursor.take() {
+			self.entries.truncate(index+1);
+		}
+		if self.entries.len() == self.capacity {
+			self.entries.pop_front();
+		}
+		self.entries.push_back(HistoryEntry{clock, snapshot});
+	}
+
+	/// Move to the entry at `index`, clamped to the recorded range,
+	/// and return it.
+	pub fn seek(&mut self, index: usize) -> Option<&HistoryEntry> {
+		if self.entries.is_empty() {
+			return None;
+		}
+		let index = index.min(self.entries.len()-1);
+		self.cursor = Some(index);
+		self.entries.get(index)
+	}
+
+	/// The index of the entry a `fraction` (0 to 1) of the way from the
+	/// oldest to the newest.
+	pub fn index_at(&self, fraction: f64) -> usize {
+		let last = self.entries.len().saturating_sub(1);
+		let fraction = fraction.clamp(0_f64, 1_f64);
+		(fraction*last as f64).round() as usize
+	}
+
+	/// How far (0 to 1) the current position is from the oldest to the
+	/// newest entry.
+	pub fn fraction(&self) -> f64 {
+		match self.position() {
+			Some(index) if self.entries.len() > 1 => index as f64/(self.entries.len()-1) as f64,
+			_ => 1_f64,
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.entries.clear();
+		self.cursor = None;
+	}
+}
+
+/// A `Range` slider that scrubs through the history of a `Simloop`.
+/// The slider runs from 0, the oldest snapshot, to 100, the newest.
+/// Moving it stops the loop and redraws the simulation at that point;
+/// it jumps back to 100 whenever the loop starts running again.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+	range: Range,
+}
+
+impl Timeline {
+	pub fn new<T>( name: &str, text: &str, refstate: Rc<RefCell<Simloop<T>>>) -> Timeline
+		where T: SimStep + Snapshot + 'static {
+		let range = Range::new(name, text, 0_f64, 100_f64, 0.1, 1_f64);
+		range.set(100_f64);
+		range.elem().set_attribute("data-param-type", "timeline").unwrap();
+		range.add_continuous_range_function({
+			let refstate = refstate.clone();
+			move | val:f64 | {
+				let mut simloop = refstate.borrow_mut();
+				let index = match simloop.history() {
+					Some(history) => history.index_at(val/100_f64),
+					None => return,
+				};
+				if let Err(error) = simloop.scrub_to(index) {
+					let message = error.to_string();
+					js!{ @(no_return)
+						console.error(@{message});
+					};
+				}
+			}
+		});
+		refstate.borrow_mut().add_run_function({
+			let range = range.clone();
+			move | running:bool | {
+				if running {
+					range.set(100_f64);
+				}
+			}
+		});
+		Timeline{ range }
+	}
+
+	pub fn range(&self) -> &Range {
+		&self.range
+	}
+}
+
+impl UiElement for Timeline {
+	fn elem( &self) -> &HtmlElement { self.range.elem() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn clock(steps: u64) -> SimClock {
+		SimClock{ steps, time: steps as f64, ..SimClock::default() }
+	}
+
+	fn record(history: &mut History, steps: u64) {
+		history.record(clock(steps), steps.to_string());
+	}
+
+	/// Snapshots are due every few steps and old ones fall off the end
+	#[test]
+	fn test_ring_buffer() {
+		let mut history = History::new(3, 10);
+		assert!(history.is_due(0));
+		for steps in 0..100 {
+			if history.is_due(steps) {
+				record(&mut history, steps);
+			}
+		}
+		assert_eq!(history.len(), 3);
+		let steps : Vec<u64> = history.iter().map( |e| e.clock.steps).collect();
+		assert_eq!(steps, vec!(70, 80, 90));
+		assert_eq!(history.position(), Some(2));
+	}
+
+	/// Recording after scrubbing back replaces the newer entries
+	#[test]
+	fn test_scrub_and_branch() {
+		let mut history = History::new(10, 1);
+		for steps in 0..5 {
+			record(&mut history, steps);
+		}
+		assert_eq!(history.index_at(0.5), 2);
+		assert_eq!(history.seek(1).unwrap().snapshot, "1");
+		assert_eq!(history.fraction(), 0.25);
+		assert!(!history.is_due(1));
+		assert!(history.is_due(2));
+		record(&mut history, 2);
+		assert_eq!(history.len(), 3);
+		assert_eq!(history.position(), Some(2));
+		assert_eq!(history.seek(100).unwrap().snapshot, "2");
+	}
+}
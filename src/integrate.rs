@@ -0,0 +1,695 @@
+/// ODE integrators
+/// ===============
+///
+/// This module defines an `OdeSystem` trait for a set of first order
+/// differential equations, dy/dt = f(t, y), and a `NewtonSystem`
+/// trait for equations of motion, d²x/dt² = a(t, x). A simulation
+/// only has to write its derivative or force law, and can then be
+/// stepped with any of the stock integrators:
+///
+/// - `Euler`, `Midpoint` and `Rk4` for an `OdeSystem`
+/// - `VelocityVerlet` and `Leapfrog` for a `NewtonSystem`
+///
+/// For problems where a fixed step is either too slow or unstable,
+/// `DormandPrince` adapts its step to meet an error tolerance and can
+/// interpolate the solution at any time, such as a frame time.
+///
+/// The state can be an `f64`, a `Vec2`, a `Vec` of states, or a pair
+/// of states, for example positions and velocities.
+///
+/// # Examples
+/// ```
+/// # use websim::integrate::*;
+/// struct Decay { rate: f64 }
+/// impl OdeSystem for Decay {
+///     type State = f64;
+///     fn derivative(&self, _t: f64, y: &f64) -> f64 { -self.rate*y }
+/// }
+/// let y = Rk4.step(&Decay{rate: 1.0}, 0.0, &1.0, 0.01);
+/// ```
+use std::f64;
+use std::fmt;
+
+use ::simple_vec::Vec2;
+
+/// The state of an ODE. The integrators only need to be able to
+/// take a step along a rate of change.
+pub trait OdeState: Clone {
+	/// The state `self + h*rate`.
+	fn add_scaled(&self, h: f64, rate: &Self) -> Self;
+
+	/// Push each of the numbers making up the state onto `out`, used
+	/// for error estimates.
+	fn push_components(&self, out: &mut Vec<f64>);
+}
+
+impl OdeState for f64 {
+	fn add_scaled(&self, h: f64, rate: &f64) -> f64 {
+		self + h*rate
+	}
+
+	fn push_components(&self, out: &mut Vec<f64>) {
+		out.push(*self);
+	}
+}
+
+impl OdeState for Vec2 {
+	fn add_scaled(&self, h: f64, rate: &Vec2) -> Vec2 {
+		*self + h*(*rate)
+	}
+
+	fn push_components(&self, out: &mut Vec<f64>) {
+		out.push(self.x);
+		out.push(self.y);
+	}
+}
+
+impl<T: OdeState> OdeState for Vec<T> {
+	fn add_scaled(&self, h: f64, rate: &Vec<T>) -> Vec<T> {
+		assert_eq!(self.len(), rate.len(), "state and rate differ in length");
+		self.iter().zip(rate.iter())
+			.map( |(y, r)| y.add_scaled(h, r))
+			.collect()
+	}
+
+	fn push_components(&self, out: &mut Vec<f64>) {
+		for y in self.iter() {
+			y.push_components(out);
+		}
+	}
+}
+
+impl<A: OdeState, B: OdeState> OdeState for (A, B) {
+	fn add_scaled(&self, h: f64, rate: &(A, B)) -> (A, B) {
+		(self.0.add_scaled(h, &rate.0), self.1.add_scaled(h, &rate.1))
+	}
+
+	fn push_components(&self, out: &mut Vec<f64>) {
+		self.0.push_components(out);
+		self.1.push_components(out);
+	}
+}
+
+fn components<Y: OdeState>(y: &Y) -> Vec<f64> {
+	let mut out = Vec::new();
+	y.push_components(&mut out);
+	out
+}
+
+/// A system of first order differential equations dy/dt = f(t, y).
+pub trait OdeSystem {
+	type State: OdeState;
+
+	/// The rate of change of the state `y` at time `t`.
+	fn derivative(&self, t: f64, y: &Self::State) -> Self::State;
+}
+
+/// A scheme for advancing an `OdeSystem` by one time step.
+pub trait Integrator {
+	/// The state a time `dt` after state `y` at time `t`.
+	fn step<S: OdeSystem>(&self, system: &S, t: f64, y: &S::State, dt: f64) -> S::State;
+}
+
+/// Explicit Euler, first order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Euler;
+
+impl Integrator for Euler {
+	fn step<S: OdeSystem>(&self, system: &S, t: f64, y: &S::State, dt: f64) -> S::State {
+		y.add_scaled(dt, &system.derivative(t, y))
+	}
+}
+
+/// Explicit midpoint, second order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Midpoint;
+
+impl Integrator for Midpoint {
+	fn step<S: OdeSystem>(&self, system: &S, t: f64, y: &S::State, dt: f64) -> S::State {
+		let k1 = system.derivative(t, y);
+		let y_mid = y.add_scaled(0.5*dt, &k1);
+		let k2 = system.derivative(t + 0.5*dt, &y_mid);
+		y.add_scaled(dt, &k2)
+	}
+}
+
+/// The classic fourth order Runge-Kutta.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rk4;
+
+impl Integrator for Rk4 {
+	fn step<S: OdeSystem>(&self, system: &S, t: f64, y: &S::State, dt: f64) -> S::State {
+		let k1 = system.derivative(t, y);
+		let k2 = system.derivative(t + 0.5*dt, &y.add_scaled(0.5*dt, &k1));
+		let k3 = system.derivative(t + 0.5*dt, &y.add_scaled(0.5*dt, &k2));
+		let k4 = system.derivative(t + dt, &y.add_scaled(dt, &k3));
+		y.add_scaled(dt/6_f64, &k1)
+			.add_scaled(dt/3_f64, &k2)
+			.add_scaled(dt/3_f64, &k3)
+			.add_scaled(dt/6_f64, &k4)
+	}
+}
+
+/// Equations of motion where the acceleration depends only on time
+/// and position, d²x/dt² = a(t, x).
+pub trait NewtonSystem {
+	type State: OdeState;
+
+	/// The acceleration at position `x` and time `t`.
+	fn acceleration(&self, t: f64, x: &Self::State) -> Self::State;
+}
+
+/// A scheme for advancing a `NewtonSystem` by one time step.
+pub trait NewtonIntegrator {
+	/// The position and velocity a time `dt` after position `x` and
+	/// velocity `v` at time `t`.
+	fn step<S: NewtonSystem>(&self, system: &S, t: f64, x: &S::State, v: &S::State, dt: f64)
+		-> (S::State, S::State);
+}
+
+/// Velocity Verlet (kick-drift-kick), second order and time
+/// reversible. Takes two acceleration evaluations per step.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VelocityVerlet;
+
+impl NewtonIntegrator for VelocityVerlet {
+	fn step<S: NewtonSystem>(&self, system: &S, t: f64, x: &S::State, v: &S::State, dt: f64)
+		-> (S::State, S::State) {
+		let a0 = system.acceleration(t, x);
+		let v_half = v.add_scaled(0.5*dt, &a0);
+		let x1 = x.add_scaled(dt, &v_half);
+		let a1 = system.acceleration(t + dt, &x1);
+		let v1 = v_half.add_scaled(0.5*dt, &a1);
+		(x1, v1)
+	}
+}
+
+/// Leapfrog (drift-kick-drift), second order and time reversible.
+/// Takes one acceleration evaluation per step.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Leapfrog;
+
+impl NewtonIntegrator for Leapfrog {
+	fn step<S: NewtonSystem>(&self, system: &S, t: f64, x: &S::State, v: &S::State, dt: f64)
+		-> (S::State, S::State) {
+		let x_half = x.add_scaled(0.5*dt, v);
+		let a = system.acceleration(t + 0.5*dt, &x_half);
+		let v1 = v.add_scaled(dt, &a);
+		let x1 = x_half.add_scaled(0.5*dt, &v1);
+		(x1, v1)
+	}
+}
+
+/// A `NewtonSystem` seen as a first order `OdeSystem` in position and
+/// velocity, so it can be stepped with any `Integrator`.
+#[derive(Debug, Clone, Copy)]
+pub struct FirstOrder<'a, S: 'a>(pub &'a S);
+
+impl<'a, S: NewtonSystem> OdeSystem for FirstOrder<'a, S> {
+	type State = (S::State, S::State);
+
+	fn derivative(&self, t: f64, y: &Self::State) -> Self::State {
+		(y.1.clone(), self.0.acceleration(t, &y.0))
+	}
+}
+
+/// Dormand-Prince nodes
+const DP_C : [f64; 7] = [0.0, 0.2, 0.3, 0.8, 8.0/9.0, 1.0, 1.0];
+/// Dormand-Prince Runge-Kutta matrix, row `i` gives stage `i+1`
+const DP_A : [[f64; 6]; 6] = [
+	[0.2, 0.0, 0.0, 0.0, 0.0, 0.0],
+	[3.0/40.0, 9.0/40.0, 0.0, 0.0, 0.0, 0.0],
+	[44.0/45.0, -56.0/15.0, 32.0/9.0, 0.0, 0.0, 0.0],
+	[19372.0/6561.0, -25360.0/2187.0, 64448.0/6561.0, -212.0/729.0, 0.0, 0.0],
+	[9017.0/3168.0, -355.0/33.0, 46732.0/5247.0, 49.0/176.0, -5103.0/18656.0, 0.0],
+	[35.0/384.0, 0.0, 500.0/1113.0, 125.0/192.0, -2187.0/6784.0, 11.0/84.0],
+];
+/// Fifth order weights, the same as the last row of `DP_A`
+const DP_B : [f64; 7] = [35.0/384.0, 0.0, 500.0/1113.0, 125.0/192.0, -2187.0/6784.0, 11.0/84.0, 0.0];
+/// Difference between the fifth and fourth order weights
+const DP_E : [f64; 7] = [
+	71.0/57600.0, 0.0, -71.0/16695.0, 71.0/1920.0, -17253.0/339200.0, 22.0/525.0, -1.0/40.0];
+/// Weights for the dense output
+const DP_D : [f64; 7] = [
+	-12715105075.0/11282082432.0, 0.0, 87487479700.0/32700410799.0,
+	-10690763975.0/1880347072.0, 701980252875.0/199316789632.0,
+	-1453857185.0/822651844.0, 69997945.0/29380423.0];
+
+/// Safety factor for the next step size
+const SAFETY : f64 = 0.9;
+/// Limits on how much the step size can change from one step to the next
+const MIN_FACTOR : f64 = 0.2;
+const MAX_FACTOR : f64 = 5.0;
+/// Steps smaller than this, relative to the time, can't move the
+/// time forward
+const MIN_STEP_FRACTION : f64 = 1.0e-14;
+
+/// Counts of the steps taken by an adaptive integrator.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StepStats {
+	/// Steps that met the tolerance
+	pub accepted: u64,
+	/// Steps thrown away and retried with a smaller step size
+	pub rejected: u64,
+}
+
+/// The ways an adaptive integrator can fail to advance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepError {
+	/// No step size down to the smallest allowed brings the error, in
+	/// units of the tolerance, within tolerance at time `t`
+	Stalled{t: f64, error: f64},
+	/// The time `t` asked for is before `earliest`, the start of the
+	/// last step
+	Backwards{t: f64, earliest: f64},
+}
+
+impl fmt::Display for StepError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			StepError::Stalled{t, error} =>
+				write!(f, "no step size meets the tolerance at t = {} (error {})", t, error),
+			StepError::Backwards{t, earliest} =>
+				write!(f, "can't go back to t = {} before t = {}", t, earliest),
+		}
+	}
+}
+
+/// The last accepted step, kept for interpolating inside it.
+#[derive(Debug, Clone)]
+struct DenseStep<Y> {
+	t: f64,
+	dt: f64,
+	y: Y,
+	k: Vec<Y>,
+}
+
+impl<Y: OdeState> DenseStep<Y> {
+	/// The solution at time `t`, inside the step.
+	fn interpolate(&self, t: f64) -> Y {
+		let b = (t - self.t)/self.dt;
+		let b1 = 1_f64 - b;
+		let c_delta = b*(1_f64 - b1 + 2_f64*b*b1);
+		let c_first = b*b1*b1;
+		let c_last = -b*b*b1;
+		let c_dense = b*b*b1*b1;
+		let mut y = self.y.clone();
+		for (i, k) in self.k.iter().enumerate() {
+			let mut w = c_delta*DP_B[i] + c_dense*DP_D[i];
+			if i == 0 {
+				w += c_first;
+			}
+			if i == 6 {
+				w += c_last;
+			}
+			if w != 0_f64 {
+				y = y.add_scaled(self.dt*w, k);
+			}
+		}
+		y
+	}
+}
+
+/// The Dormand-Prince embedded Runge-Kutta 5(4) integrator. Each step
+/// compares fifth and fourth order solutions to estimate the error,
+/// and the step size is adjusted so the error stays within the
+/// absolute and relative tolerances. Steps that miss the tolerance
+/// are rejected and retried with a smaller step.
+///
+/// The integrator keeps its own time and state, which may run ahead
+/// of the times asked for. `advance_to` interpolates the solution
+/// (dense output) at exactly the time wanted, so a `Simloop` can
+/// ask for the state at each frame time without cutting the steps
+/// short. If the solution blows up, so that no step size meets the
+/// tolerance, they return a `StepError` rather than retrying forever.
+#[derive(Debug, Clone)]
+pub struct DormandPrince<Y> {
+	/// Absolute error tolerance
+	pub atol: f64,
+	/// Relative error tolerance
+	pub rtol: f64,
+	/// Largest step size allowed
+	pub max_dt: f64,
+	/// Smallest step size allowed, steps this small are always accepted
+	pub min_dt: f64,
+	t: f64,
+	y: Y,
+	/// Size of the next step to try, `None` before the first step
+	dt: Option<f64>,
+	/// Derivative at the current time and state
+	k_first: Option<Y>,
+	last: Option<DenseStep<Y>>,
+	stats: StepStats,
+}
+
+impl<Y: OdeState> DormandPrince<Y> {
+	/// A new integrator starting from state `y` at time `t`.
+	pub fn new(t: f64, y: Y, atol: f64, rtol: f64) -> DormandPrince<Y> {
+		assert!(atol > 0_f64 && rtol >= 0_f64, "tolerances must be positive");
+		DormandPrince{
+			atol,
+			rtol,
+			max_dt: f64::INFINITY,
+			min_dt: 0_f64,
+			t,
+			y,
+			dt: None,
+			k_first: None,
+			last: None,
+			stats: StepStats::default(),
+		}
+	}
+
+	/// Start again from state `y` at time `t`, e.g. after a reset or
+	/// a change of parameters. The step counts are kept.
+	pub fn set_state(&mut self, t: f64, y: Y) {
+		self.t = t;
+		self.y = y;
+		self.dt = None;
+		self.k_first = None;
+		self.last = None;
+	}
+
+	/// The time the integrator has stepped to, which may be past the
+	/// last time asked for.
+	pub fn time(&self) -> f64 {
+		self.t
+	}
+
+	/// The state at `time()`.
+	pub fn state(&self) -> &Y {
+		&self.y
+	}
+
+	/// The size of the next step to try.
+	pub fn step_size(&self) -> Option<f64> {
+		self.dt
+	}
+
+	pub fn stats(&self) -> StepStats {
+		self.stats
+	}
+
+	pub fn reset_stats(&mut self) {
+		self.stats = StepStats::default();
+	}
+
+	/// The solution at time `t`, taking as many steps as needed to
+	/// get there. `t` can't be before the start of the last step.
+	/// If a step stalls the integrator stays at the last step it
+	/// managed to take.
+	pub fn advance_to<S>(&mut self, system: &S, t: f64) -> Result<Y, StepError>
+		where S: OdeSystem<State=Y> {
+		let earliest = self.last.as_ref().map_or(self.t, |last| last.t);
+		if t < earliest {
+			return Err(StepError::Backwards{t, earliest});
+		}
+		while self.t < t {
+			self.step(system)?;
+		}
+		Ok(match self.last {
+			Some(ref last) if t < self.t => last.interpolate(t),
+			_ => self.y.clone(),
+		})
+	}
+
+	/// Take one accepted step, retrying as often as needed. Fails,
+	/// leaving the state as it was, if the error can't be brought
+	/// within tolerance, or stays infinite or NaN, even with the
+	/// smallest step allowed: `min_dt`, or a step too small to move
+	/// the time forward.
+	pub fn step<S>(&mut self, system: &S) -> Result<(), StepError>
+		where S: OdeSystem<State=Y> {
+		let k_first = match self.k_first.take() {
+			Some(k) => k,
+			None => system.derivative(self.t, &self.y),
+		};
+		let mut dt = match self.dt {
+			Some(dt) => dt,
+			None => self.initial_step(system, &k_first),
+		};
+		loop {
+			dt = dt.min(self.max_dt).max(self.min_dt);
+			let (y_new, k, error) = self.trial_step(system, &k_first, dt);
+			let finite = error.is_finite();
+			let accept = finite && (error <= 1_f64 || dt <= self.min_dt);
+			// Grow or shrink the step for the error to just meet
+			// tolerance, shrinking as fast as allowed if the step blew up
+			let factor = if !finite {
+				MIN_FACTOR
+			} else if error > 0_f64 {
+				(SAFETY*error.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR)
+			} else {
+				MAX_FACTOR
+			};
+			if accept {
+				self.stats.accepted += 1;
+				self.last = Some(DenseStep{t: self.t, dt, y: self.y.clone(), k});
+				self.t += dt;
+				self.y = y_new;
+				// First same as last, the final stage is the next first
+				self.k_first = self.last.as_ref().map( |last| last.k[6].clone());
+				self.dt = Some(dt*factor);
+				return Ok(());
+			}
+			self.stats.rejected += 1;
+			let smallest = self.min_dt.max(MIN_STEP_FRACTION*self.t.abs().max(1_f64));
+			if dt <= smallest {
+				self.k_first = Some(k_first);
+				return Err(StepError::Stalled{t: self.t, error});
+			}
+			dt *= factor.min(1_f64);
+		}
+	}
+
+	/// The new state, stage derivatives and scaled error for a step
+	/// of size `dt`.
+	fn trial_step<S>(&self, system: &S, k_first: &Y, dt: f64) -> (Y, Vec<Y>, f64)
+		where S: OdeSystem<State=Y> {
+		let mut k = Vec::with_capacity(7);
+		k.push(k_first.clone());
+		let mut y_new = self.y.clone();
+		for (i, row) in DP_A.iter().enumerate() {
+			let mut y_stage = self.y.clone();
+			for (a, k_j) in row.iter().zip(k.iter()) {
+				if *a != 0_f64 {
+					y_stage = y_stage.add_scaled(dt*a, k_j);
+				}
+			}
+			if i == DP_A.len()-1 {
+				y_new = y_stage.clone();
+			}
+			k.push(system.derivative(self.t + DP_C[i+1]*dt, &y_stage));
+		}
+		let error = self.error_norm(&y_new, &k, dt);
+		(y_new, k, error)
+	}
+
+	/// The root mean square of the error estimate, in units of the
+	/// tolerance.
+	fn error_norm(&self, y_new: &Y, k: &[Y], dt: f64) -> f64 {
+		let y0 = components(&self.y);
+		let y1 = components(y_new);
+		let ks : Vec<Vec<f64>> = k.iter().map(components).collect();
+		let mut sum = 0_f64;
+		for j in 0..y0.len() {
+			let error : f64 = DP_E.iter().zip(ks.iter())
+				.map( |(e, k_i)| e*k_i[j])
+				.sum::<f64>()*dt;
+			let scale = self.atol + self.rtol*y0[j].abs().max(y1[j].abs());
+			sum += (error/scale).powi(2);
+		}
+		if y0.is_empty() {
+			0_f64
+		} else {
+			(sum/y0.len() as f64).sqrt()
+		}
+	}
+
+	/// A first guess at the step size, from the size of the state and
+	/// its derivatives (Hairer, Nørsett and Wanner).
+	fn initial_step<S>(&self, system: &S, k_first: &Y) -> f64
+		where S: OdeSystem<State=Y> {
+		let scale : Vec<f64> = components(&self.y).iter()
+			.map( |y| self.atol + self.rtol*y.abs())
+			.collect();
+		let norm = |v: &[f64]| {
+			let sum : f64 = v.iter().zip(scale.iter()).map( |(v, s)| (v/s).powi(2)).sum();
+			(sum/v.len().max(1) as f64).sqrt()
+		};
+		let d0 = norm(&components(&self.y));
+		let f0 = components(k_first);
+		let d1 = norm(&f0);
+		let h0 = if d0 < 1e-5 || d1 < 1e-5 { 1e-6 } else { 0.01*d0/d1 };
+		let h0 = h0.min(self.max_dt);
+		let y1 = self.y.add_scaled(h0, k_first);
+		let f1 = components(&system.derivative(self.t + h0, &y1));
+		let df : Vec<f64> = f1.iter().zip(f0.iter()).map( |(a, b)| a - b).collect();
+		let d2 = norm(&df)/h0;
+		let h1 = if d1.max(d2) <= 1e-15 {
+			(h0*1e-3).max(1e-6)
+		} else {
+			(0.01/d1.max(d2)).powf(0.2)
+		};
+		(100_f64*h0).min(h1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// dy/dt = -y, with y = exp(-t)
+	struct Decay;
+
+	impl OdeSystem for Decay {
+		type State = f64;
+
+		fn derivative(&self, _t: f64, y: &f64) -> f64 {
+			-y
+		}
+	}
+
+	/// Unit mass on a unit spring in 2D
+	struct Spring;
+
+	impl NewtonSystem for Spring {
+		type State = Vec2;
+
+		fn acceleration(&self, _t: f64, x: &Vec2) -> Vec2 {
+			-(*x)
+		}
+	}
+
+	fn decay_error<I: Integrator>(integrator: I, steps: u32) -> f64 {
+		let dt = 1_f64/steps as f64;
+		let mut y = 1_f64;
+		for i in 0..steps {
+			y = integrator.step(&Decay, i as f64*dt, &y, dt);
+		}
+		(y - (-1_f64).exp()).abs()
+	}
+
+	fn spring_error<I: NewtonIntegrator>(integrator: I, steps: u32) -> f64 {
+		let dt = 1_f64/steps as f64;
+		let mut x = Vec2{x: 1.0, y: 0.0};
+		let mut v = Vec2{x: 0.0, y: 1.0};
+		for i in 0..steps {
+			let (x1, v1) = integrator.step(&Spring, i as f64*dt, &x, &v, dt);
+			x = x1;
+			v = v1;
+		}
+		// Circular orbit
+		let (s, c) = 1_f64.sin_cos();
+		(x - Vec2{x: c, y: s}).norm()
+	}
+
+	/// Halving the step cuts the error by 2^order
+	fn order(error: f64, half_error: f64) -> f64 {
+		(error/half_error).log2()
+	}
+
+	#[test]
+	fn test_first_order_convergence() {
+		let euler = order(decay_error(Euler, 100), decay_error(Euler, 200));
+		let midpoint = order(decay_error(Midpoint, 100), decay_error(Midpoint, 200));
+		let rk4 = order(decay_error(Rk4, 20), decay_error(Rk4, 40));
+		assert!((euler - 1_f64).abs() < 0.1, "euler order {}", euler);
+		assert!((midpoint - 2_f64).abs() < 0.1, "midpoint order {}", midpoint);
+		assert!((rk4 - 4_f64).abs() < 0.1, "rk4 order {}", rk4);
+	}
+
+	#[test]
+	fn test_newton_convergence() {
+		let verlet = order(spring_error(VelocityVerlet, 100), spring_error(VelocityVerlet, 200));
+		let leapfrog = order(spring_error(Leapfrog, 100), spring_error(Leapfrog, 200));
+		assert!((verlet - 2_f64).abs() < 0.1, "velocity verlet order {}", verlet);
+		assert!((leapfrog - 2_f64).abs() < 0.1, "leapfrog order {}", leapfrog);
+	}
+
+	/// A Newtonian system stepped with RK4 through `FirstOrder`, and
+	/// a `Vec` state stepped particle by particle
+	#[test]
+	fn test_states() {
+		let dt = 0.01;
+		let mut y = (Vec2{x: 1.0, y: 0.0}, Vec2{x: 0.0, y: 1.0});
+		for i in 0..100 {
+			y = Rk4.step(&FirstOrder(&Spring), i as f64*dt, &y, dt);
+		}
+		let (s, c) = 1_f64.sin_cos();
+		assert!((y.0 - Vec2{x: c, y: s}).norm() < 1e-9);
+
+		let ys = vec!(1_f64, 2_f64);
+		assert_eq!(components(&(ys.clone(), Vec2{x: 3.0, y: 4.0})), vec!(1.0, 2.0, 3.0, 4.0));
+		let rates = vec!(0.5_f64, -1_f64);
+		assert_eq!(ys.add_scaled(2_f64, &rates), vec!(2_f64, 0_f64));
+	}
+
+	/// The adaptive integrator meets its tolerance, and interpolating
+	/// at the frame times gives the same answer as stepping to them
+	#[test]
+	fn test_dormand_prince() {
+		let mut dp = DormandPrince::new(0_f64, 1_f64, 1e-10, 1e-10);
+		let y = dp.advance_to(&Decay, 1_f64).unwrap();
+		assert!((y - (-1_f64).exp()).abs() < 1e-9);
+		let steps = dp.stats().accepted;
+		assert!(steps > 5 && steps < 200, "{} steps", steps);
+
+		let mut dp = DormandPrince::new(0_f64, (Vec2{x: 1.0, y: 0.0}, Vec2{x: 0.0, y: 1.0}), 1e-8, 1e-8);
+		for frame in 1..=60 {
+			let t = frame as f64/6_f64;
+			let (x, _v) = dp.advance_to(&FirstOrder(&Spring), t).unwrap();
+			let (s, c) = t.sin_cos();
+			assert!((x - Vec2{x: c, y: s}).norm() < 1e-6, "error at t = {}", t);
+		}
+		assert!(dp.time() >= 10_f64);
+		assert!(dp.stats().accepted < 600);
+	}
+
+	/// A stiff spring makes an oversized step fail, and the
+	/// integrator recovers by rejecting it and shrinking the step
+	#[test]
+	fn test_rejected_steps() {
+		struct Stiff;
+		impl NewtonSystem for Stiff {
+			type State = f64;
+			fn acceleration(&self, _t: f64, x: &f64) -> f64 {
+				-1.0e6*x
+			}
+		}
+		let mut dp = DormandPrince::new(0_f64, (1_f64, 0_f64), 1e-6, 1e-6);
+		dp.step(&FirstOrder(&Stiff)).unwrap();
+		dp.dt = Some(1_f64);
+		let (x, _v) = dp.advance_to(&FirstOrder(&Stiff), 0.01).unwrap();
+		assert!(dp.stats().rejected > 0);
+		assert!((x - (0.01_f64*1000.0).cos()).abs() < 1e-3);
+	}
+
+	/// A solution that blows up in finite time gives infinite or NaN
+	/// errors; the integrator shrinks the step and gives up rather
+	/// than retrying forever, staying at its last good step
+	#[test]
+	fn test_blow_up() {
+		struct Blow;
+		impl OdeSystem for Blow {
+			type State = f64;
+			fn derivative(&self, _t: f64, y: &f64) -> f64 {
+				if *y > 10.0 { f64::NAN } else { y*y }
+			}
+		}
+		let mut dp = DormandPrince::new(0_f64, 1_f64, 1e-6, 1e-6);
+		dp.dt = Some(10_f64);
+		match dp.advance_to(&Blow, 2_f64) {
+			Err(StepError::Stalled{t, ..}) => assert_eq!(t, dp.time()),
+			result => panic!("expected a stall, got {:?}", result),
+		}
+		// y = 1/(1 - t) passes 10 at t = 0.9
+		assert!((dp.time() - 0.9).abs() < 1e-3, "stalled at {}", dp.time());
+		assert!(*dp.state() <= 10.0);
+		assert!(dp.stats().rejected > 0);
+		match dp.advance_to(&Blow, 0.5) {
+			Err(StepError::Backwards{t, earliest}) => assert!(t == 0.5 && earliest > 0.5),
+			result => panic!("expected going backwards to fail, got {:?}", result),
+		}
+	}
+}
@@ -5,6 +5,22 @@ pub mod container;
 pub mod output;
 pub mod control;
 pub mod simulation;
+pub mod snapshot;
+pub mod history;
+pub mod time;
+pub mod integrate;
+pub mod symplectic;
+pub mod sde;
+pub mod thermostat;
+pub mod constraints;
+pub mod diagnostics;
+pub mod springs;
+pub mod boundary;
+pub mod neighbors;
+pub mod hard_disks;
+pub mod rigid;
+pub mod nbody;
+pub mod potentials;
 
 pub mod simple_rng;
 pub mod simple_color;
@@ -0,0 +1,463 @@
+/// Gravitational N-body systems
+/// ============================
+///
+/// Point masses attracting each other with Newtonian gravity, softened
+/// over a length ε so close encounters don't need tiny time steps: the
+/// potential between two bodies is -G m₁ m₂ / sqrt(r² + ε²) (Plummer
+/// softening).
+///
+/// The forces can be found by direct summation over every pair, exact
+/// but O(N²), or with a Barnes-Hut quadtree, O(N log N), which treats
+/// a distant group of bodies as a single mass at its center of mass.
+/// How distant is set by the opening angle θ: a cell of size s at a
+/// distance d is used whole when s/d < θ. θ = 0.5 gives forces good to
+/// about a percent; θ = 0 opens every cell and is as exact as direct
+/// summation.
+///
+/// `Gravitation` is a `symplectic::SeparableHamiltonian`, and `NBody`
+/// holds the positions and momenta of the bodies, stepping them with
+/// any `SymplecticIntegrator`.
+///
+/// A planet on a circular orbit:
+///
+/// ```
+/// # use websim::simple_vec::Vec2;
+/// # use websim::nbody::*;
+/// # use websim::symplectic::Yoshida4;
+/// let mut system = NBody::new(Gravitation::new(1.0, 0.0, Method::Direct));
+/// system.add(1.0, Vec2::zero(), Vec2::zero());
+/// system.add(1.0e-6, Vec2{x: 1.0, y: 0.0}, Vec2{x: 0.0, y: 1.0});
+/// let period = 2.0*std::f64::consts::PI;
+/// for _ in 0..1000 {
+///     system.step(&Yoshida4, period/1000.0);
+/// }
+/// assert!((system.q[1] - Vec2{x: 1.0, y: 0.0}).norm() < 1e-3);
+/// ```
+use ::diagnostics::Conserved;
+use ::simple_vec::Vec2;
+use ::symplectic::{SeparableHamiltonian, SymplecticIntegrator};
+
+/// How the forces are found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+	/// Sum over every pair of bodies
+	Direct,
+	/// Use a Barnes-Hut quadtree with opening angle `theta`
+	BarnesHut{theta: f64},
+}
+
+/// Cells this deep in a quadtree aren't split any further, so bodies
+/// at the same point don't split cells forever.
+const MAX_DEPTH : usize = 48;
+
+/// A cell of a quadtree.
+#[derive(Debug, Clone)]
+struct Cell {
+	/// The middle of the cell
+	center: Vec2,
+	/// Half the width of the cell
+	half: f64,
+	mass: f64,
+	center_of_mass: Vec2,
+	/// Indices of the child cells, empty for a leaf
+	children: Vec<usize>,
+	/// Bodies in a leaf
+	bodies: Vec<usize>,
+}
+
+impl Cell {
+	fn contains(&self, r: Vec2) -> bool {
+		(r.x - self.center.x).abs() <= self.half && (r.y - self.center.y).abs() <= self.half
+	}
+}
+
+/// A Barnes-Hut quadtree of bodies, each cell holding the total mass
+/// and center of mass of the bodies in it.
+#[derive(Debug, Clone)]
+pub struct QuadTree {
+	cells: Vec<Cell>,
+}
+
+impl QuadTree {
+	/// The quadtree of bodies of masses `m` at positions `r`.
+	pub fn new(r: &[Vec2], m: &[f64]) -> QuadTree {
+		assert_eq!(r.len(), m.len(), "positions and masses differ in length");
+		let mut tree = QuadTree{cells: Vec::new()};
+		if r.is_empty() {
+			return tree;
+		}
+		let (mut lo, mut hi) = (r[0], r[0]);
+		for r in r.iter() {
+			lo = Vec2{x: lo.x.min(r.x), y: lo.y.min(r.y)};
+			hi = Vec2{x: hi.x.max(r.x), y: hi.y.max(r.y)};
+		}
+		let center = 0.5*(lo + hi);
+		// A little room, so rounding in the cell centers doesn't leave
+		// the outermost bodies just outside their cells
+		let half = (0.5*(hi.x - lo.x)).max(0.5*(hi.y - lo.y)).max(f64::MIN_POSITIVE)*(1_f64 + 1e-9);
+		tree.build((0..r.len()).collect(), center, half, 0, r, m);
+		tree
+	}
+
+	/// Build the cell holding `bodies`, returning its index.
+	fn build(&mut self, bodies: Vec<usize>, center: Vec2, half: f64, depth: usize, r: &[Vec2], m: &[f64]) -> usize {
+		let mass : f64 = bodies.iter().map( |&i| m[i]).sum();
+		let center_of_mass = if mass > 0_f64 {
+			bodies.iter().fold(Vec2::zero(), |c, &i| c + m[i]*r[i])/mass
+		} else {
+			center
+		};
+		let index = self.cells.len();
+		self.cells.push(Cell{center, half, mass, center_of_mass, children: Vec::new(), bodies: Vec::new()});
+		if bodies.len() <= 1 || depth == MAX_DEPTH {
+			self.cells[index].bodies = bodies;
+			return index;
+		}
+		let mut quadrants = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+		for i in bodies {
+			let q = (r[i].x >= center.x) as usize + 2*(r[i].y >= center.y) as usize;
+			quadrants[q].push(i);
+		}
+		let quarter = 0.5*half;
+		let mut children = Vec::new();
+		for (q, bodies) in quadrants.iter_mut().enumerate() {
+			if bodies.is_empty() {
+				continue;
+			}
+			let offset = Vec2{
+				x: if q & 1 == 1 { quarter } else { -quarter },
+				y: if q & 2 == 2 { quarter } else { -quarter },
+			};
+			let bodies = ::std::mem::take(bodies);
+			children.push(self.build(bodies, center + offset, quarter, depth + 1, r, m));
+		}
+		self.cells[index].children = children;
+		index
+	}
+
+	/// The number of cells.
+	pub fn len(&self) -> usize {
+		self.cells.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.cells.is_empty()
+	}
+
+	/// The total mass.
+	pub fn mass(&self) -> f64 {
+		self.cells.first().map_or(0_f64, |c| c.mass)
+	}
+
+	/// The center of mass of all the bodies.
+	pub fn center_of_mass(&self) -> Vec2 {
+		self.cells.first().map_or(Vec2::zero(), |c| c.center_of_mass)
+	}
+
+	/// The gravitational field, G = 1, at body `i` at position `ri`
+	/// from every other body in the tree, with softening length
+	/// `softening` and opening angle `theta`.
+	pub fn field(&self, i: usize, ri: Vec2, r: &[Vec2], m: &[f64], softening: f64, theta: f64) -> Vec2 {
+		let mut field = Vec2::zero();
+		let mut stack = if self.cells.is_empty() { vec!() } else { vec!(0) };
+		while let Some(c) = stack.pop() {
+			let cell = &self.cells[c];
+			if cell.children.is_empty() {
+				for &j in cell.bodies.iter().filter( |&&j| j != i) {
+					field = field + pair_field(r[j] - ri, m[j], softening);
+				}
+				continue;
+			}
+			let d = cell.center_of_mass - ri;
+			if !cell.contains(ri) && 2_f64*cell.half < theta*d.norm() {
+				field = field + pair_field(d, cell.mass, softening);
+			} else {
+				stack.extend(cell.children.iter().cloned());
+			}
+		}
+		field
+	}
+}
+
+/// The softened field, G = 1, of a mass `m` displaced by `d`.
+fn pair_field(d: Vec2, m: f64, softening: f64) -> Vec2 {
+	let r2 = d.norm_squared() + softening*softening;
+	if r2 == 0_f64 {
+		return Vec2::zero();
+	}
+	(m/(r2*r2.sqrt()))*d
+}
+
+/// Softened Newtonian gravity between bodies of the given masses.
+#[derive(Debug, Clone)]
+pub struct Gravitation {
+	pub masses: Vec<f64>,
+	/// The gravitational constant
+	pub g: f64,
+	/// The softening length ε
+	pub softening: f64,
+	pub method: Method,
+}
+
+impl Gravitation {
+	/// Gravitation with constant `g`, between no bodies yet.
+	pub fn new(g: f64, softening: f64, method: Method) -> Gravitation {
+		Gravitation{masses: Vec::new(), g, softening, method}
+	}
+
+	/// The forces on bodies at positions `q` by direct summation.
+	pub fn direct_forces(&self, q: &[Vec2], f: &mut [Vec2]) {
+		for f in f.iter_mut() {
+			*f = Vec2::zero();
+		}
+		for i in 0..q.len() {
+			for j in i+1..q.len() {
+				let pull = (self.g*self.masses[i]*self.masses[j])*pair_field(q[j] - q[i], 1_f64, self.softening);
+				f[i] = f[i] + pull;
+				f[j] = f[j] - pull;
+			}
+		}
+	}
+
+	/// The forces on bodies at positions `q` from a Barnes-Hut
+	/// quadtree with opening angle `theta`.
+	pub fn tree_forces(&self, q: &[Vec2], f: &mut [Vec2], theta: f64) {
+		let tree = QuadTree::new(q, &self.masses);
+		for (i, f) in f.iter_mut().enumerate() {
+			*f = (self.g*self.masses[i])*tree.field(i, q[i], q, &self.masses, self.softening, theta);
+		}
+	}
+}
+
+impl SeparableHamiltonian for Gravitation {
+	fn kinetic(&self, p: &[Vec2]) -> f64 {
+		p.iter().zip(self.masses.iter()).map( |(p, &m)| p.norm_squared()/(2_f64*m)).sum()
+	}
+
+	/// The potential energy, always by direct summation.
+	fn potential(&self, q: &[Vec2]) -> f64 {
+		let eps2 = self.softening*self.softening;
+		let mut potential = 0_f64;
+		for i in 0..q.len() {
+			for j in i+1..q.len() {
+				let r2 = (q[j] - q[i]).norm_squared() + eps2;
+				if r2 > 0_f64 {
+					potential -= self.g*self.masses[i]*self.masses[j]/r2.sqrt();
+				}
+			}
+		}
+		potential
+	}
+
+	fn velocity(&self, p: &[Vec2], dq: &mut [Vec2]) {
+		for ((dq, p), &m) in dq.iter_mut().zip(p.iter()).zip(self.masses.iter()) {
+			*dq = *p/m;
+		}
+	}
+
+	fn force(&self, q: &[Vec2], dp: &mut [Vec2]) {
+		match self.method {
+			Method::Direct => self.direct_forces(q, dp),
+			Method::BarnesHut{theta} => self.tree_forces(q, dp, theta),
+		}
+	}
+}
+
+/// A gravitating system: the positions and momenta of its bodies.
+#[derive(Debug, Clone)]
+pub struct NBody {
+	pub gravitation: Gravitation,
+	pub q: Vec<Vec2>,
+	pub p: Vec<Vec2>,
+	pub time: f64,
+}
+
+impl NBody {
+	pub fn new(gravitation: Gravitation) -> NBody {
+		NBody{gravitation, q: Vec::new(), p: Vec::new(), time: 0_f64}
+	}
+
+	/// Add a body of mass `mass` at `r` moving with velocity `v`,
+	/// returning its index.
+	pub fn add(&mut self, mass: f64, r: Vec2, v: Vec2) -> usize {
+		assert!(mass > 0_f64, "mass must be positive");
+		self.gravitation.masses.push(mass);
+		self.q.push(r);
+		self.p.push(mass*v);
+		self.q.len() - 1
+	}
+
+	pub fn len(&self) -> usize {
+		self.q.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.q.is_empty()
+	}
+
+	pub fn mass(&self, i: usize) -> f64 {
+		self.gravitation.masses[i]
+	}
+
+	pub fn velocity(&self, i: usize) -> Vec2 {
+		self.p[i]/self.mass(i)
+	}
+
+	pub fn total_mass(&self) -> f64 {
+		self.gravitation.masses.iter().sum()
+	}
+
+	pub fn center_of_mass(&self) -> Vec2 {
+		let weighted = self.q.iter().zip(self.gravitation.masses.iter())
+			.fold(Vec2::zero(), |c, (&r, &m)| c + m*r);
+		weighted/self.total_mass()
+	}
+
+	/// Shift to the frame where the center of mass is at rest at the
+	/// origin.
+	pub fn to_center_of_mass_frame(&mut self) {
+		let (r, v) = (self.center_of_mass(), Conserved::momentum(self)/self.total_mass());
+		for ((q, p), &m) in self.q.iter_mut().zip(self.p.iter_mut()).zip(self.gravitation.masses.iter()) {
+			*q = *q - r;
+			*p = *p - m*v;
+		}
+	}
+
+	/// Advance the bodies by a time `dt` with `integrator`.
+	pub fn step<I: SymplecticIntegrator>(&mut self, integrator: &I, dt: f64) {
+		integrator.step(&self.gravitation, &mut self.q, &mut self.p, dt);
+		self.time += dt;
+	}
+}
+
+impl Conserved for NBody {
+	fn kinetic_energy(&self) -> f64 {
+		self.gravitation.kinetic(&self.p)
+	}
+
+	fn potential_energy(&self) -> f64 {
+		self.gravitation.potential(&self.q)
+	}
+
+	fn momentum(&self) -> Vec2 {
+		self.p.iter().fold(Vec2::zero(), |total, &p| total + p)
+	}
+
+	fn angular_momentum(&self) -> f64 {
+		self.q.iter().zip(self.p.iter()).map( |(r, &p)| r.cross(p)).sum()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ::simple_rng::Rng;
+	use ::symplectic::{StormerVerlet, Yoshida4};
+
+	/// `n` bodies of random mass in a clump of radius 1
+	fn random_bodies(n: usize, softening: f64, method: Method) -> NBody {
+		let mut rng = Rng::new();
+		rng.seed(12345, 67890);
+		let mut system = NBody::new(Gravitation::new(1.0, softening, method));
+		for _ in 0..n {
+			let (radius, angle) = (rng.uniform().sqrt(), 2.0*::std::f64::consts::PI*rng.uniform());
+			let r = Vec2{x: radius*angle.cos(), y: radius*angle.sin()};
+			let v = Vec2{x: rng.uniform() - 0.5, y: rng.uniform() - 0.5};
+			system.add(0.5 + rng.uniform(), r, v);
+		}
+		system
+	}
+
+	fn forces(system: &NBody) -> Vec<Vec2> {
+		let mut f = vec!(Vec2::zero(); system.len());
+		system.gravitation.force(&system.q, &mut f);
+		f
+	}
+
+	/// The tree holds all the mass, and its cells partition the bodies
+	#[test]
+	fn test_quadtree() {
+		let system = random_bodies(300, 0.0, Method::Direct);
+		let tree = QuadTree::new(&system.q, &system.gravitation.masses);
+		assert!((tree.mass() - system.total_mass()).abs() < 1e-10);
+		assert!((tree.center_of_mass() - system.center_of_mass()).norm() < 1e-12);
+		let mut count = vec!(0; system.len());
+		for cell in tree.cells.iter() {
+			for &i in cell.bodies.iter() {
+				assert!(cell.contains(system.q[i]));
+				count[i] += 1;
+			}
+		}
+		assert!(count.iter().all( |&c| c == 1));
+		// Bodies on top of each other share a leaf
+		let r = [Vec2{x: 1.0, y: 1.0}; 3];
+		let tree = QuadTree::new(&r, &[1.0; 3]);
+		assert!(tree.len() <= MAX_DEPTH + 1);
+		assert_eq!(tree.field(0, r[0], &r, &[1.0; 3], 0.0, 0.5), Vec2::zero());
+	}
+
+	/// The tree forces match direct summation: exactly when every cell
+	/// is opened, and to about a percent with θ = 0.5, getting worse
+	/// as θ grows
+	#[test]
+	fn test_tree_vs_direct() {
+		let mut system = random_bodies(1000, 0.01, Method::Direct);
+		let direct = forces(&system);
+		system.gravitation.method = Method::BarnesHut{theta: 0.0};
+		for (f, d) in forces(&system).iter().zip(direct.iter()) {
+			assert!((*f - *d).norm() <= 1e-9*d.norm(), "{:?} against {:?}", f, d);
+		}
+		let mut last = 0_f64;
+		for &(theta, tolerance) in [(0.3, 5e-3), (0.5, 2e-2), (0.8, 6e-2)].iter() {
+			system.gravitation.method = Method::BarnesHut{theta};
+			let tree = forces(&system);
+			let error : f64 = tree.iter().zip(direct.iter()).map( |(f, d)| (*f - *d).norm_squared()).sum();
+			let scale : f64 = direct.iter().map( |d| d.norm_squared()).sum();
+			let error = (error/scale).sqrt();
+			assert!(error < tolerance && error > last, "theta {} rms error {}", theta, error);
+			last = error;
+		}
+	}
+
+	/// Direct forces are equal and opposite, so momentum is kept, and
+	/// the energy error stays small
+	#[test]
+	fn test_conservation() {
+		let mut system = random_bodies(20, 0.05, Method::Direct);
+		let (e0, p0, l0) = (system.energy(), Conserved::momentum(&system), Conserved::angular_momentum(&system));
+		for _ in 0..2000 {
+			system.step(&StormerVerlet, 1.0e-3);
+		}
+		assert!((Conserved::momentum(&system) - p0).norm() < 1e-10);
+		assert!((Conserved::angular_momentum(&system) - l0).abs() < 1e-10);
+		assert!(((system.energy() - e0)/e0).abs() < 1e-3, "energy {} from {}", system.energy(), e0);
+	}
+
+	/// Two equal bodies on a circular orbit about their center of mass
+	/// come back after a period 2π sqrt(a³/G(m₁+m₂))
+	#[test]
+	fn test_binary() {
+		let mut system = NBody::new(Gravitation::new(2.0, 0.0, Method::Direct));
+		// Separation a = 2, each at speed sqrt(G m/(2a))
+		let speed = (2.0*3.0/4.0_f64).sqrt();
+		system.add(3.0, Vec2{x: -1.0, y: 0.0}, Vec2{x: 0.0, y: -speed});
+		system.add(3.0, Vec2{x: 1.0, y: 0.0}, Vec2{x: 0.0, y: speed});
+		let period = 2.0*::std::f64::consts::PI*(8.0/(2.0*6.0_f64)).sqrt();
+		let e0 = system.energy();
+		let steps = 2000;
+		for _ in 0..steps {
+			system.step(&Yoshida4, period/steps as f64);
+		}
+		assert!((system.time - period).abs() < 1e-9);
+		assert!((system.q[1] - Vec2{x: 1.0, y: 0.0}).norm() < 1e-6, "at {:?}", system.q[1]);
+		assert!(((system.energy() - e0)/e0).abs() < 1e-9);
+	}
+
+	#[test]
+	fn test_center_of_mass_frame() {
+		let mut system = random_bodies(10, 0.0, Method::Direct);
+		system.to_center_of_mass_frame();
+		assert!(system.center_of_mass().norm() < 1e-12);
+		assert!(Conserved::momentum(&system).norm() < 1e-12);
+	}
+}
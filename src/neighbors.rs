@@ -0,0 +1,429 @@
+/// Neighbor search
+/// ===============
+///
+/// A `CellList` divides a rectangular box into square-ish cells at
+/// least as wide as the interaction cutoff, so that the particles
+/// interacting with a particle are all in its own cell or the eight
+/// around it. Finding every pair closer than the cutoff then takes a
+/// time proportional to the number of particles, rather than to its
+/// square.
+///
+/// A `VerletList` keeps the pairs found with a cell list out to the
+/// cutoff plus a "skin" distance, and reuses them step after step
+/// until some particle has moved more than half the skin, so the
+/// cells only need rebuilding every so often.
+///
+/// The box and its edges are given by a `boundary::Boundary`. Along
+/// periodic axes the cells wrap around and distances use the minimum
+/// image; along reflecting or open axes particles outside the box are
+/// binned in the nearest edge cell.
+use std::collections::HashSet;
+
+use ::boundary::{Boundary, Edge};
+use ::simple_vec::Vec2;
+
+/// A pair of particles closer than the cutoff, with `i < j`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pair {
+	pub i: usize,
+	pub j: usize,
+	/// The displacement from particle `j` to particle `i`, using the
+	/// minimum image for periodic boxes
+	pub rij: Vec2,
+	/// The squared distance between the particles
+	pub r2: f64,
+}
+
+impl Pair {
+	pub fn distance(&self) -> f64 {
+		self.r2.sqrt()
+	}
+}
+
+/// Offsets to the neighboring cells that are searched from each cell:
+/// half of the eight neighbors, so every pair of cells is visited once.
+const HALF_STENCIL : [(isize, isize); 4] = [(1, 0), (-1, 1), (0, 1), (1, 1)];
+
+/// Bins particles into cells for finding the pairs closer than a
+/// cutoff distance.
+#[derive(Debug, Clone)]
+pub struct CellList {
+	boundary: Boundary,
+	cutoff: f64,
+	/// Number of cells along x and y
+	nx: usize,
+	ny: usize,
+	cell_size: Vec2,
+	/// The particle indices in each cell
+	cells: Vec<Vec<usize>>,
+	/// The neighboring cells searched from each cell
+	neighbors: Vec<Vec<usize>>,
+}
+
+impl CellList {
+	/// A cell list for the box of `boundary`, finding pairs closer
+	/// than `cutoff`.
+	pub fn new(boundary: Boundary, cutoff: f64) -> CellList {
+		assert!(cutoff > 0_f64, "cutoff must be positive");
+		let size = boundary.size();
+		let cells_along = |length: f64, edge: Edge| {
+			if edge == Edge::Periodic {
+				assert!(cutoff <= 0.5*length,
+					"cutoff can't be more than half the size of a periodic box");
+			}
+			let n = ((length/cutoff).floor() as usize).max(1);
+			// With fewer than three cells across a periodic box a
+			// neighboring cell is reached from both sides, so use one
+			if edge == Edge::Periodic && n < 3 { 1 } else { n }
+		};
+		let nx = cells_along(size.x, boundary.x);
+		let ny = cells_along(size.y, boundary.y);
+		let cell_size = Vec2{x: size.x/nx as f64, y: size.y/ny as f64};
+		let mut list = CellList{
+			boundary,
+			cutoff,
+			nx,
+			ny,
+			cell_size,
+			cells: vec!(Vec::new(); nx*ny),
+			neighbors: Vec::with_capacity(nx*ny),
+		};
+		// With a single cell across a periodic axis, different offsets
+		// can lead to the same pair of cells, so keep each pair once
+		let mut seen = HashSet::new();
+		list.neighbors = (0..nx*ny).map( |c| list.half_stencil(c, &mut seen)).collect();
+		list
+	}
+
+	pub fn boundary(&self) -> Boundary {
+		self.boundary
+	}
+
+	pub fn cutoff(&self) -> f64 {
+		self.cutoff
+	}
+
+	/// The number of cells along x and y.
+	pub fn shape(&self) -> (usize, usize) {
+		(self.nx, self.ny)
+	}
+
+	/// The distinct neighboring cells searched from cell `c`.
+	fn half_stencil(&self, c: usize, seen: &mut HashSet<(usize, usize)>) -> Vec<usize> {
+		let (cx, cy) = ((c % self.nx) as isize, (c / self.nx) as isize);
+		let (nx, ny) = (self.nx as isize, self.ny as isize);
+		let mut neighbors = Vec::new();
+		for &(dx, dy) in HALF_STENCIL.iter() {
+			let (mut x, mut y) = (cx + dx, cy + dy);
+			if self.boundary.x == Edge::Periodic {
+				x = (x + nx) % nx;
+			} else if x < 0 || x >= nx {
+				continue;
+			}
+			if self.boundary.y == Edge::Periodic {
+				y = (y + ny) % ny;
+			} else if y >= ny {
+				continue;
+			}
+			let n = (y*nx + x) as usize;
+			if n != c && seen.insert((c.min(n), c.max(n))) {
+				neighbors.push(n);
+			}
+		}
+		neighbors
+	}
+
+	/// The cell holding a point. Points outside the box are wrapped
+	/// back in along periodic axes, or put in the nearest edge cell.
+	pub fn cell_of(&self, r: Vec2) -> usize {
+		let r = self.boundary.wrap(r);
+		let index = |x: f64, width: f64, n: usize| {
+			((x/width).floor().max(0_f64) as usize).min(n-1)
+		};
+		let x = index(r.x, self.cell_size.x, self.nx);
+		let y = index(r.y, self.cell_size.y, self.ny);
+		y*self.nx + x
+	}
+
+	/// The displacement `a - b`, see `Boundary::displacement`.
+	pub fn displacement(&self, a: Vec2, b: Vec2) -> Vec2 {
+		self.boundary.displacement(a, b)
+	}
+
+	/// Sort the particles at `positions` into cells. Call this
+	/// whenever the particles have moved, before looking for pairs.
+	pub fn build(&mut self, positions: &[Vec2]) {
+		for cell in self.cells.iter_mut() {
+			cell.clear();
+		}
+		for (i, r) in positions.iter().enumerate() {
+			let c = self.cell_of(*r);
+			self.cells[c].push(i);
+		}
+	}
+
+	/// Call `func` with every pair of particles closer than the
+	/// cutoff, each pair once. `positions` must be the ones the list
+	/// was last built with.
+	pub fn for_each_pair<F>(&self, positions: &[Vec2], mut func: F)
+		where F: FnMut(Pair) {
+		let cutoff2 = self.cutoff*self.cutoff;
+		let mut check = |a: usize, b: usize| {
+			let (i, j) = if a < b { (a, b) } else { (b, a) };
+			let rij = self.displacement(positions[i], positions[j]);
+			let r2 = rij.norm_squared();
+			if r2 < cutoff2 {
+				func(Pair{i, j, rij, r2});
+			}
+		};
+		for (c, cell) in self.cells.iter().enumerate() {
+			for (k, &a) in cell.iter().enumerate() {
+				for &b in cell[k+1..].iter() {
+					check(a, b);
+				}
+				for &n in self.neighbors[c].iter() {
+					for &b in self.cells[n].iter() {
+						check(a, b);
+					}
+				}
+			}
+		}
+	}
+
+	/// Every pair of particles closer than the cutoff.
+	pub fn pairs(&self, positions: &[Vec2]) -> Vec<Pair> {
+		let mut pairs = Vec::new();
+		self.for_each_pair(positions, |pair| pairs.push(pair));
+		pairs
+	}
+}
+
+/// Counts of how often a `VerletList` was rebuilt.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NeighborStats {
+	/// Calls to `VerletList::update`
+	pub updates: u64,
+	/// Times the list was rebuilt
+	pub rebuilds: u64,
+}
+
+impl NeighborStats {
+	/// The average number of updates between rebuilds.
+	pub fn updates_per_rebuild(&self) -> f64 {
+		if self.rebuilds == 0 {
+			self.updates as f64
+		} else {
+			self.updates as f64/self.rebuilds as f64
+		}
+	}
+}
+
+/// A list of the pairs of particles closer than the cutoff plus a
+/// skin distance, rebuilt with a `CellList` only when a particle has
+/// moved more than half the skin since the last rebuild. Until then
+/// no pair can have come from outside the skin to inside the cutoff,
+/// so checking the listed pairs finds every interacting pair.
+#[derive(Debug, Clone)]
+pub struct VerletList {
+	cells: CellList,
+	cutoff: f64,
+	skin: f64,
+	/// Candidate pairs, `i < j`
+	pairs: Vec<(usize, usize)>,
+	/// Positions at the last rebuild
+	reference: Vec<Vec2>,
+	stats: NeighborStats,
+}
+
+impl VerletList {
+	/// A neighbor list for the box of `boundary`, finding pairs
+	/// closer than `cutoff`, with a skin of `skin`.
+	pub fn new(boundary: Boundary, cutoff: f64, skin: f64) -> VerletList {
+		assert!(skin >= 0_f64, "skin can't be negative");
+		VerletList{
+			cells: CellList::new(boundary, cutoff + skin),
+			cutoff,
+			skin,
+			pairs: Vec::new(),
+			reference: Vec::new(),
+			stats: NeighborStats::default(),
+		}
+	}
+
+	pub fn boundary(&self) -> Boundary {
+		self.cells.boundary()
+	}
+
+	pub fn cutoff(&self) -> f64 {
+		self.cutoff
+	}
+
+	pub fn skin(&self) -> f64 {
+		self.skin
+	}
+
+	pub fn stats(&self) -> NeighborStats {
+		self.stats
+	}
+
+	pub fn reset_stats(&mut self) {
+		self.stats = NeighborStats::default();
+	}
+
+	/// The displacement `a - b`, see `CellList::displacement`.
+	pub fn displacement(&self, a: Vec2, b: Vec2) -> Vec2 {
+		self.cells.displacement(a, b)
+	}
+
+	/// Whether a particle has moved more than half the skin since the
+	/// last rebuild, or the number of particles has changed.
+	pub fn needs_rebuild(&self, positions: &[Vec2]) -> bool {
+		if positions.len() != self.reference.len() {
+			return true;
+		}
+		let limit2 = 0.25*self.skin*self.skin;
+		positions.iter().zip(self.reference.iter())
+			.any( |(&r, &r0)| self.displacement(r, r0).norm_squared() > limit2)
+	}
+
+	/// Rebuild the list from the particles at `positions`.
+	pub fn rebuild(&mut self, positions: &[Vec2]) {
+		self.cells.build(positions);
+		let mut pairs = Vec::new();
+		self.cells.for_each_pair(positions, |pair| pairs.push((pair.i, pair.j)));
+		self.pairs = pairs;
+		self.reference = positions.to_vec();
+		self.stats.rebuilds += 1;
+	}
+
+	/// Bring the list up to date with the particles at `positions`,
+	/// rebuilding it if needed. Call this once per step, before
+	/// looking for pairs. Returns whether the list was rebuilt.
+	pub fn update(&mut self, positions: &[Vec2]) -> bool {
+		self.stats.updates += 1;
+		if self.needs_rebuild(positions) {
+			self.rebuild(positions);
+			true
+		} else {
+			false
+		}
+	}
+
+	/// Call `func` with every pair of particles closer than the
+	/// cutoff, each pair once.
+	pub fn for_each_pair<F>(&self, positions: &[Vec2], mut func: F)
+		where F: FnMut(Pair) {
+		let cutoff2 = self.cutoff*self.cutoff;
+		for &(i, j) in self.pairs.iter() {
+			let rij = self.displacement(positions[i], positions[j]);
+			let r2 = rij.norm_squared();
+			if r2 < cutoff2 {
+				func(Pair{i, j, rij, r2});
+			}
+		}
+	}
+
+	/// Every pair of particles closer than the cutoff.
+	pub fn pairs(&self, positions: &[Vec2]) -> Vec<Pair> {
+		let mut pairs = Vec::new();
+		self.for_each_pair(positions, |pair| pairs.push(pair));
+		pairs
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ::simple_rng::Rng;
+
+	fn random_points(n: usize, size: Vec2) -> Vec<Vec2> {
+		let mut rng = Rng::new();
+		(0..n).map( |_| Vec2{x: size.x*rng.uniform(), y: size.y*rng.uniform()}).collect()
+	}
+
+	/// Every pair closer than the cutoff, checking them all
+	fn brute_force(list: &CellList, positions: &[Vec2]) -> Vec<(usize, usize)> {
+		let mut pairs = Vec::new();
+		for i in 0..positions.len() {
+			for j in i+1..positions.len() {
+				let d = list.displacement(positions[i], positions[j]);
+				if d.norm() < list.cutoff() {
+					pairs.push((i, j));
+				}
+			}
+		}
+		pairs
+	}
+
+	fn found(list: &CellList, positions: &[Vec2]) -> Vec<(usize, usize)> {
+		let mut pairs : Vec<(usize, usize)> = list.pairs(positions).iter()
+			.map( |p| (p.i, p.j))
+			.collect();
+		pairs.sort();
+		pairs
+	}
+
+	#[test]
+	fn test_pairs_match_brute_force() {
+		let size = Vec2{x: 10.0, y: 7.0};
+		let positions = random_points(400, size);
+		let edges = [
+			(Edge::Periodic, Edge::Periodic),
+			(Edge::Reflecting, Edge::Reflecting),
+			(Edge::Periodic, Edge::Open),
+			(Edge::Open, Edge::Periodic),
+		];
+		for &(x, y) in edges.iter() {
+			for &cutoff in [0.5, 1.3, 3.5].iter() {
+				let mut list = CellList::new(Boundary::new(size, x, y), cutoff);
+				list.build(&positions);
+				assert_eq!(found(&list, &positions), brute_force(&list, &positions),
+					"{:?}, {:?} with cutoff {}", x, y, cutoff);
+			}
+		}
+	}
+
+	/// Pairs across a periodic edge use the minimum image, and walls
+	/// don't see across
+	#[test]
+	fn test_minimum_image() {
+		let size = Vec2{x: 10.0, y: 10.0};
+		let positions = [Vec2{x: 0.2, y: 5.0}, Vec2{x: 9.9, y: 5.1}];
+		let mut list = CellList::new(Boundary::periodic(size), 1.0);
+		list.build(&positions);
+		let pairs = list.pairs(&positions);
+		assert_eq!(pairs.len(), 1);
+		assert!((pairs[0].rij - Vec2{x: 0.3, y: -0.1}).norm() < 1e-12);
+
+		let mut list = CellList::new(Boundary::reflecting(size), 1.0);
+		list.build(&positions);
+		assert!(list.pairs(&positions).is_empty());
+	}
+
+	/// As the particles wander the Verlet list keeps finding the same
+	/// pairs as a fresh cell list, while rebuilding only now and then
+	#[test]
+	fn test_verlet_list() {
+		let size = Vec2{x: 10.0, y: 10.0};
+		let mut positions = random_points(300, size);
+		let mut rng = Rng::new();
+		let mut verlet = VerletList::new(Boundary::periodic(size), 1.0, 0.4);
+		let mut cells = CellList::new(Boundary::periodic(size), 1.0);
+		for _ in 0..200 {
+			for r in positions.iter_mut() {
+				r.x += 0.02*(rng.uniform() - 0.5);
+				r.y += 0.02*(rng.uniform() - 0.5);
+			}
+			verlet.update(&positions);
+			cells.build(&positions);
+			let mut pairs : Vec<(usize, usize)> = verlet.pairs(&positions).iter()
+				.map( |p| (p.i, p.j))
+				.collect();
+			pairs.sort();
+			assert_eq!(pairs, found(&cells, &positions));
+		}
+		let stats = verlet.stats();
+		assert_eq!(stats.updates, 200);
+		assert!(stats.rebuilds > 1 && stats.rebuilds < 50, "{} rebuilds", stats.rebuilds);
+	}
+}
@@ -0,0 +1,362 @@
+/// Pair potentials
+/// ===============
+///
+/// Interactions between pairs of particles that depend only on the
+/// distance `r` between them. Each potential gives its energy and the
+/// magnitude of its force, -dU/dr, positive when the particles push
+/// apart. `PairPotential::force_on` turns that into the force vector
+/// on a particle from the displacement to the other one.
+///
+/// Any potential can be cut off at a distance with `cut`, and
+/// shifted so the energy (`shifted`) or the energy and the force
+/// (`force_shifted`) go to zero at the cutoff.
+use std::f64;
+
+use ::simple_vec::Vec2;
+
+/// An interaction between two particles a distance `r` apart.
+pub trait PairPotential {
+	/// The potential energy U(r).
+	fn energy(&self, r: f64) -> f64;
+
+	/// The magnitude of the force -dU/dr, positive when repulsive.
+	fn force(&self, r: f64) -> f64;
+
+	/// The distance beyond which the potential is zero, if any.
+	fn cutoff(&self) -> Option<f64> {
+		None
+	}
+
+	/// The force on particle `i` from particle `j`, where
+	/// `rij = r_i - r_j`.
+	fn force_on(&self, rij: Vec2) -> Vec2 {
+		let r = rij.norm();
+		rij*(self.force(r)/r)
+	}
+
+	/// The energy of the pair with displacement `rij`.
+	fn energy_of(&self, rij: Vec2) -> f64 {
+		self.energy(rij.norm())
+	}
+
+	/// This potential, zero beyond `cutoff` but otherwise unchanged.
+	fn cut(self, cutoff: f64) -> Cut<Self> where Self: Sized {
+		Cut::new(self, cutoff, Shift::None)
+	}
+
+	/// This potential, cut off at `cutoff` and shifted so the energy
+	/// is continuous there.
+	fn shifted(self, cutoff: f64) -> Cut<Self> where Self: Sized {
+		Cut::new(self, cutoff, Shift::Energy)
+	}
+
+	/// This potential, cut off at `cutoff` and shifted so both the
+	/// energy and the force are continuous there.
+	fn force_shifted(self, cutoff: f64) -> Cut<Self> where Self: Sized {
+		Cut::new(self, cutoff, Shift::Force)
+	}
+}
+
+/// How a cut off potential is shifted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shift {
+	/// Not at all, the energy jumps at the cutoff
+	None,
+	/// The energy is shifted to be zero at the cutoff
+	Energy,
+	/// The force is shifted to be zero at the cutoff, and the energy
+	/// to match
+	Force,
+}
+
+/// A potential cut off at a distance, see `PairPotential::cut`.
+#[derive(Debug, Clone, Copy)]
+pub struct Cut<P> {
+	pub potential: P,
+	cutoff: f64,
+	shift: Shift,
+	/// Energy and force of the potential at the cutoff
+	energy_cut: f64,
+	force_cut: f64,
+}
+
+impl<P: PairPotential> Cut<P> {
+	pub fn new(potential: P, cutoff: f64, shift: Shift) -> Cut<P> {
+		assert!(cutoff > 0_f64, "cutoff must be positive");
+		let energy_cut = potential.energy(cutoff);
+		let force_cut = potential.force(cutoff);
+		Cut{potential, cutoff, shift, energy_cut, force_cut}
+	}
+
+	pub fn shift(&self) -> Shift {
+		self.shift
+	}
+}
+
+impl<P: PairPotential> PairPotential for Cut<P> {
+	fn energy(&self, r: f64) -> f64 {
+		if r >= self.cutoff {
+			return 0_f64;
+		}
+		let u = self.potential.energy(r);
+		match self.shift {
+			Shift::None => u,
+			Shift::Energy => u - self.energy_cut,
+			Shift::Force => u - self.energy_cut + (r - self.cutoff)*self.force_cut,
+		}
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		if r >= self.cutoff {
+			return 0_f64;
+		}
+		let f = self.potential.force(r);
+		match self.shift {
+			Shift::None | Shift::Energy => f,
+			Shift::Force => f - self.force_cut,
+		}
+	}
+
+	fn cutoff(&self) -> Option<f64> {
+		Some(self.cutoff)
+	}
+}
+
+/// The Lennard-Jones potential, U = 4ε((σ/r)¹² - (σ/r)⁶), with its
+/// minimum of -ε at r = 2^(1/6) σ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LennardJones {
+	pub epsilon: f64,
+	pub sigma: f64,
+}
+
+impl PairPotential for LennardJones {
+	fn energy(&self, r: f64) -> f64 {
+		let s6 = (self.sigma/r).powi(6);
+		4_f64*self.epsilon*(s6*s6 - s6)
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		let s6 = (self.sigma/r).powi(6);
+		24_f64*self.epsilon*(2_f64*s6*s6 - s6)/r
+	}
+}
+
+/// The Weeks-Chandler-Andersen potential: the repulsive part of
+/// Lennard-Jones, cut off at its minimum r = 2^(1/6) σ and shifted up
+/// by ε so it goes smoothly to zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wca {
+	pub epsilon: f64,
+	pub sigma: f64,
+}
+
+impl Wca {
+	fn lj(&self) -> LennardJones {
+		LennardJones{epsilon: self.epsilon, sigma: self.sigma}
+	}
+
+	fn range(&self) -> f64 {
+		2_f64.powf(1_f64/6_f64)*self.sigma
+	}
+}
+
+impl PairPotential for Wca {
+	fn energy(&self, r: f64) -> f64 {
+		if r >= self.range() {
+			0_f64
+		} else {
+			self.lj().energy(r) + self.epsilon
+		}
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		if r >= self.range() {
+			0_f64
+		} else {
+			self.lj().force(r)
+		}
+	}
+
+	fn cutoff(&self) -> Option<f64> {
+		Some(self.range())
+	}
+}
+
+/// The purely repulsive soft-sphere potential, U = ε(σ/r)ⁿ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftSphere {
+	pub epsilon: f64,
+	pub sigma: f64,
+	pub n: i32,
+}
+
+impl PairPotential for SoftSphere {
+	fn energy(&self, r: f64) -> f64 {
+		self.epsilon*(self.sigma/r).powi(self.n)
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		self.n as f64*self.epsilon*(self.sigma/r).powi(self.n)/r
+	}
+}
+
+/// The Morse potential, U = D(1 - exp(-a(r - r0)))², with a well of
+/// depth `d` at `r0`, measured from the energy of separated particles,
+/// and width about 1/a.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Morse {
+	pub d: f64,
+	pub a: f64,
+	pub r0: f64,
+}
+
+impl PairPotential for Morse {
+	fn energy(&self, r: f64) -> f64 {
+		let e = 1_f64 - (-self.a*(r - self.r0)).exp();
+		self.d*e*e
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		let x = (-self.a*(r - self.r0)).exp();
+		-2_f64*self.d*self.a*x*(1_f64 - x)
+	}
+}
+
+/// The Coulomb potential, U = k/r, where `k` is the product of the
+/// charges times the Coulomb constant: positive for like charges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coulomb {
+	pub k: f64,
+}
+
+impl PairPotential for Coulomb {
+	fn energy(&self, r: f64) -> f64 {
+		self.k/r
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		self.k/(r*r)
+	}
+}
+
+/// A harmonic bond, U = k(r - r0)²/2.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Harmonic {
+	pub k: f64,
+	pub r0: f64,
+}
+
+impl PairPotential for Harmonic {
+	fn energy(&self, r: f64) -> f64 {
+		0.5*self.k*(r - self.r0).powi(2)
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		-self.k*(r - self.r0)
+	}
+}
+
+/// A finitely extensible nonlinear elastic (FENE) bond,
+/// U = -(k R²/2) ln(1 - (r/R)²), which can't stretch past `r_max`.
+/// Beyond that the energy and the pull are infinite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fene {
+	pub k: f64,
+	pub r_max: f64,
+}
+
+impl PairPotential for Fene {
+	fn energy(&self, r: f64) -> f64 {
+		let x = r/self.r_max;
+		if x >= 1_f64 {
+			f64::INFINITY
+		} else {
+			-0.5*self.k*self.r_max*self.r_max*(1_f64 - x*x).ln()
+		}
+	}
+
+	fn force(&self, r: f64) -> f64 {
+		let x = r/self.r_max;
+		if x >= 1_f64 {
+			f64::NEG_INFINITY
+		} else {
+			-self.k*r/(1_f64 - x*x)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The force matches the slope of the energy
+	fn check_force<P: PairPotential>(potential: &P, rs: &[f64]) {
+		let h = 1e-6;
+		for &r in rs.iter() {
+			let slope = (potential.energy(r + h) - potential.energy(r - h))/(2_f64*h);
+			let force = potential.force(r);
+			assert!((force + slope).abs() < 1e-5*(1_f64 + force.abs()),
+				"force {} vs -dU/dr {} at r = {}", force, -slope, r);
+		}
+	}
+
+	#[test]
+	fn test_forces() {
+		let rs = [0.9, 1.0, 1.1, 1.5, 2.0];
+		check_force(&LennardJones{epsilon: 1.0, sigma: 1.0}, &rs);
+		check_force(&Wca{epsilon: 1.0, sigma: 1.0}, &rs);
+		check_force(&SoftSphere{epsilon: 1.0, sigma: 1.0, n: 12}, &rs);
+		check_force(&Morse{d: 2.0, a: 1.5, r0: 1.2}, &rs);
+		check_force(&Coulomb{k: -3.0}, &rs);
+		check_force(&Harmonic{k: 5.0, r0: 1.2}, &rs);
+		check_force(&Fene{k: 30.0, r_max: 2.5}, &rs);
+		check_force(&LennardJones{epsilon: 1.0, sigma: 1.0}.force_shifted(2.5), &rs);
+	}
+
+	#[test]
+	fn test_minima() {
+		let lj = LennardJones{epsilon: 2.0, sigma: 1.5};
+		let r_min = 2_f64.powf(1_f64/6_f64)*1.5;
+		assert!(lj.force(r_min).abs() < 1e-12);
+		assert!((lj.energy(r_min) + 2.0).abs() < 1e-12);
+		let morse = Morse{d: 2.0, a: 1.5, r0: 1.2};
+		assert_eq!(morse.energy(1.2), 0.0);
+		assert_eq!(morse.force(1.2), 0.0);
+		assert!((morse.energy(100.0) - 2.0).abs() < 1e-12);
+		let fene = Fene{k: 30.0, r_max: 1.5};
+		assert_eq!(fene.energy(1.5), f64::INFINITY);
+	}
+
+	/// WCA and the shifted potentials go smoothly to zero at the cutoff
+	#[test]
+	fn test_cutoffs() {
+		let wca = Wca{epsilon: 1.0, sigma: 1.0};
+		let rc = wca.cutoff().unwrap();
+		assert!(wca.energy(rc - 1e-9).abs() < 1e-6);
+		assert_eq!(wca.energy(rc), 0.0);
+
+		let lj = LennardJones{epsilon: 1.0, sigma: 1.0};
+		let cut = lj.cut(2.5);
+		assert_eq!(cut.energy(2.6), 0.0);
+		assert_eq!(cut.energy(1.5), lj.energy(1.5));
+		let shifted = lj.shifted(2.5);
+		assert!(shifted.energy(2.5 - 1e-9).abs() < 1e-8);
+		assert_eq!(shifted.force(1.5), lj.force(1.5));
+		let force_shifted = lj.force_shifted(2.5);
+		assert!(force_shifted.force(2.5 - 1e-9).abs() < 1e-8);
+		assert!(force_shifted.energy(2.5 - 1e-9).abs() < 1e-8);
+		assert_eq!(force_shifted.cutoff(), Some(2.5));
+	}
+
+	/// Repulsive forces push particle i away from particle j
+	#[test]
+	fn test_force_on() {
+		let rij = Vec2{x: 0.6, y: 0.8};
+		let f = Coulomb{k: 2.0}.force_on(rij);
+		assert!((f - Vec2{x: 1.2, y: 1.6}).norm() < 1e-12);
+		let f = Harmonic{k: 2.0, r0: 0.5}.force_on(rij);
+		assert!((f - Vec2{x: -0.6, y: -0.8}).norm() < 1e-12);
+		assert_eq!(Harmonic{k: 2.0, r0: 0.5}.energy_of(rij), 0.25);
+	}
+}
@@ -0,0 +1,736 @@
+/// Rigid bodies
+/// ============
+///
+/// Two dimensional rigid bodies, each a `Shape` (a circle or a convex
+/// polygon) with a position, angle, velocity and angular velocity.
+/// Shapes are described in the body's own frame, centered on its
+/// center of mass; the mass and moment of inertia follow from the
+/// shape and a density.
+///
+/// `collide` finds the contact between two bodies with the separating
+/// axis test, and a `World` steps a set of bodies under gravity,
+/// resolving contacts with sequential impulses: each contact gets an
+/// impulse along its normal to stop the bodies moving into each other
+/// (or bounce them apart, with restitution), and one along the surface
+/// for Coulomb friction, repeated a few times so that contacts sharing
+/// a body settle together.
+///
+/// Bodies with infinite mass (see `Body::fixed`) don't move, and make
+/// walls and floors.
+use std::cmp::Ordering;
+
+use ::diagnostics::Conserved;
+use ::simple_color::Color;
+use ::simple_vec::Vec2;
+use ::simple_vec::Transform2D as Transform;
+use ::gfx::Graphic;
+
+/// The shape of a body, in its own frame with the center of mass at
+/// the origin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+	Circle{radius: f64},
+	/// A convex polygon with its vertices counterclockwise
+	Polygon{vertices: Vec<Vec2>},
+}
+
+impl Shape {
+	pub fn circle(radius: f64) -> Shape {
+		assert!(radius > 0_f64, "radius must be positive");
+		Shape::Circle{radius}
+	}
+
+	/// A convex polygon with the given vertices, in either order. It
+	/// is moved so that its centroid is at the origin. Repeated
+	/// vertices are dropped; the polygon must still have at least
+	/// three, enclose some area, and be convex.
+	pub fn polygon(points: &[Vec2]) -> Shape {
+		let mut vertices : Vec<Vec2> = Vec::with_capacity(points.len());
+		for &p in points.iter() {
+			if vertices.last() != Some(&p) {
+				vertices.push(p);
+			}
+		}
+		while vertices.len() > 1 && vertices.first() == vertices.last() {
+			vertices.pop();
+		}
+		assert!(vertices.len() >= 3, "a polygon needs at least three distinct vertices");
+		let (area, centroid, _) = polygon_moments(&vertices);
+		assert!(area != 0_f64 && area.is_finite(), "a polygon needs a non-zero area");
+		if area < 0_f64 {
+			vertices.reverse();
+		}
+		let n = vertices.len();
+		let convex = (0..n).all( |i| {
+			let (a, b, c) = (vertices[i], vertices[(i + 1) % n], vertices[(i + 2) % n]);
+			(b - a).cross(c - b) >= 0_f64
+		});
+		assert!(convex, "a polygon must be convex");
+		for v in vertices.iter_mut() {
+			*v = *v - centroid;
+		}
+		Shape::Polygon{vertices}
+	}
+
+	/// A `width` by `height` rectangle.
+	pub fn rectangle(width: f64, height: f64) -> Shape {
+		let (w, h) = (0.5*width, 0.5*height);
+		Shape::polygon(&[
+			Vec2{x: -w, y: -h},
+			Vec2{x: w, y: -h},
+			Vec2{x: w, y: h},
+			Vec2{x: -w, y: h},
+		])
+	}
+
+	pub fn area(&self) -> f64 {
+		match *self {
+			Shape::Circle{radius} => ::std::f64::consts::PI*radius*radius,
+			Shape::Polygon{ref vertices} => polygon_moments(vertices).0,
+		}
+	}
+
+	/// The second moment of area about the origin, the moment of
+	/// inertia for unit density.
+	pub fn second_moment(&self) -> f64 {
+		match *self {
+			Shape::Circle{radius} => 0.5*self.area()*radius*radius,
+			Shape::Polygon{ref vertices} => polygon_moments(vertices).2,
+		}
+	}
+}
+
+/// The signed area, centroid and second moment of area about the
+/// origin of a polygon.
+fn polygon_moments(vertices: &[Vec2]) -> (f64, Vec2, f64) {
+	let mut area = 0_f64;
+	let mut first = Vec2::zero();
+	let mut second = 0_f64;
+	for (i, &a) in vertices.iter().enumerate() {
+		let b = vertices[(i + 1) % vertices.len()];
+		let cross = a.cross(b);
+		area += 0.5*cross;
+		first = first + (cross/6_f64)*(a + b);
+		second += cross/12_f64*(a.dot(a) + a.dot(b) + b.dot(b));
+	}
+	(area, first/area, second)
+}
+
+/// A rigid body.
+#[derive(Debug, Clone)]
+pub struct Body {
+	/// Position of the center of mass
+	pub r: Vec2,
+	pub angle: f64,
+	pub v: Vec2,
+	/// Angular velocity, counterclockwise
+	pub omega: f64,
+	/// Coefficient of restitution, 1 for elastic collisions and 0 for
+	/// ones that don't bounce at all
+	pub restitution: f64,
+	/// Coefficient of friction
+	pub friction: f64,
+	shape: Shape,
+	inv_mass: f64,
+	inv_inertia: f64,
+}
+
+impl Body {
+	/// A body at rest at the origin, with the mass and moment of
+	/// inertia of `shape` at a uniform `density`.
+	pub fn new(shape: Shape, density: f64) -> Body {
+		assert!(density > 0_f64, "density must be positive");
+		let mass = density*shape.area();
+		let inertia = density*shape.second_moment();
+		Body{
+			r: Vec2::zero(),
+			angle: 0_f64,
+			v: Vec2::zero(),
+			omega: 0_f64,
+			restitution: 0.5,
+			friction: 0.3,
+			shape,
+			inv_mass: 1_f64/mass,
+			inv_inertia: 1_f64/inertia,
+		}
+	}
+
+	/// A body with infinite mass, that nothing can move.
+	pub fn fixed(shape: Shape) -> Body {
+		Body{inv_mass: 0_f64, inv_inertia: 0_f64, ..Body::new(shape, 1_f64)}
+	}
+
+	/// Place the body at `r`, returning it.
+	pub fn at(self, r: Vec2) -> Body {
+		Body{r, ..self}
+	}
+
+	/// Turn the body to `angle`, returning it.
+	pub fn turned(self, angle: f64) -> Body {
+		Body{angle, ..self}
+	}
+
+	pub fn shape(&self) -> &Shape {
+		&self.shape
+	}
+
+	/// The mass, infinite for fixed bodies.
+	pub fn mass(&self) -> f64 {
+		1_f64/self.inv_mass
+	}
+
+	/// The moment of inertia about the center of mass, infinite for
+	/// fixed bodies.
+	pub fn inertia(&self) -> f64 {
+		1_f64/self.inv_inertia
+	}
+
+	pub fn is_fixed(&self) -> bool {
+		self.inv_mass == 0_f64
+	}
+
+	/// The transform from the body's frame to the world.
+	pub fn transform(&self) -> Transform {
+		Transform::rotate(self.angle, Vec2::zero())
+			.combine_left(Transform::translate(self.r.x, self.r.y))
+	}
+
+	/// A point in the body's frame, in the world.
+	pub fn to_world(&self, p: Vec2) -> Vec2 {
+		p.transform(self.transform())
+	}
+
+	/// The vertices of a polygon in the world, or none for a circle.
+	pub fn vertices(&self) -> Vec<Vec2> {
+		match self.shape {
+			Shape::Circle{..} => Vec::new(),
+			Shape::Polygon{ref vertices} => {
+				let t = self.transform();
+				vertices.iter().map( |v| v.transform(t)).collect()
+			},
+		}
+	}
+
+	/// The velocity of the point of the body at `p` in the world.
+	pub fn velocity_at(&self, p: Vec2) -> Vec2 {
+		let d = p - self.r;
+		self.v + self.omega*Vec2{x: -d.y, y: d.x}
+	}
+
+	/// Give the body an impulse `j` at the point `p` in the world.
+	pub fn apply_impulse(&mut self, j: Vec2, p: Vec2) {
+		self.v = self.v + self.inv_mass*j;
+		self.omega += self.inv_inertia*(p - self.r).cross(j);
+	}
+
+	/// The kinetic energy, of translation and rotation. Zero for fixed
+	/// bodies.
+	pub fn kinetic_energy(&self) -> f64 {
+		if self.is_fixed() {
+			0_f64
+		} else {
+			0.5*self.mass()*self.v.norm_squared() + 0.5*self.inertia()*self.omega*self.omega
+		}
+	}
+
+	/// The angular momentum about the origin. Zero for fixed bodies.
+	pub fn angular_momentum(&self) -> f64 {
+		if self.is_fixed() {
+			0_f64
+		} else {
+			self.mass()*self.r.cross(self.v) + self.inertia()*self.omega
+		}
+	}
+
+	/// The body drawn in `color` where it is now. Circles get a line
+	/// from the center, so they can be seen to turn.
+	pub fn graphic(&self, color: Color) -> Graphic {
+		let mut graphic = match self.shape {
+			Shape::Circle{radius} => {
+				let mut disk = Graphic::circle(Vec2::zero(), radius);
+				disk.set_color(color);
+				let spoke = Graphic::line(&[Vec2::zero(), Vec2{x: radius, y: 0_f64}]);
+				Graphic::collection(&[disk, spoke])
+			},
+			Shape::Polygon{ref vertices} => {
+				let mut polygon = Graphic::polygon(vertices);
+				polygon.set_color(color);
+				polygon
+			},
+		};
+		graphic.transform(self.transform());
+		graphic
+	}
+}
+
+/// A point of contact and how deep the bodies overlap there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactPoint {
+	pub point: Vec2,
+	pub depth: f64,
+}
+
+/// The contact between two overlapping bodies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+	/// The unit normal, pointing from the first body to the second
+	pub normal: Vec2,
+	/// One or two points of contact
+	pub points: Vec<ContactPoint>,
+}
+
+/// The outward unit normal of the edge from `a` to `b` of a
+/// counterclockwise polygon.
+fn edge_normal(a: Vec2, b: Vec2) -> Vec2 {
+	let e = b - a;
+	Vec2{x: e.y, y: -e.x}/e.norm()
+}
+
+/// The edge of polygon `a` along which polygon `b` is least deep,
+/// and how far `b` is outside it (negative when they overlap).
+fn max_separation(a: &[Vec2], b: &[Vec2]) -> (usize, f64) {
+	let mut best = (0, f64::NEG_INFINITY);
+	for i in 0..a.len() {
+		let n = edge_normal(a[i], a[(i + 1) % a.len()]);
+		let separation = b.iter().map( |&v| (v - a[i]).dot(n)).fold(f64::INFINITY, f64::min);
+		if separation > best.1 {
+			best = (i, separation);
+		}
+	}
+	best
+}
+
+/// The part of the segment `p` on the side of `normal` facing away
+/// from it with an `offset`, keeping points with p·normal <= offset.
+fn clip(p: [Vec2; 2], normal: Vec2, offset: f64) -> Option<[Vec2; 2]> {
+	let d0 = p[0].dot(normal) - offset;
+	let d1 = p[1].dot(normal) - offset;
+	if d0 > 0_f64 && d1 > 0_f64 {
+		return None;
+	}
+	let cut = |inside: Vec2, outside: Vec2, di: f64, d_out: f64| inside + (di/(di - d_out))*(outside - inside);
+	let mut q = p;
+	if d0 > 0_f64 {
+		q[0] = cut(p[1], p[0], d1, d0);
+	} else if d1 > 0_f64 {
+		q[1] = cut(p[0], p[1], d0, d1);
+	}
+	Some(q)
+}
+
+/// Contact between two convex polygons, by the separating axis test
+/// and clipping the most anti-parallel edge of one against the edge
+/// of least penetration of the other.
+fn polygon_polygon(a: &[Vec2], b: &[Vec2]) -> Option<Contact> {
+	let (edge_a, sep_a) = max_separation(a, b);
+	if sep_a > 0_f64 {
+		return None;
+	}
+	let (edge_b, sep_b) = max_separation(b, a);
+	if sep_b > 0_f64 {
+		return None;
+	}
+	// Prefer `a` as the reference polygon unless `b` is clearly better
+	let (reference, incident, edge, flip) = if sep_b > sep_a + 1.0e-9 {
+		(b, a, edge_b, true)
+	} else {
+		(a, b, edge_a, false)
+	};
+	let r0 = reference[edge];
+	let r1 = reference[(edge + 1) % reference.len()];
+	let normal = edge_normal(r0, r1);
+	// The incident edge faces most against the reference normal
+	let k = (0..incident.len())
+		.min_by( |&i, &j| {
+			let ni = edge_normal(incident[i], incident[(i + 1) % incident.len()]).dot(normal);
+			let nj = edge_normal(incident[j], incident[(j + 1) % incident.len()]).dot(normal);
+			ni.partial_cmp(&nj).unwrap_or(Ordering::Equal)
+		})
+		.unwrap();
+	let segment = [incident[k], incident[(k + 1) % incident.len()]];
+	let tangent = (r1 - r0)/(r1 - r0).norm();
+	let segment = clip(segment, -tangent, -r0.dot(tangent))
+		.and_then( |s| clip(s, tangent, r1.dot(tangent)))?;
+	let points : Vec<ContactPoint> = segment.iter()
+		.map( |&p| ContactPoint{point: p, depth: -(p - r0).dot(normal)})
+		.filter( |c| c.depth >= 0_f64)
+		.collect();
+	if points.is_empty() {
+		return None;
+	}
+	Some(Contact{normal: if flip { -normal } else { normal }, points})
+}
+
+/// Contact between a convex polygon and a circle, with the normal
+/// from the polygon to the circle.
+fn polygon_circle(vertices: &[Vec2], center: Vec2, radius: f64) -> Option<Contact> {
+	let (edge, separation) = max_separation(vertices, &[center]);
+	if separation > radius {
+		return None;
+	}
+	if separation <= 0_f64 {
+		// The center is inside, push out through the nearest edge
+		let normal = edge_normal(vertices[edge], vertices[(edge + 1) % vertices.len()]);
+		let point = ContactPoint{point: center - radius*normal, depth: radius - separation};
+		return Some(Contact{normal, points: vec!(point)});
+	}
+	// Otherwise the closest point on the outline
+	let mut closest = vertices[0];
+	for i in 0..vertices.len() {
+		let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+		let e = b - a;
+		let s = ((center - a).dot(e)/e.norm_squared()).clamp(0_f64, 1_f64);
+		let q = a + s*e;
+		if (center - q).norm_squared() < (center - closest).norm_squared() {
+			closest = q;
+		}
+	}
+	let d = center - closest;
+	let distance = d.norm();
+	if distance > radius {
+		return None;
+	}
+	Some(Contact{normal: d/distance, points: vec!(ContactPoint{point: closest, depth: radius - distance})})
+}
+
+/// The contact between two bodies, if they overlap.
+pub fn collide(a: &Body, b: &Body) -> Option<Contact> {
+	match (&a.shape, &b.shape) {
+		(&Shape::Circle{radius: ra}, &Shape::Circle{radius: rb}) => {
+			let d = b.r - a.r;
+			let distance = d.norm();
+			if distance > ra + rb {
+				return None;
+			}
+			let normal = if distance > 0_f64 { d/distance } else { Vec2{x: 1_f64, y: 0_f64} };
+			let point = a.r + (ra - 0.5*(ra + rb - distance))*normal;
+			Some(Contact{normal, points: vec!(ContactPoint{point, depth: ra + rb - distance})})
+		},
+		(&Shape::Polygon{..}, &Shape::Circle{radius}) => polygon_circle(&a.vertices(), b.r, radius),
+		(&Shape::Circle{radius}, &Shape::Polygon{..}) => {
+			polygon_circle(&b.vertices(), a.r, radius)
+				.map( |c| Contact{normal: -c.normal, ..c})
+		},
+		(&Shape::Polygon{..}, &Shape::Polygon{..}) => polygon_polygon(&a.vertices(), &b.vertices()),
+	}
+}
+
+/// A contact point being resolved, with the accumulated impulses.
+#[derive(Debug, Clone, Copy)]
+struct Constraint {
+	a: usize,
+	b: usize,
+	normal: Vec2,
+	point: Vec2,
+	depth: f64,
+	/// Effective masses along the normal and the tangent
+	normal_mass: f64,
+	tangent_mass: f64,
+	/// Target normal speed, from restitution
+	bounce: f64,
+	friction: f64,
+	normal_impulse: f64,
+	tangent_impulse: f64,
+}
+
+/// A set of rigid bodies under gravity.
+#[derive(Debug, Clone)]
+pub struct World {
+	pub bodies: Vec<Body>,
+	pub gravity: Vec2,
+	/// Passes over the contacts each step
+	pub iterations: usize,
+	/// Overlap allowed without correction, to keep resting contacts
+	/// steady
+	pub slop: f64,
+}
+
+impl World {
+	pub fn new(gravity: Vec2) -> World {
+		World{bodies: Vec::new(), gravity, iterations: 10, slop: 0.005}
+	}
+
+	/// Add a body, returning its index.
+	pub fn add(&mut self, body: Body) -> usize {
+		self.bodies.push(body);
+		self.bodies.len() - 1
+	}
+
+	/// The contacts between every pair of bodies, as indices and the
+	/// contact.
+	pub fn contacts(&self) -> Vec<(usize, usize, Contact)> {
+		let mut contacts = Vec::new();
+		for i in 0..self.bodies.len() {
+			for j in i+1..self.bodies.len() {
+				let (a, b) = (&self.bodies[i], &self.bodies[j]);
+				if a.is_fixed() && b.is_fixed() {
+					continue;
+				}
+				if let Some(contact) = collide(a, b) {
+					contacts.push((i, j, contact));
+				}
+			}
+		}
+		contacts
+	}
+
+	/// The total kinetic energy.
+	pub fn kinetic_energy(&self) -> f64 {
+		self.bodies.iter().map( |b| b.kinetic_energy()).sum()
+	}
+
+	/// Advance the bodies by a time `dt`.
+	pub fn step(&mut self, dt: f64) {
+		for body in self.bodies.iter_mut().filter( |b| !b.is_fixed()) {
+			body.v = body.v + dt*self.gravity;
+		}
+		// Bounces slower than gravity gives in a couple of steps are
+		// treated as resting contact, so bodies can come to rest
+		let resting = 2_f64*dt*self.gravity.norm();
+		let mut constraints = Vec::new();
+		for (i, j, contact) in self.contacts() {
+			let (a, b) = (&self.bodies[i], &self.bodies[j]);
+			let restitution = a.restitution.min(b.restitution);
+			let friction = (a.friction*b.friction).sqrt();
+			let n = contact.normal;
+			let t = Vec2{x: -n.y, y: n.x};
+			for c in contact.points.iter() {
+				let (ra, rb) = (c.point - a.r, c.point - b.r);
+				let effective = |d: Vec2| {
+					let k = a.inv_mass + b.inv_mass
+						+ a.inv_inertia*ra.cross(d).powi(2) + b.inv_inertia*rb.cross(d).powi(2);
+					if k > 0_f64 { 1_f64/k } else { 0_f64 }
+				};
+				let vn = (b.velocity_at(c.point) - a.velocity_at(c.point)).dot(n);
+				constraints.push(Constraint{
+					a: i,
+					b: j,
+					normal: n,
+					point: c.point,
+					depth: c.depth,
+					normal_mass: effective(n),
+					tangent_mass: effective(t),
+					bounce: if vn < -resting { -restitution*vn } else { 0_f64 },
+					friction,
+					normal_impulse: 0_f64,
+					tangent_impulse: 0_f64,
+				});
+			}
+		}
+		for _ in 0..self.iterations {
+			for c in constraints.iter_mut() {
+				self.solve(c);
+			}
+		}
+		for body in self.bodies.iter_mut() {
+			body.r = body.r + dt*body.v;
+			body.angle += dt*body.omega;
+		}
+		// Push overlapping bodies apart, without changing velocities
+		for c in constraints.iter() {
+			let (ia, ib) = (self.bodies[c.a].inv_mass, self.bodies[c.b].inv_mass);
+			if ia + ib == 0_f64 {
+				continue;
+			}
+			let points = constraints.iter().filter( |o| o.a == c.a && o.b == c.b).count() as f64;
+			let push = 0.4*(c.depth - self.slop).max(0_f64)/(ia + ib)/points*c.normal;
+			self.bodies[c.a].r = self.bodies[c.a].r - ia*push;
+			self.bodies[c.b].r = self.bodies[c.b].r + ib*push;
+		}
+	}
+
+	/// One pass of sequential impulses at a contact point.
+	fn solve(&mut self, c: &mut Constraint) {
+		let n = c.normal;
+		let t = Vec2{x: -n.y, y: n.x};
+		let (a, b, p) = (c.a, c.b, c.point);
+		let relative = |bodies: &[Body]| bodies[b].velocity_at(p) - bodies[a].velocity_at(p);
+
+		// The bodies can push but not pull, so the total normal impulse
+		// stays positive
+		let vn = relative(&self.bodies).dot(n);
+		let total = (c.normal_impulse + c.normal_mass*(c.bounce - vn)).max(0_f64);
+		let dj = total - c.normal_impulse;
+		c.normal_impulse = total;
+		self.exchange(a, b, dj*n, p);
+
+		// Friction is at most the coefficient times the normal impulse
+		let vt = relative(&self.bodies).dot(t);
+		let limit = c.friction*c.normal_impulse;
+		let total = (c.tangent_impulse - c.tangent_mass*vt).clamp(-limit, limit);
+		let dj = total - c.tangent_impulse;
+		c.tangent_impulse = total;
+		self.exchange(a, b, dj*t, p);
+	}
+
+	/// Give body `b` the impulse `j` at `p`, and body `a` the opposite.
+	fn exchange(&mut self, a: usize, b: usize, j: Vec2, p: Vec2) {
+		self.bodies[a].apply_impulse(-j, p);
+		self.bodies[b].apply_impulse(j, p);
+	}
+}
+
+impl Conserved for World {
+	fn kinetic_energy(&self) -> f64 {
+		World::kinetic_energy(self)
+	}
+
+	/// The gravitational potential energy of the moving bodies.
+	fn potential_energy(&self) -> f64 {
+		self.bodies.iter().filter( |b| !b.is_fixed()).map( |b| -b.mass()*self.gravity.dot(b.r)).sum()
+	}
+
+	fn momentum(&self) -> Vec2 {
+		self.bodies.iter().filter( |b| !b.is_fixed()).fold(Vec2::zero(), |p, b| p + b.mass()*b.v)
+	}
+
+	fn angular_momentum(&self) -> f64 {
+		self.bodies.iter().map( |b| b.angular_momentum()).sum()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn close(a: f64, b: f64) -> bool {
+		(a - b).abs() < 1e-9*(1_f64 + a.abs().max(b.abs()))
+	}
+
+	#[test]
+	fn test_mass_properties() {
+		let body = Body::new(Shape::rectangle(2.0, 4.0), 3.0);
+		assert!(close(body.mass(), 24.0));
+		assert!(close(body.inertia(), 24.0*(4.0 + 16.0)/12.0));
+		let body = Body::new(Shape::circle(2.0), 1.0);
+		assert!(close(body.inertia(), 0.5*body.mass()*4.0));
+		// A triangle, given clockwise, is recentered and made counterclockwise
+		let shape = Shape::polygon(&[Vec2{x: 0.0, y: 0.0}, Vec2{x: 0.0, y: 3.0}, Vec2{x: 3.0, y: 0.0}]);
+		assert!(close(shape.area(), 4.5));
+		if let Shape::Polygon{vertices} = shape {
+			assert_eq!(vertices, vec!(Vec2{x: 2.0, y: -1.0}, Vec2{x: -1.0, y: 2.0}, Vec2{x: -1.0, y: -1.0}));
+		}
+		assert!(Body::fixed(Shape::circle(1.0)).is_fixed());
+		// Repeated vertices are dropped
+		let square = [
+			Vec2{x: 0.0, y: 0.0},
+			Vec2{x: 1.0, y: 0.0},
+			Vec2{x: 1.0, y: 0.0},
+			Vec2{x: 1.0, y: 1.0},
+			Vec2{x: 0.0, y: 1.0},
+			Vec2{x: 0.0, y: 0.0},
+		];
+		if let Shape::Polygon{vertices} = Shape::polygon(&square) {
+			assert_eq!(vertices.len(), 4);
+		}
+	}
+
+	/// Polygons that would give edges without a direction are refused
+	#[test]
+	fn test_degenerate_polygons() {
+		use std::panic::catch_unwind;
+		let point = Vec2{x: 1.0, y: 1.0};
+		assert!(catch_unwind( || Shape::polygon(&[point, point, point, Vec2::zero()])).is_err());
+		assert!(catch_unwind( || Shape::polygon(&[Vec2::zero(), point, 2.0*point])).is_err());
+		let dart = [Vec2{x: 0.0, y: 0.0}, Vec2{x: 2.0, y: 1.0}, Vec2{x: 0.0, y: 2.0}, Vec2{x: 1.0, y: 1.0}];
+		assert!(catch_unwind( || Shape::polygon(&dart)).is_err());
+	}
+
+	#[test]
+	fn test_collide() {
+		let a = Body::new(Shape::circle(1.0), 1.0);
+		let b = Body::new(Shape::circle(1.0), 1.0).at(Vec2{x: 1.5, y: 0.0});
+		let contact = collide(&a, &b).unwrap();
+		assert_eq!(contact.normal, Vec2{x: 1.0, y: 0.0});
+		assert!(close(contact.points[0].depth, 0.5));
+		assert!(collide(&a, &b.clone().at(Vec2{x: 2.5, y: 0.0})).is_none());
+
+		// A box resting on a wider box touches at two corners
+		let floor = Body::fixed(Shape::rectangle(10.0, 1.0));
+		let block = Body::new(Shape::rectangle(1.0, 1.0), 1.0).at(Vec2{x: 0.0, y: 0.9});
+		let contact = collide(&floor, &block).unwrap();
+		assert!((contact.normal - Vec2{x: 0.0, y: 1.0}).norm() < 1e-12);
+		assert_eq!(contact.points.len(), 2);
+		for c in contact.points.iter() {
+			assert!(close(c.depth, 0.1));
+		}
+		// Turned onto a corner, it touches at one point
+		let block = block.turned(0.25*::std::f64::consts::PI).at(Vec2{x: 0.0, y: 1.2});
+		assert_eq!(collide(&floor, &block).unwrap().points.len(), 1);
+		// A circle on the floor, from either side
+		let ball = Body::new(Shape::circle(0.5), 1.0).at(Vec2{x: 3.0, y: 0.8});
+		let contact = collide(&ball, &floor).unwrap();
+		assert!((contact.normal - Vec2{x: 0.0, y: -1.0}).norm() < 1e-12);
+		assert!(close(contact.points[0].depth, 0.2));
+		// ...and off a corner
+		let ball = ball.at(Vec2{x: 5.3, y: 0.9});
+		let contact = collide(&floor, &ball).unwrap();
+		assert!((contact.normal - Vec2{x: 0.6, y: 0.8}).norm() < 1e-12);
+	}
+
+	/// Elastic collisions keep energy, momentum and angular momentum;
+	/// an off-center hit sets the bodies spinning
+	#[test]
+	fn test_elastic_collision() {
+		let mut world = World::new(Vec2::zero());
+		let mut a = Body::new(Shape::rectangle(1.0, 1.0), 1.0).at(Vec2{x: -2.0, y: 0.3});
+		a.v = Vec2{x: 1.0, y: 0.0};
+		a.restitution = 1.0;
+		a.friction = 0.0;
+		let mut b = Body::new(Shape::circle(0.5), 2.0).at(Vec2{x: 1.0, y: 0.0});
+		b.restitution = 1.0;
+		world.add(a);
+		world.add(b);
+		let energy = world.kinetic_energy();
+		let momentum = |w: &World| w.bodies.iter().fold(Vec2::zero(), |p, b| p + b.mass()*b.v);
+		let angular = |w: &World| w.bodies.iter().map( |b| b.angular_momentum()).sum::<f64>();
+		let (p0, l0) = (momentum(&world), angular(&world));
+		for _ in 0..3000 {
+			world.step(0.001);
+		}
+		assert!(world.bodies[1].v.x > 0.0 && world.bodies[0].omega != 0.0);
+		assert!((world.kinetic_energy() - energy).abs() < 1e-6, "energy {}", world.kinetic_energy());
+		assert!((momentum(&world) - p0).norm() < 1e-9);
+		assert!((angular(&world) - l0).abs() < 1e-6);
+	}
+
+	/// A box dropped on the floor comes to rest on it
+	#[test]
+	fn test_resting_contact() {
+		let mut world = World::new(Vec2{x: 0.0, y: -9.8});
+		world.add(Body::fixed(Shape::rectangle(10.0, 1.0)));
+		let block = world.add(Body::new(Shape::rectangle(1.0, 1.0), 1.0).at(Vec2{x: 0.0, y: 2.0}));
+		for _ in 0..2000 {
+			world.step(1.0/500.0);
+		}
+		let body = &world.bodies[block];
+		assert!(body.v.norm() < 1e-3 && body.omega.abs() < 1e-3, "still moving {:?} {}", body.v, body.omega);
+		assert!((body.r.y - 1.0).abs() < 0.01, "resting at {}", body.r.y);
+		assert!(body.angle.abs() < 1e-6);
+	}
+
+	/// Friction stops a box sliding along the floor, a frictionless one
+	/// keeps going
+	#[test]
+	fn test_friction() {
+		let slide = |friction: f64| {
+			let mut world = World::new(Vec2{x: 0.0, y: -9.8});
+			let mut floor = Body::fixed(Shape::rectangle(100.0, 1.0));
+			floor.friction = friction;
+			world.add(floor);
+			let mut block = Body::new(Shape::rectangle(1.0, 0.5), 1.0).at(Vec2{x: 0.0, y: 0.75});
+			block.friction = friction;
+			block.v = Vec2{x: 2.0, y: 0.0};
+			world.add(block);
+			for _ in 0..1000 {
+				world.step(1.0/500.0);
+			}
+			(world.bodies[1].r.x, world.bodies[1].v.x)
+		};
+		// Sliding to a stop with deceleration μ g, after v²/(2 μ g)
+		let (x, v) = slide(0.5);
+		assert!(v.abs() < 1e-6 && (x - 4.0/9.8).abs() < 0.02, "stopped at {} moving at {}", x, v);
+		let (x, v) = slide(0.0);
+		assert!((v - 2.0).abs() < 1e-6 && (x - 4.0).abs() < 0.01);
+	}
+}
@@ -0,0 +1,311 @@
+/// Stochastic differential equations
+/// =================================
+///
+/// Integrators for particles pushed around by random forces, with the
+/// noise drawn from a `NormalDist`.
+///
+/// Overdamped (Brownian) motion follows the SDE
+///
+/// dx = a(t, x) dt + sqrt(2 D(x)) dW
+///
+/// with drift `a` and diffusion coefficient `D`, described by the
+/// `Sde` trait and stepped with `EulerMaruyama` or `Milstein`. The
+/// `BrownianDynamics` wrapper builds the SDE for particles in a
+/// `ForceField` from a friction coefficient and a temperature.
+///
+/// Underdamped (inertial) Langevin dynamics, with masses and
+/// velocities, is stepped with `Baoab`.
+use ::simple_rng::NormalDist;
+use ::simple_vec::Vec2;
+
+/// A random `Vec2` with independent standard normal components,
+/// whatever the mean and standard deviation of `rng`.
+fn standard_normal(rng: &mut NormalDist) -> Vec2 {
+	Vec2{x: rng.standard(), y: rng.standard()}
+}
+
+/// An SDE for a set of particles,
+/// dx_i = a_i(t, x) dt + sqrt(2 D_i(x_i)) dW_i,
+/// with independent noise in each direction.
+pub trait Sde {
+	/// The drift `a` of every particle, written into `a`.
+	fn drift(&self, t: f64, x: &[Vec2], a: &mut [Vec2]);
+
+	/// The diffusion coefficient `D` of particle `i` at position `x`.
+	fn diffusion(&self, t: f64, i: usize, x: Vec2) -> f64;
+
+	/// The gradient of the diffusion coefficient of particle `i`, used
+	/// by `Milstein`. Defaults to zero, for noise that doesn't depend
+	/// on position.
+	fn diffusion_gradient(&self, _t: f64, _i: usize, _x: Vec2) -> Vec2 {
+		Vec2::zero()
+	}
+}
+
+/// A scheme for advancing an `Sde` by one time step.
+pub trait SdeIntegrator {
+	/// Advance the positions `x` from time `t` by a time `dt`.
+	fn step<S: Sde>(&self, sde: &S, t: f64, x: &mut [Vec2], dt: f64, rng: &mut NormalDist);
+}
+
+/// Euler-Maruyama, strong order 1/2 (order 1 for noise that doesn't
+/// depend on position).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EulerMaruyama;
+
+impl SdeIntegrator for EulerMaruyama {
+	fn step<S: Sde>(&self, sde: &S, t: f64, x: &mut [Vec2], dt: f64, rng: &mut NormalDist) {
+		let mut a = vec!(Vec2::zero(); x.len());
+		sde.drift(t, x, &mut a);
+		for (i, (x, a)) in x.iter_mut().zip(a.iter()).enumerate() {
+			let b = (2_f64*sde.diffusion(t, i, *x)).sqrt();
+			let dw = dt.sqrt()*standard_normal(rng);
+			*x = *x + dt*(*a) + b*dw;
+		}
+	}
+}
+
+/// Milstein, strong order 1. Adds the correction
+/// (1/2) b b' (dW² - dt) to Euler-Maruyama, where b b' = D' for a
+/// noise amplitude b = sqrt(2 D). Each direction only uses its own
+/// component of the gradient of `D`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Milstein;
+
+impl SdeIntegrator for Milstein {
+	fn step<S: Sde>(&self, sde: &S, t: f64, x: &mut [Vec2], dt: f64, rng: &mut NormalDist) {
+		let mut a = vec!(Vec2::zero(); x.len());
+		sde.drift(t, x, &mut a);
+		for (i, (x, a)) in x.iter_mut().zip(a.iter()).enumerate() {
+			let b = (2_f64*sde.diffusion(t, i, *x)).sqrt();
+			let grad = sde.diffusion_gradient(t, i, *x);
+			let dw = dt.sqrt()*standard_normal(rng);
+			let correction = Vec2{
+				x: 0.5*grad.x*(dw.x*dw.x - dt),
+				y: 0.5*grad.y*(dw.y*dw.y - dt),
+			};
+			*x = *x + dt*(*a) + b*dw + correction;
+		}
+	}
+}
+
+/// Conservative forces on a set of particles.
+pub trait ForceField {
+	/// The force on every particle, written into `f`.
+	fn force(&self, x: &[Vec2], f: &mut [Vec2]);
+
+	/// The mass of particle `i`. Defaults to 1.
+	fn mass(&self, _i: usize) -> f64 {
+		1_f64
+	}
+}
+
+/// Overdamped motion of particles in a force field, in contact with a
+/// heat bath: the drift is F/γ and the diffusion coefficient comes
+/// from the Einstein relation D = kT/γ.
+#[derive(Debug, Clone, Copy)]
+pub struct BrownianDynamics<'a, F: 'a> {
+	pub forces: &'a F,
+	/// Friction (drag) coefficient γ, force per unit velocity
+	pub friction: f64,
+	/// Thermal energy kT
+	pub kt: f64,
+}
+
+impl<'a, F: ForceField> Sde for BrownianDynamics<'a, F> {
+	fn drift(&self, _t: f64, x: &[Vec2], a: &mut [Vec2]) {
+		self.forces.force(x, a);
+		for a in a.iter_mut() {
+			*a = *a/self.friction;
+		}
+	}
+
+	fn diffusion(&self, _t: f64, _i: usize, _x: Vec2) -> f64 {
+		self.kt/self.friction
+	}
+}
+
+/// The BAOAB splitting for underdamped Langevin dynamics,
+/// m dv = F dt - m γ v dt + sqrt(2 m γ kT) dW:
+/// a half kick (B), a half drift (A), an exact Ornstein-Uhlenbeck
+/// update of the velocities (O), a half drift and a half kick. It
+/// samples positions from the Boltzmann distribution very accurately
+/// for its step size.
+///
+/// The forces at the end of each step are kept for the start of the
+/// next; call `reset` if the positions or forces are changed in
+/// between.
+#[derive(Debug, Clone)]
+pub struct Baoab {
+	/// Friction (collision) rate γ, per unit time
+	pub friction: f64,
+	/// Thermal energy kT
+	pub kt: f64,
+	forces: Option<Vec<Vec2>>,
+}
+
+impl Baoab {
+	pub fn new(friction: f64, kt: f64) -> Baoab {
+		assert!(friction >= 0_f64 && kt >= 0_f64, "friction and temperature can't be negative");
+		Baoab{friction, kt, forces: None}
+	}
+
+	/// Forget the forces kept from the last step.
+	pub fn reset(&mut self) {
+		self.forces = None;
+	}
+
+	/// Advance positions `x` and velocities `v` by a time `dt`.
+	pub fn step<F: ForceField>(
+		&mut self,
+		field: &F,
+		x: &mut [Vec2],
+		v: &mut [Vec2],
+		dt: f64,
+		rng: &mut NormalDist) {
+		assert_eq!(x.len(), v.len(), "positions and velocities differ in length");
+		let mut f = match self.forces.take() {
+			Some(f) if f.len() == x.len() => f,
+			_ => {
+				let mut f = vec!(Vec2::zero(); x.len());
+				field.force(x, &mut f);
+				f
+			},
+		};
+		let c1 = (-self.friction*dt).exp();
+		let c2 = (1_f64 - c1*c1).sqrt();
+		for (i, ((x, v), f)) in x.iter_mut().zip(v.iter_mut()).zip(f.iter()).enumerate() {
+			let m = field.mass(i);
+			*v = *v + (0.5*dt/m)*(*f);
+			*x = *x + 0.5*dt*(*v);
+			*v = c1*(*v) + (c2*(self.kt/m).sqrt())*standard_normal(rng);
+			*x = *x + 0.5*dt*(*v);
+		}
+		field.force(x, &mut f);
+		for (i, (v, f)) in v.iter_mut().zip(f.iter()).enumerate() {
+			*v = *v + (0.5*dt/field.mass(i))*(*f);
+		}
+		self.forces = Some(f);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Particles in a harmonic well, V = k x²/2
+	struct Well {
+		k: f64,
+		m: f64,
+	}
+
+	impl ForceField for Well {
+		fn force(&self, x: &[Vec2], f: &mut [Vec2]) {
+			for (x, f) in x.iter().zip(f.iter_mut()) {
+				*f = -self.k*(*x);
+			}
+		}
+
+		fn mass(&self, _i: usize) -> f64 {
+			self.m
+		}
+	}
+
+	/// The mean square of the components of a set of vectors
+	fn mean_square(v: &[Vec2]) -> f64 {
+		v.iter().map( |v| v.norm_squared()).sum::<f64>()/(2*v.len()) as f64
+	}
+
+	fn rng() -> NormalDist {
+		let mut rng = NormalDist::new(0.0, 1.0);
+		rng.seed(12345, 67890);
+		rng
+	}
+
+	/// The noise is standard normal whatever the distribution of the
+	/// generator, even one with no spread
+	#[test]
+	fn test_standard_normal() {
+		let mut rng = NormalDist::new(3.0, 0.0);
+		rng.seed(12345, 67890);
+		let dw : Vec<Vec2> = (0..5000).map( |_| standard_normal(&mut rng)).collect();
+		assert!((mean_square(&dw) - 1.0).abs() < 0.05, "mean square {}", mean_square(&dw));
+		assert_eq!(rng.next(), 3.0);
+	}
+
+	/// Brownian particles in a well reach the Boltzmann distribution,
+	/// <x²> = kT/k in each direction
+	#[test]
+	fn test_overdamped_equilibrium() {
+		let well = Well{k: 2.0, m: 1.0};
+		let sde = BrownianDynamics{forces: &well, friction: 0.5, kt: 1.5};
+		let mut rng = rng();
+		let mut em = vec!(Vec2::zero(); 2000);
+		let mut milstein = em.clone();
+		for i in 0..1000 {
+			let t = i as f64*0.01;
+			EulerMaruyama.step(&sde, t, &mut em, 0.01, &mut rng);
+			Milstein.step(&sde, t, &mut milstein, 0.01, &mut rng);
+		}
+		let expected = 1.5/2.0;
+		assert!((mean_square(&em)/expected - 1.0).abs() < 0.1, "euler-maruyama {}", mean_square(&em));
+		assert!((mean_square(&milstein)/expected - 1.0).abs() < 0.1, "milstein {}", mean_square(&milstein));
+	}
+
+	/// Geometric Brownian motion, dx = sqrt(2 D) dW with D = x²/2, so
+	/// the noise is proportional to x. Milstein follows the exact
+	/// solution x = x0 exp(W - t/2) for the same noise much more
+	/// closely than Euler-Maruyama.
+	#[test]
+	fn test_milstein_strong_order() {
+		struct Gbm;
+		impl Sde for Gbm {
+			fn drift(&self, _t: f64, _x: &[Vec2], a: &mut [Vec2]) {
+				for a in a.iter_mut() {
+					*a = Vec2::zero();
+				}
+			}
+			fn diffusion(&self, _t: f64, _i: usize, x: Vec2) -> f64 {
+				0.5*x.x*x.x
+			}
+			fn diffusion_gradient(&self, _t: f64, _i: usize, x: Vec2) -> Vec2 {
+				Vec2{x: x.x, y: 0.0}
+			}
+		}
+		fn error<I: SdeIntegrator>(integrator: I) -> f64 {
+			let mut rng = rng();
+			let mut total = 0_f64;
+			let dt = 0.01_f64;
+			for _ in 0..200 {
+				let mut x = [Vec2{x: 1.0, y: 0.0}];
+				let mut w = 0_f64;
+				for _ in 0..100 {
+					// Use the same noise as the integrator
+					let mut copy = rng;
+					w += dt.sqrt()*copy.next();
+					integrator.step(&Gbm, 0.0, &mut x, dt, &mut rng);
+				}
+				total += (x[0].x - (w - 0.5).exp()).abs();
+			}
+			total/200_f64
+		}
+		let em = error(EulerMaruyama);
+		let milstein = error(Milstein);
+		assert!(milstein < 0.25*em, "milstein {} vs euler-maruyama {}", milstein, em);
+	}
+
+	/// BAOAB gives equipartition, <v²> = kT/m and <x²> = kT/k
+	#[test]
+	fn test_baoab_equilibrium() {
+		let well = Well{k: 4.0, m: 2.0};
+		let mut baoab = Baoab::new(1.0, 0.8);
+		let mut rng = rng();
+		let mut x = vec!(Vec2::zero(); 2000);
+		let mut v = x.clone();
+		for _ in 0..1000 {
+			baoab.step(&well, &mut x, &mut v, 0.05, &mut rng);
+		}
+		assert!((mean_square(&x)/(0.8/4.0) - 1.0).abs() < 0.1, "<x²> = {}", mean_square(&x));
+		assert!((mean_square(&v)/(0.8/2.0) - 1.0).abs() < 0.1, "<v²> = {}", mean_square(&v));
+	}
+}
@@ -17,6 +17,8 @@
 use std::f64;
 use std::f64::consts::PI;
 
+use ::time::entropy_seed;
+
 /// These are "random" seeds taken from random.org
 /// lol! constant "random" numbers!
 const S0 : u64 = 0x2475136db02a2834_u64;
@@ -47,6 +49,22 @@ impl Rng {
 		self.s1 = s1.wrapping_add(S1); 
 	}
 
+	/// A new random number generator seeded from entropy, giving a
+	/// different sequence each time you run.
+	pub fn from_entropy() -> Rng {
+		let mut rng = Rng::new();
+		rng.seed_from_entropy();
+		rng
+	}
+
+	/// Sets the internal state of the random number generator from
+	/// entropy: `crypto.getRandomValues` in the browser or the
+	/// operating system when running natively.
+	pub fn seed_from_entropy( &mut self) {
+		let (s0, s1) = entropy_seed();
+		self.seed(s0, s1);
+	}
+
 	/// Gives a random `u64` between 0 and 2^(64)-1
 	pub fn next(&mut self) -> u64 {
 		let s0 = self.s0;
@@ -103,22 +121,39 @@ impl NormalDist {
     pub fn seed(&mut self, s0: u64, s1:u64) {
         self.rng.seed(s0,s1);
     }
+
+    /// Reset the state of the random number generator from entropy,
+    /// see `Rng::seed_from_entropy`.
+    pub fn seed_from_entropy(&mut self) {
+        self.rng.seed_from_entropy();
+        self.stored = None;
+    }
     
+    /// Gives a random `f64` uniformly distributed in (0,1]
+    /// from the underlying generator, see `Rng::uniform`.
+    pub fn uniform(&mut self) -> f64 {
+        self.rng.uniform()
+    }
+
     /// Gives a random `f64` drawn from the normal 
     /// distribution: N(mu, sigma^2).
     pub fn next(&mut self) -> f64 {
-        match self.stored {
-            Some(val) => {
-                self.stored.take();
-                val
-            },
+        self.mu + self.sigma*self.standard()
+    }
+
+    /// Gives a random `f64` drawn from the standard normal
+    /// distribution N(0, 1), whatever `mu` and `sigma` are,
+    /// even a `sigma` of zero.
+    pub fn standard(&mut self) -> f64 {
+        match self.stored.take() {
+            Some(val) => val,
             None => {
                 let u0 = self.rng.uniform();
                 let u1 = self.rng.uniform();
-                let r = self.sigma*(-2.0*u0.ln()).sqrt();
+                let r = (-2.0*u0.ln()).sqrt();
                 let (s,c) = (2.0*PI*u1).sin_cos();
-                self.stored = Some(r*s+self.mu);
-                r*c+self.mu
+                self.stored = Some(r*s);
+                r*c
             }
         }
     }
@@ -2,55 +2,334 @@
 
 use std::boxed::Box;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{
+	Cell,
+	RefCell,
+};
+use std::fmt;
 
 use stdweb::traits::*;
 use stdweb::unstable::TryInto;
 use stdweb::web::{
 	window,
-	// document,
-	// HtmlElement,
+	document,
+	set_timeout,
+	HtmlElement,
 	RequestAnimationFrameHandle,
  };
-// use stdweb::web::html_element::InputElement;
-// use stdweb::web::event::ClickEvent;
 
-// use ::control::Toggle;
+use ::container::UiElement;
+use ::control::{
+	Button,
+	Toggle,
+};
+use ::history::History;
+use ::snapshot::{
+	Snapshot,
+	SnapshotError,
+};
 
 const MAX_TIME_STEP : f64 = 16.66666666666666666666666666666666666666666666666666666666;
 
+/// Default limit on the number of fixed steps taken in a single
+/// frame when the loop falls behind.
+const DEFAULT_MAX_STEPS : u32 = 8;
+
+/// Relative tolerance when comparing accumulated time to the fixed
+/// step, so round-off doesn't drop a step from a frame.
+const STEP_TOLERANCE : f64 = 1.0e-9;
+
+/// Weight of the newest frame in the smoothed frames per second.
+const FPS_SMOOTHING : f64 = 0.1;
+
+/// A simulation that can be driven by a `Simloop`.
+///
+/// Each frame the loop calls `update` one or more times to advance
+/// the physics, then calls `render` once to draw the result. Simple
+/// simulations can implement only `step`, which `update` calls by
+/// default, and do everything in one place.
 pub trait SimStep {
-	fn step(&mut self, dt: f64);
+	/// Advance the simulation a time `dt` (ms).
+	fn step(&mut self, _dt: f64) {}
+
+	/// Advance the physics a time `dt` (ms). Defaults to `step`.
+	fn update(&mut self, dt: f64) {
+		self.step(dt);
+	}
+
+	/// Draw the current state, called once per frame after all the
+	/// updates. `alpha` is the leftover fraction of a fixed step
+	/// (see `Simloop::alpha`), for interpolated drawing.
+	fn render(&mut self, _alpha: f64) {}
+
+	/// Called when the loop is started. The loop is borrowed while
+	/// this runs, so it must not borrow the `Simloop` itself; use
+	/// `Simloop::add_run_function` for that.
+	fn on_start(&mut self) {}
+
+	/// Called when the loop is stopped, while the loop is borrowed as
+	/// with `on_start`.
+	fn on_stop(&mut self) {}
+
+	/// Called when the simulation is reset to its initial state.
+	fn on_reset(&mut self) {}
+}
+
+/// The simulation clock kept by a loop.
+///
+/// While in `SimStep::update` the clock already counts the step being
+/// taken, so `time` is the simulated time at the end of the step.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SimClock {
+	/// Total simulated time (ms), the sum of every `dt` passed to `update`
+	pub time: f64,
+	/// Number of calls to `update`
+	pub steps: u64,
+	/// Number of calls to `render`
+	pub frames: u64,
+	/// Wall-clock time (ms) spent running
+	pub wall_time: f64,
+	/// Measured animation frames per second, smoothed over recent frames
+	pub fps: f64,
+}
+
+impl SimClock {
+	/// Total simulated time in seconds.
+	pub fn seconds(&self) -> f64 {
+		self.time/1000_f64
+	}
+}
+
+/// A shared, read-only view of a loop's `SimClock`. Clone it into a
+/// state or an output widget to read the clock at any time.
+#[derive(Debug, Clone, Default)]
+pub struct ClockHandle(Rc<Cell<SimClock>>);
+
+impl ClockHandle {
+	pub fn get(&self) -> SimClock {
+		self.0.get()
+	}
+}
+
+/// The part of the loop that turns animation frame times into calls
+/// to `update` and `render`. It does not touch the DOM, so it is
+/// shared between `Simloop` and `Headless`.
+#[derive(Debug)]
+struct Stepper {
+	/// Time stamp (ms) of the last animation frame, `None` until the
+	/// first frame after a start
+	time_old: Option<f64>,
+	/// Fixed time step (ms), `None` to step once per frame
+	fixed_dt: Option<f64>,
+	/// Maximum number of fixed steps taken per frame
+	max_steps: u32,
+	/// Wall-clock time (ms) not yet consumed by fixed steps
+	accumulator: f64,
+	/// Simulation time per unit of wall-clock time
+	time_scale: f64,
+	clock: ClockHandle,
+}
+
+impl Stepper {
+	fn new() -> Stepper {
+		Stepper{
+			time_old: None,
+			fixed_dt: None,
+			max_steps: DEFAULT_MAX_STEPS,
+			accumulator: 0_f64,
+			time_scale: 1_f64,
+			clock: ClockHandle::default(),
+		}
+	}
+
+	/// Advance the simulation for an animation frame with time
+	/// stamp `time` (ms), `None` when the loop is starting.
+	fn advance_frame<T: SimStep>(&mut self, time: Option<f64>, state: &mut T) {
+		let frame_dt = match (self.time_old, time) {
+			(Some(time_old), Some(time)) => Some(time-time_old),
+			_ => None,
+		};
+		self.time_old = time;
+		self.frame(frame_dt, state);
+	}
+
+	/// Advance the simulation for an animation frame that lasted
+	/// `frame_dt` (ms), counting the wall-clock time and frame rate.
+	/// The first frame after a start has no previous frame to measure
+	/// from, `None`, and is advanced by `MAX_TIME_STEP` without
+	/// touching the wall-clock time or frame rate.
+	fn frame<T: SimStep>(&mut self, frame_dt: Option<f64>, state: &mut T) {
+		let frame_dt = match frame_dt {
+			Some(frame_dt) => frame_dt,
+			None => {
+				self.advance(MAX_TIME_STEP, state);
+				return;
+			},
+		};
+		let mut clock = self.clock.get();
+		clock.wall_time += frame_dt;
+		let fps = 1000_f64/frame_dt;
+		clock.fps = if clock.fps == 0_f64 {
+			fps
+		} else {
+			(1_f64-FPS_SMOOTHING)*clock.fps + FPS_SMOOTHING*fps
+		};
+		self.clock.0.set(clock);
+		self.advance(frame_dt, state);
+	}
+
+	/// Update the state with time step `dt`, counting it on the clock.
+	fn update<T: SimStep>(&mut self, dt: f64, state: &mut T) {
+		let mut clock = self.clock.get();
+		clock.time += dt;
+		clock.steps += 1;
+		self.clock.0.set(clock);
+		state.update(dt);
+	}
+
+	/// Render the state, counting the frame on the clock.
+	fn render<T: SimStep>(&mut self, alpha: f64, state: &mut T) {
+		let mut clock = self.clock.get();
+		clock.frames += 1;
+		self.clock.0.set(clock);
+		state.render(alpha);
+	}
+
+	/// Advance the simulation for a frame that lasted `frame_dt` (ms)
+	/// of wall-clock time, scaled by the time scale. In the default
+	/// mode the state is updated once with the frame time, capped at
+	/// `MAX_TIME_STEP`. In fixed step mode the frame time is added to
+	/// an accumulator and the state is updated a whole number of
+	/// times with the fixed time step. The state is rendered once at
+	/// the end.
+	fn advance<T: SimStep>(&mut self, frame_dt: f64, state: &mut T) {
+		match self.fixed_dt {
+			None => {
+				let dt = self.time_scale*MAX_TIME_STEP.min(frame_dt);
+				self.update(dt, state);
+			},
+			Some(dt) => {
+				self.accumulator += self.time_scale*frame_dt;
+				// Running faster than real time needs more steps per frame
+				let max_steps = (self.max_steps as f64*self.time_scale.max(1_f64)).ceil() as u32;
+				let threshold = dt*(1_f64-STEP_TOLERANCE);
+				let mut steps = 0;
+				while self.accumulator >= threshold && steps < max_steps {
+					self.update(dt, state);
+					self.accumulator -= dt;
+					steps += 1;
+				}
+				// If we fell too far behind drop the time we could
+				// not catch up on, keeping only the leftover fraction
+				if self.accumulator >= threshold {
+					self.accumulator %= dt;
+				}
+				self.accumulator = self.accumulator.max(0_f64);
+			},
+		}
+		let alpha = self.alpha();
+		self.render(alpha, state);
+	}
+
+	fn set_fixed_step(&mut self, dt: f64, max_steps: u32) {
+		assert!(dt > 0_f64, "fixed time step must be positive");
+		self.fixed_dt = Some(dt);
+		self.max_steps = max_steps.max(1);
+		self.accumulator = 0_f64;
+	}
+
+	fn clear_fixed_step(&mut self) {
+		self.fixed_dt = None;
+		self.accumulator = 0_f64;
+	}
+
+	fn alpha(&self) -> f64 {
+		match self.fixed_dt {
+			Some(dt) => self.accumulator/dt,
+			None => 0_f64,
+		}
+	}
+
+	fn set_time_scale(&mut self, time_scale: f64) {
+		assert!(time_scale > 0_f64, "time scale must be positive");
+		self.time_scale = time_scale;
+	}
+
+	fn reset_clock(&mut self) {
+		self.clock.0.set(SimClock::default());
+	}
+}
+
+/// The history of a loop, and how to take snapshots of its state.
+#[derive(Debug)]
+struct Recorder<T> {
+	history: History,
+	snapshot: fn(&T) -> String,
+}
+
+/// Functions called with the new running state whenever a loop is
+/// started or stopped.
+struct RunFunctions(Vec<Rc<dyn Fn(bool)>>);
+
+impl RunFunctions {
+	/// Call the functions with `running` from a timeout, once whoever
+	/// started or stopped the loop is done and has let go of it.
+	fn call(&self, running: bool) {
+		if self.0.is_empty() {
+			return;
+		}
+		let funcs = self.0.clone();
+		set_timeout( move || {
+			for func in funcs.iter() {
+				func(running);
+			}
+		}, 0);
+	}
+}
+
+impl fmt::Debug for RunFunctions {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "RunFunctions({})", self.0.len())
+	}
 }
 
 #[derive(Debug)]
 pub struct Simloop<T: SimStep + 'static> {
-	time_old: f64,
+	stepper: Stepper,
 	handle: Option<RequestAnimationFrameHandle>,
+	/// The scheduler driving the loop, if added to one
+	scheduler: Option<Scheduler>,
+	/// Whether the scheduler is stepping the loop
+	scheduled: bool,
+	run_functions: RunFunctions,
+	/// The last snapshot saved with `save_snapshot`
+	snapshot: Option<String>,
+	/// The recorded history, if recording
+	recorder: Option<Recorder<T>>,
 	pub state: Box<T>,
 }
 
 
 impl<T: SimStep + 'static> Simloop<T> {
-	fn step_frame(&mut self, time: f64, refstate: Rc<RefCell<Self>>) {
-		let dt = if time > 0_f64 { 
-			MAX_TIME_STEP.min(time-self.time_old)
-		} else {
-			MAX_TIME_STEP
-		};
-		self.time_old = time;
-		self.state.step(dt);
+	fn step_frame(&mut self, time: Option<f64>, refstate: Rc<RefCell<Self>>) {
+		self.stepper.advance_frame(time, &mut *self.state);
+		self.update_history();
 		self.handle = Some(
 			window().request_animation_frame( move | time | {
-				refstate.borrow_mut().step_frame(time, refstate.clone());
+				refstate.borrow_mut().step_frame(Some(time), refstate.clone());
 			})
 		);
 	}
 
 	pub fn new( state: T) -> Simloop<T> {
 		Simloop{
-			time_old: 0_f64,
+			stepper: Stepper::new(),
 			handle: None,
+			scheduler: None,
+			scheduled: false,
+			run_functions: RunFunctions(Vec::new()),
+			snapshot: None,
+			recorder: None,
 			state: Box::new(state),
 		}
 	}
@@ -59,69 +338,769 @@ impl<T: SimStep + 'static> Simloop<T> {
 		Rc::new(RefCell::new(Simloop::new(state)))
 	}
 
+	/// Stop the loop. A loop added to a `Scheduler` is paused while
+	/// the others keep running.
 	pub fn stop_loop(&mut self) {
 		let maybe_handle = self.handle.take();
 		match maybe_handle {
 			Some(handle) => {
 				handle.cancel();
+				self.state.on_stop();
+				self.run_functions.call(false);
+			},
+			None if self.scheduled => {
+				self.scheduled = false;
+				self.state.on_stop();
+				self.run_functions.call(false);
 			},
 			None => {},
 		};
 	}
 
+	/// Start the loop. A loop added to a `Scheduler` is stepped by the
+	/// scheduler's frames, in lock step with the other loops.
 	pub fn start_loop( refstate: Rc<RefCell<Simloop<T>>>) {
-		refstate.borrow_mut().step_frame(0_f64 , refstate.clone());
-	}
-
-	// pub fn create_toggle( refstate: Rc<RefCell<Simloop<T>>> ) -> Toggle {
-	// 	let elem : HtmlElement = document().create_element("span").unwrap().try_into().unwrap();
-	// 	elem.class_list().add("control").unwrap();
-	// 	elem.set_attribute("data-param-type", "toggle").unwrap();
-	// 	let button : InputElement = document().create_element( "input").unwrap().try_into().unwrap();
-	// 	button.class_list().add("control_toggle").unwrap();
-	// 	button.set_attribute("type","button").unwrap();
-	// 	button.set_attribute("value", "Start").unwrap();
-	// 	button.set_attribute("data-toggle-state", "off").unwrap();
-	// 	button.add_event_listener({
-	// 		let button = button.clone();
-	// 		let refstate = refstate.clone();//?
-	// 		move | _:ClickEvent | {
-	// 			let test = button.get_attribute("data-toggle-state").unwrap() == "off";
-	// 			if test {
-	// 				button.set_attribute("data-toggle-state", "on").unwrap();
-	// 				Simloop::start_loop( refstate.clone());
-	// 				button.set_raw_value( "Stop");
-	// 			} else {
-	// 				button.set_attribute("data-toggle-state", "off").unwrap();
-	// 				refstate.borrow_mut().stop_loop();
-	// 				button.set_raw_value( "Start");
-	// 			};
-	// 		}
-	// 	});
-	// 	elem.append_child( &button);
-	// 	Toggle::create_from(elem, button)
-	// }
-}
-
-/// A time function!
-pub fn get_time() -> u64 {
-	let value = js! {
-		var d = new Date();
-//		console.log(d);
-		var n = d.getTime();
-		// console.log(n);
-		return n;
-    };
-	// js!{ @(no_return)
-	// 	console.log(@{value});
-	// };
-	let time : u64 = value.try_into().unwrap();
-//	let time : u64 = 0;
-	// let str_time = time.to_string();
-	// js!{ @(no_return)
-	// 	console.log(@{str_time});
-	// };
-    // let time : String = value.try_into().unwrap();
-    time
+		let scheduler = {
+			let mut simloop = refstate.borrow_mut();
+			// Don't start a second chain of animation frames
+			if simloop.is_running() {
+				return;
+			}
+			match simloop.scheduler.clone() {
+				Some(scheduler) => {
+					simloop.start_scheduled();
+					scheduler
+				},
+				None => {
+					simloop.state.on_start();
+					simloop.step_frame(None, refstate.clone());
+					simloop.run_functions.call(true);
+					return;
+				},
+			}
+		};
+		scheduler.start();
+	}
+
+	/// Mark the loop to be stepped by its scheduler.
+	fn start_scheduled(&mut self) {
+		if self.is_running() {
+			return;
+		}
+		self.state.on_start();
+		self.scheduled = true;
+		self.run_functions.call(true);
+	}
+
+	/// Call `func` with the new running state every time the loop is
+	/// started or stopped, for keeping controls in sync with the loop.
+	/// `func` is called once the current event has been handled, when
+	/// the loop is no longer borrowed, so it is free to borrow the loop,
+	/// e.g. to read `is_running` or `clock`.
+	pub fn add_run_function<F>( &mut self, func: F)
+		where F: Fn(bool) + 'static {
+		self.run_functions.0.push( Rc::new(func));
+	}
+
+	/// Whether the loop is currently running.
+	pub fn is_running(&self) -> bool {
+		self.handle.is_some() || self.scheduled
+	}
+
+	/// Advance the simulation by exactly one frame (`MAX_TIME_STEP`
+	/// of wall-clock time, scaled by the time scale) and draw it. Does
+	/// nothing if the loop is running.
+	pub fn step_once(&mut self) {
+		if !self.is_running() {
+			self.stepper.advance(MAX_TIME_STEP, &mut *self.state);
+			self.update_history();
+		}
+	}
+
+	/// Set the amount of simulation time that passes per unit of
+	/// wall-clock time: 0.5 runs at half speed, 4 at four times
+	/// speed. In fixed step mode the cap on steps per frame grows
+	/// with the time scale.
+	pub fn set_time_scale(&mut self, time_scale: f64) {
+		self.stepper.set_time_scale(time_scale);
+	}
+
+	pub fn time_scale(&self) -> f64 {
+		self.stepper.time_scale
+	}
+
+	/// The current simulation clock.
+	pub fn clock(&self) -> SimClock {
+		self.stepper.clock.get()
+	}
+
+	/// A shared handle for reading the simulation clock.
+	pub fn clock_handle(&self) -> ClockHandle {
+		self.stepper.clock.clone()
+	}
+
+	/// Stop the loop, reset the state with `SimStep::on_reset` and
+	/// draw the result.
+	pub fn reset(&mut self) {
+		self.stop_loop();
+		self.stepper.accumulator = 0_f64;
+		self.stepper.reset_clock();
+		self.state.on_reset();
+		self.state.render(0_f64);
+		if let Some(recorder) = self.recorder.as_mut() {
+			recorder.history.clear();
+		}
+		self.update_history();
+	}
+
+	/// Take a snapshot for the history if one is due.
+	fn update_history(&mut self) {
+		if let Some(recorder) = self.recorder.as_mut() {
+			let clock = self.stepper.clock.get();
+			if recorder.history.is_due(clock.steps) {
+				recorder.history.record(clock, (recorder.snapshot)(&self.state));
+			}
+		}
+	}
+
+	/// The recorded history, if recording.
+	pub fn history(&self) -> Option<&History> {
+		self.recorder.as_ref().map( |recorder| &recorder.history)
+	}
+
+	/// Stop recording and drop the history.
+	pub fn stop_history(&mut self) {
+		self.recorder = None;
+	}
+
+	/// Switch to fixed step mode. Each frame the elapsed time is
+	/// accumulated and `SimStep::update` is called with the fixed time
+	/// step `dt` (ms) as many times as fit, but never more than
+	/// `max_steps` times in a single frame.
+	pub fn set_fixed_step(&mut self, dt: f64, max_steps: u32) {
+		self.stepper.set_fixed_step(dt, max_steps);
+	}
+
+	/// Switch back to stepping once per frame with the frame time.
+	pub fn clear_fixed_step(&mut self) {
+		self.stepper.clear_fixed_step();
+	}
+
+	/// The fixed time step (ms), if the loop is in fixed step mode.
+	pub fn fixed_step(&self) -> Option<f64> {
+		self.stepper.fixed_dt
+	}
+
+	/// The fraction of a fixed step left over after the last frame,
+	/// between 0 and 1. Use it to interpolate between the previous
+	/// and current states when drawing. Always 0 when not in fixed
+	/// step mode.
+	pub fn alpha(&self) -> f64 {
+		self.stepper.alpha()
+	}
+}
+
+impl<T: SimStep + Snapshot + 'static> Simloop<T> {
+	/// Save a snapshot of the state in the loop, replacing any
+	/// earlier one.
+	pub fn save_snapshot(&mut self) {
+		self.snapshot = Some(self.state.snapshot());
+	}
+
+	/// The snapshot saved with `save_snapshot`, if any.
+	pub fn saved_snapshot(&self) -> Option<&str> {
+		self.snapshot.as_deref()
+	}
+
+	/// Stop the loop and restore the state from the saved snapshot.
+	pub fn restore_snapshot(&mut self) -> Result<(), SnapshotError> {
+		let snapshot = match self.snapshot.take() {
+			Some(snapshot) => snapshot,
+			None => return Err(SnapshotError::NoSnapshot),
+		};
+		let result = self.load_snapshot(&snapshot);
+		self.snapshot = Some(snapshot);
+		result
+	}
+
+	/// Start recording a history of the last `capacity` states, one
+	/// snapshot every `every` steps, starting with the current state.
+	/// Any earlier history is dropped.
+	pub fn record_history(&mut self, capacity: usize, every: u64) {
+		self.recorder = Some(Recorder{
+			history: History::new(capacity, every),
+			snapshot: T::snapshot,
+		});
+		self.update_history();
+	}
+
+	/// Stop the loop and go back to the history entry at `index`
+	/// (0 is the oldest), restoring both the state and the clock and
+	/// redrawing. Running on from there records over the newer
+	/// entries.
+	pub fn scrub_to(&mut self, index: usize) -> Result<(), SnapshotError> {
+		let entry = match self.recorder.as_mut().and_then( |r| r.history.seek(index)) {
+			Some(entry) => entry.clone(),
+			None => return Err(SnapshotError::NoSnapshot),
+		};
+		self.stop_loop();
+		self.stepper.accumulator = 0_f64;
+		self.state.restore(&entry.snapshot)?;
+		let mut clock = entry.clock;
+		let now = self.stepper.clock.get();
+		clock.wall_time = now.wall_time;
+		clock.fps = now.fps;
+		self.stepper.clock.0.set(clock);
+		self.state.render(0_f64);
+		Ok(())
+	}
+
+	/// Go back `entries` entries in the history from the current one.
+	pub fn rewind(&mut self, entries: usize) -> Result<(), SnapshotError> {
+		let position = self.history().and_then( |h| h.position()).unwrap_or(0);
+		self.scrub_to(position.saturating_sub(entries))
+	}
+
+	/// Go forward `entries` entries in the history from the current
+	/// one, as far as the newest.
+	pub fn forward(&mut self, entries: usize) -> Result<(), SnapshotError> {
+		let position = self.history().and_then( |h| h.position()).unwrap_or(0);
+		self.scrub_to(position + entries)
+	}
+
+	/// Stop the loop, restore the state from the snapshot text, e.g.
+	/// read from a file, and draw the result. The history from before
+	/// the load is dropped, starting again from the loaded state.
+	pub fn load_snapshot(&mut self, snapshot: &str) -> Result<(), SnapshotError> {
+		self.stop_loop();
+		self.stepper.accumulator = 0_f64;
+		self.state.restore(snapshot)?;
+		self.state.render(0_f64);
+		if let Some(recorder) = self.recorder.as_mut() {
+			recorder.history.clear();
+		}
+		self.update_history();
+		Ok(())
+	}
+}
+
+/// A loop that a `Scheduler` can step.
+trait Scheduled {
+	fn is_running(&self) -> bool;
+
+	fn start(&mut self);
+
+	fn stop(&mut self);
+
+	/// Advance for an animation frame that lasted `frame_dt` (ms),
+	/// `None` for the first frame after a start.
+	fn frame(&mut self, frame_dt: Option<f64>);
 }
 
+impl<T: SimStep + 'static> Scheduled for Simloop<T> {
+	fn is_running(&self) -> bool {
+		Simloop::is_running(self)
+	}
+
+	fn start(&mut self) {
+		self.start_scheduled();
+	}
+
+	fn stop(&mut self) {
+		self.stop_loop();
+	}
+
+	fn frame(&mut self, frame_dt: Option<f64>) {
+		self.stepper.frame(frame_dt, &mut *self.state);
+		self.update_history();
+	}
+}
+
+struct SchedulerLoops {
+	loops: Vec<Rc<RefCell<dyn Scheduled>>>,
+	handle: Option<RequestAnimationFrameHandle>,
+	/// Time stamp (ms) of the last animation frame
+	time_old: Option<f64>,
+}
+
+impl fmt::Debug for SchedulerLoops {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "SchedulerLoops({}, running: {})", self.loops.len(), self.handle.is_some())
+	}
+}
+
+/// Drives several `Simloop`s from a single chain of animation frames,
+/// so they all see the same frame times and stay in lock step, e.g.
+/// for side-by-side comparisons. Once added, each loop is still
+/// started and stopped on its own with `Simloop::start_loop` and
+/// `Simloop::stop_loop` (or its `SimControls`), which pauses it
+/// without affecting the others. The frames stop when no loop is
+/// running. Clones share the same set of loops.
+#[derive(Debug, Clone)]
+pub struct Scheduler(Rc<RefCell<SchedulerLoops>>);
+
+impl Default for Scheduler {
+	fn default() -> Scheduler {
+		Scheduler::new()
+	}
+}
+
+impl Scheduler {
+	pub fn new() -> Scheduler {
+		Scheduler(Rc::new(RefCell::new(SchedulerLoops{
+			loops: Vec::new(),
+			handle: None,
+			time_old: None,
+		})))
+	}
+
+	/// Add a loop to be driven by the scheduler. If the loop was
+	/// running on its own it carries on running in the scheduler.
+	pub fn add<T: SimStep + 'static>(&self, refstate: &Rc<RefCell<Simloop<T>>>) {
+		let running = {
+			let mut simloop = refstate.borrow_mut();
+			assert!(simloop.scheduler.is_none(), "loop already added to a scheduler");
+			let running = simloop.is_running();
+			if let Some(handle) = simloop.handle.take() {
+				handle.cancel();
+			}
+			simloop.scheduler = Some(self.clone());
+			simloop.scheduled = running;
+			running
+		};
+		self.0.borrow_mut().loops.push(refstate.clone());
+		if running {
+			self.start();
+		}
+	}
+
+	/// The number of loops added.
+	pub fn len(&self) -> usize {
+		self.0.borrow().loops.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.borrow().loops.is_empty()
+	}
+
+	/// Whether the scheduler is requesting animation frames.
+	pub fn is_running(&self) -> bool {
+		self.0.borrow().handle.is_some()
+	}
+
+	/// Start requesting animation frames, if not already. Starting a
+	/// loop that was added calls this.
+	pub fn start(&self) {
+		if self.is_running() {
+			return;
+		}
+		let mut loops = self.0.borrow_mut();
+		loops.time_old = None;
+		loops.handle = Some(Scheduler::request_frame(self.0.clone()));
+	}
+
+	/// Start all the loops that were added.
+	pub fn start_all(&self) {
+		for simloop in self.loops() {
+			simloop.borrow_mut().start();
+		}
+		self.start();
+	}
+
+	/// Stop all the loops that were added.
+	pub fn stop_all(&self) {
+		for simloop in self.loops() {
+			simloop.borrow_mut().stop();
+		}
+	}
+
+	fn loops(&self) -> Vec<Rc<RefCell<dyn Scheduled>>> {
+		self.0.borrow().loops.clone()
+	}
+
+	fn request_frame(refloops: Rc<RefCell<SchedulerLoops>>) -> RequestAnimationFrameHandle {
+		window().request_animation_frame( move | time | {
+			Scheduler::step_frame(refloops, time);
+		})
+	}
+
+	/// Step every running loop with the same frame time. `time` is
+	/// the animation frame time stamp (ms).
+	fn step_frame(refloops: Rc<RefCell<SchedulerLoops>>, time: f64) {
+		// Let go of the loops while stepping, so a simulation can add,
+		// start or stop loops from its update or render
+		let (loops, time_old) = {
+			let loops = refloops.borrow();
+			(loops.loops.clone(), loops.time_old)
+		};
+		let frame_dt = time_old.map( |time_old| time-time_old);
+		for simloop in loops.iter() {
+			let mut simloop = simloop.borrow_mut();
+			if simloop.is_running() {
+				simloop.frame(frame_dt);
+			}
+		}
+		let running = refloops.borrow().loops.iter().any( |simloop| simloop.borrow().is_running());
+		let mut loops = refloops.borrow_mut();
+		if running {
+			loops.time_old = Some(time);
+			loops.handle = Some(Scheduler::request_frame(refloops.clone()));
+		} else {
+			loops.handle = None;
+		}
+	}
+}
+
+/// A set of controls bound to a `Simloop`: a Run/Pause toggle, a
+/// Step button and a Reset button. The toggle's label always matches
+/// whether the loop is running, however it was started or stopped.
+/// Step pauses the loop and advances a single frame, and Reset pauses
+/// the loop and calls `SimStep::on_reset`.
+#[derive(Debug, Clone)]
+pub struct SimControls {
+	elem: HtmlElement,
+	run: Toggle,
+	step: Button,
+	reset: Button,
+}
+
+impl SimControls {
+	pub fn new<T: SimStep + 'static>( name: &str, refstate: Rc<RefCell<Simloop<T>>>) -> SimControls {
+		let elem : HtmlElement = document().create_element("span").unwrap().try_into().unwrap();
+		elem.class_list().add("control").unwrap();
+		elem.set_attribute("id", name).unwrap();
+		elem.set_attribute("data-param-type", "sim_controls").unwrap();
+		let run = Toggle::new( &format!("{}_run",name), "Run", "Pause");
+		let step = Button::new( &format!("{}_step",name), "Step");
+		let reset = Button::new( &format!("{}_reset",name), "Reset");
+		elem.append_child( run.elem());
+		elem.append_child( step.elem());
+		elem.append_child( reset.elem());
+
+		run.set( refstate.borrow().is_running());
+		refstate.borrow_mut().add_run_function({
+			let run = run.clone();
+			move | running:bool | {
+				run.set(running);
+			}
+		});
+		run.add_toggle_function({
+			let refstate = refstate.clone();
+			move | status:bool | {
+				if status {
+					Simloop::start_loop( refstate.clone());
+				} else {
+					refstate.borrow_mut().stop_loop();
+				}
+			}
+		});
+		step.add_button_function({
+			let refstate = refstate.clone();
+			move | _:bool | {
+				let mut simloop = refstate.borrow_mut();
+				simloop.stop_loop();
+				simloop.step_once();
+			}
+		});
+		reset.add_button_function({
+			let refstate = refstate.clone();
+			move | _:bool | {
+				refstate.borrow_mut().reset();
+			}
+		});
+		SimControls{ elem, run, step, reset}
+	}
+
+	pub fn run_toggle(&self) -> &Toggle { &self.run }
+
+	pub fn step_button(&self) -> &Button { &self.step }
+
+	pub fn reset_button(&self) -> &Button { &self.reset }
+}
+
+impl UiElement for SimControls {
+	fn elem( &self) -> &HtmlElement { &self.elem }
+}
+
+/// Drives a simulation without a browser.
+///
+/// Instead of animation frames from `request_animation_frame`, the
+/// simulation is fed frames from a synthetic clock that advances by
+/// `frame_dt` (ms) each frame. The frames are turned into updates
+/// and renders exactly as in `Simloop`, but nothing here touches the
+/// DOM, so a simulation can be run under `cargo test` or as a native
+/// batch job.
+#[derive(Debug)]
+pub struct Headless<T: SimStep> {
+	stepper: Stepper,
+	/// Synthetic wall clock (ms)
+	time: f64,
+	/// Time between synthetic frames (ms)
+	frame_dt: f64,
+	pub state: T,
+}
+
+impl<T: SimStep> Headless<T> {
+	/// A headless driver for `state` with frames `frame_dt` (ms) apart.
+	pub fn new( state: T, frame_dt: f64) -> Headless<T> {
+		assert!(frame_dt > 0_f64, "frame time must be positive");
+		Headless{
+			stepper: Stepper{time_old: Some(0_f64), ..Stepper::new()},
+			time: 0_f64,
+			frame_dt,
+			state,
+		}
+	}
+
+	/// See `Simloop::set_fixed_step`.
+	pub fn set_fixed_step(&mut self, dt: f64, max_steps: u32) {
+		self.stepper.set_fixed_step(dt, max_steps);
+	}
+
+	/// See `Simloop::clear_fixed_step`.
+	pub fn clear_fixed_step(&mut self) {
+		self.stepper.clear_fixed_step();
+	}
+
+	/// See `Simloop::alpha`.
+	pub fn alpha(&self) -> f64 {
+		self.stepper.alpha()
+	}
+
+	/// See `Simloop::set_time_scale`.
+	pub fn set_time_scale(&mut self, time_scale: f64) {
+		self.stepper.set_time_scale(time_scale);
+	}
+
+	pub fn time_scale(&self) -> f64 {
+		self.stepper.time_scale
+	}
+
+	/// See `Simloop::step_once`.
+	pub fn step_once(&mut self) {
+		self.stepper.advance(MAX_TIME_STEP, &mut self.state);
+	}
+
+	/// See `Simloop::clock`.
+	pub fn clock(&self) -> SimClock {
+		self.stepper.clock.get()
+	}
+
+	/// See `Simloop::clock_handle`.
+	pub fn clock_handle(&self) -> ClockHandle {
+		self.stepper.clock.clone()
+	}
+
+	/// The synthetic wall clock (ms).
+	pub fn time(&self) -> f64 { self.time }
+
+	/// The time step used by `run_steps`: the fixed step if in fixed
+	/// step mode, otherwise the scaled frame time.
+	pub fn step_size(&self) -> f64 {
+		self.stepper.fixed_dt.unwrap_or(self.stepper.time_scale*self.frame_dt)
+	}
+
+	fn frame(&mut self) {
+		self.time += self.frame_dt;
+		self.stepper.advance_frame(Some(self.time), &mut self.state);
+	}
+
+	/// Run `frames` synthetic animation frames.
+	pub fn run_frames(&mut self, frames: u32) {
+		for _ in 0..frames {
+			self.frame();
+		}
+	}
+
+	/// Run synthetic frames until the clock has advanced `duration` (ms).
+	pub fn run_for(&mut self, duration: f64) {
+		let end = self.time + duration;
+		while self.time < end {
+			self.frame();
+		}
+	}
+
+	/// Update the state `steps` times with `step_size`, bypassing
+	/// the frame logic, then render once.
+	pub fn run_steps(&mut self, steps: u32) {
+		let dt = self.step_size();
+		for _ in 0..steps {
+			self.stepper.update(dt, &mut self.state);
+			self.time += dt;
+		}
+		// Keep the frame logic in step with the clock
+		self.stepper.time_old = Some(self.time);
+		self.stepper.render(0_f64, &mut self.state);
+	}
+
+	/// Run `frames` frames and collect `record(state)` after each one.
+	pub fn run_frames_with<F, R>(&mut self, frames: u32, mut record: F) -> Vec<R>
+		where F: FnMut(&T) -> R {
+		let mut output = Vec::with_capacity(frames as usize);
+		for _ in 0..frames {
+			self.frame();
+			output.push( record(&self.state));
+		}
+		output
+	}
+
+	/// Run `steps` updates and collect `record(state)` after each one.
+	pub fn run_steps_with<F, R>(&mut self, steps: u32, mut record: F) -> Vec<R>
+		where F: FnMut(&T) -> R {
+		let dt = self.step_size();
+		let mut output = Vec::with_capacity(steps as usize);
+		for _ in 0..steps {
+			self.stepper.update(dt, &mut self.state);
+			self.time += dt;
+			output.push( record(&self.state));
+		}
+		self.stepper.time_old = Some(self.time);
+		self.stepper.render(0_f64, &mut self.state);
+		output
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Counts the calls it gets and the total time it was given
+	#[derive(Debug, Default)]
+	struct Counter {
+		updates: u32,
+		renders: u32,
+		time: f64,
+	}
+
+	impl SimStep for Counter {
+		fn update(&mut self, dt: f64) {
+			self.updates += 1;
+			self.time += dt;
+		}
+
+		fn render(&mut self, _alpha: f64) {
+			self.renders += 1;
+		}
+	}
+
+	/// One update and render per frame by default
+	#[test]
+	fn test_headless_frames() {
+		let mut sim = Headless::new(Counter::default(), 10.0);
+		sim.run_frames(5);
+		assert_eq!(sim.state.updates, 5);
+		assert_eq!(sim.state.renders, 5);
+		assert_eq!(sim.state.time, 50.0);
+		assert_eq!(sim.time(), 50.0);
+	}
+
+	/// Long frames are capped at `MAX_TIME_STEP`
+	#[test]
+	fn test_headless_frame_cap() {
+		let mut sim = Headless::new(Counter::default(), 100.0);
+		sim.run_frames(2);
+		assert_eq!(sim.state.time, 2.0*MAX_TIME_STEP);
+	}
+
+	/// A fixed step updates a whole number of times per frame and
+	/// keeps the leftover fraction
+	#[test]
+	fn test_fixed_step() {
+		let mut sim = Headless::new(Counter::default(), 10.0);
+		sim.set_fixed_step(4.0, 8);
+		sim.run_frames(1);
+		assert_eq!(sim.state.updates, 2);
+		assert_eq!(sim.alpha(), 0.5);
+		sim.run_frames(1);
+		assert_eq!(sim.state.updates, 5);
+		assert_eq!(sim.state.renders, 2);
+		assert_eq!(sim.state.time, 20.0);
+	}
+
+	/// Catch up steps are capped and the lost time dropped
+	#[test]
+	fn test_fixed_step_cap() {
+		let mut sim = Headless::new(Counter::default(), 100.0);
+		sim.set_fixed_step(4.0, 3);
+		sim.run_frames(1);
+		assert_eq!(sim.state.updates, 3);
+		assert!(sim.alpha() < 1.0);
+	}
+
+	/// Run by steps or by time and collect output
+	#[test]
+	fn test_headless_steps() {
+		let mut sim = Headless::new(Counter::default(), 10.0);
+		sim.set_fixed_step(2.0, 8);
+		let times = sim.run_steps_with(3, |c| c.time);
+		assert_eq!(times, vec![2.0, 4.0, 6.0]);
+		assert_eq!(sim.state.renders, 1);
+		sim.run_for(100.0);
+		assert_eq!(sim.time(), 106.0);
+		assert_eq!(sim.state.updates, 53);
+	}
+
+	/// The time scale speeds up or slows down the simulation
+	#[test]
+	fn test_time_scale() {
+		let mut sim = Headless::new(Counter::default(), 10.0);
+		sim.set_time_scale(0.5);
+		sim.run_frames(2);
+		assert_eq!(sim.state.time, 10.0);
+		sim.set_fixed_step(2.0, 2);
+		sim.set_time_scale(4.0);
+		sim.run_frames(1);
+		assert_eq!(sim.state.updates, 2+8);
+	}
+
+	/// A single step advances one frame
+	#[test]
+	fn test_step_once() {
+		let mut sim = Headless::new(Counter::default(), 10.0);
+		sim.set_fixed_step(MAX_TIME_STEP/4.0, 8);
+		sim.step_once();
+		assert_eq!(sim.state.updates, 4);
+		assert_eq!(sim.state.renders, 1);
+		assert_eq!(sim.time(), 0.0);
+	}
+
+	/// The first frame after a start doesn't count the time since the
+	/// page loaded: it takes a nominal frame and leaves the wall-clock
+	/// time and frame rate alone
+	#[test]
+	fn test_first_frame() {
+		let mut stepper = Stepper::new();
+		stepper.set_fixed_step(4.0, 8);
+		let mut counter = Counter::default();
+		stepper.advance_frame(None, &mut counter);
+		stepper.advance_frame(Some(60000.0), &mut counter);
+		assert_eq!(counter.updates, 8);
+		assert_eq!(counter.renders, 2);
+		assert_eq!(stepper.clock.get().wall_time, 0.0);
+		assert_eq!(stepper.clock.get().fps, 0.0);
+		stepper.advance_frame(Some(60010.0), &mut counter);
+		assert_eq!(stepper.clock.get().wall_time, 10.0);
+		assert!((stepper.clock.get().fps-100.0).abs() < 1.0e-9);
+		assert!(counter.updates <= 11);
+	}
+
+	/// The clock counts steps, frames and time
+	#[test]
+	fn test_clock() {
+		let mut sim = Headless::new(Counter::default(), 10.0);
+		sim.set_fixed_step(5.0, 8);
+		let handle = sim.clock_handle();
+		sim.run_frames(4);
+		let clock = handle.get();
+		assert_eq!(clock, sim.clock());
+		assert_eq!(clock.steps, 8);
+		assert_eq!(clock.frames, 4);
+		assert_eq!(clock.time, 40.0);
+		assert_eq!(clock.seconds(), 0.04);
+		assert_eq!(clock.wall_time, 40.0);
+		assert!((clock.fps-100.0).abs() < 1.0e-9);
+		sim.run_steps(2);
+		assert_eq!(handle.get().steps, 10);
+		assert_eq!(handle.get().frames, 5);
+	}
+}
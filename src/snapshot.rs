@@ -0,0 +1,375 @@
+/// Simulation snapshots
+/// ====================
+///
+/// A state that implements `Snapshot` can be saved as text and
+/// restored later. `Simloop` uses this to keep a saved snapshot,
+/// and `SnapshotControls` adds buttons to save and restore it and to
+/// download and upload snapshots as files.
+///
+/// The text format is one value per line: a key, followed by the
+/// numbers that make up the value, separated by spaces. The
+/// `SnapshotWriter` and `SnapshotReader` take care of the format so
+/// a state only has to list its values.
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::str::FromStr;
+
+use stdweb::traits::*;
+use stdweb::unstable::TryInto;
+use stdweb::web::{
+	HtmlElement,
+	document,
+	FileList,
+	FileReader,
+	FileReaderResult,
+};
+use stdweb::web::html_element::InputElement;
+use stdweb::web::event::{
+	ChangeEvent,
+	LoadEndEvent,
+};
+
+use ::container::UiElement;
+use ::control::Button;
+use ::simple_vec::Vec2;
+use ::simulation::{
+	SimStep,
+	Simloop,
+};
+
+/// A state that can be saved to, and restored from, text.
+pub trait Snapshot {
+	/// Save the state as text.
+	fn snapshot(&self) -> String;
+
+	/// Restore the state from text made by `snapshot`. On an error
+	/// the state should be left unchanged.
+	fn restore(&mut self, snapshot: &str) -> Result<(), SnapshotError>;
+}
+
+/// The ways restoring a snapshot can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+	/// The snapshot has no value with this key
+	Missing(String),
+	/// The value with this key could not be read
+	BadValue(String),
+	/// The snapshot doesn't fit the state, with an explanation
+	Mismatch(String),
+	/// There is no saved snapshot to restore
+	NoSnapshot,
+}
+
+impl fmt::Display for SnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SnapshotError::Missing(key) => write!(f, "snapshot is missing `{}`", key),
+			SnapshotError::BadValue(key) => write!(f, "snapshot has a bad value for `{}`", key),
+			SnapshotError::Mismatch(why) => write!(f, "snapshot doesn't match: {}", why),
+			SnapshotError::NoSnapshot => write!(f, "no snapshot saved"),
+		}
+	}
+}
+
+/// Builds the text of a snapshot one value at a time.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotWriter {
+	text: String,
+}
+
+impl SnapshotWriter {
+	pub fn new() -> SnapshotWriter {
+		SnapshotWriter{ text: String::new() }
+	}
+
+	fn line<I: Iterator<Item=f64>>(&mut self, key: &str, values: I) -> &mut Self {
+		assert!(!key.is_empty() && !key.contains(char::is_whitespace),
+			"snapshot keys can't be empty or contain whitespace");
+		self.text.push_str(key);
+		for value in values {
+			// Debug formatting of floats round trips exactly
+			self.text.push_str(&format!(" {:?}", value));
+		}
+		self.text.push('\n');
+		self
+	}
+
+	pub fn f64(&mut self, key: &str, value: f64) -> &mut Self {
+		self.line(key, Some(value).into_iter())
+	}
+
+	pub fn f64s(&mut self, key: &str, values: &[f64]) -> &mut Self {
+		self.line(key, values.iter().cloned())
+	}
+
+	pub fn u64(&mut self, key: &str, value: u64) -> &mut Self {
+		self.text.push_str(&format!("{} {}\n", key, value));
+		self
+	}
+
+	pub fn bool(&mut self, key: &str, value: bool) -> &mut Self {
+		self.text.push_str(&format!("{} {}\n", key, value));
+		self
+	}
+
+	pub fn vec2(&mut self, key: &str, value: Vec2) -> &mut Self {
+		self.line(key, vec!(value.x, value.y).into_iter())
+	}
+
+	pub fn vec2s(&mut self, key: &str, values: &[Vec2]) -> &mut Self {
+		self.line(key, values.iter().flat_map( |v| vec!(v.x, v.y)))
+	}
+
+	pub fn finish(&self) -> String {
+		self.text.clone()
+	}
+}
+
+/// Reads the values back out of the text of a snapshot.
+#[derive(Debug, Clone)]
+pub struct SnapshotReader<'a> {
+	values: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> SnapshotReader<'a> {
+	pub fn new(text: &'a str) -> SnapshotReader<'a> {
+		let mut values = HashMap::new();
+		for line in text.lines() {
+			let mut words = line.split_whitespace();
+			if let Some(key) = words.next() {
+				values.insert(key, words.collect());
+			}
+		}
+		SnapshotReader{ values }
+	}
+
+	fn words(&self, key: &str) -> Result<&[&'a str], SnapshotError> {
+		match self.values.get(key) {
+			Some(words) => Ok(words),
+			None => Err(SnapshotError::Missing(String::from(key))),
+		}
+	}
+
+	fn parse<T: FromStr>(&self, key: &str) -> Result<Vec<T>, SnapshotError> {
+		let mut parsed = Vec::new();
+		for word in self.words(key)? {
+			match word.parse() {
+				Ok(value) => parsed.push(value),
+				Err(_) => return Err(SnapshotError::BadValue(String::from(key))),
+			}
+		}
+		Ok(parsed)
+	}
+
+	fn single<T: FromStr>(&self, key: &str) -> Result<T, SnapshotError> {
+		let mut values = self.parse(key)?;
+		if values.len() != 1 {
+			return Err(SnapshotError::BadValue(String::from(key)));
+		}
+		Ok(values.remove(0))
+	}
+
+	pub fn has(&self, key: &str) -> bool {
+		self.values.contains_key(key)
+	}
+
+	pub fn f64(&self, key: &str) -> Result<f64, SnapshotError> {
+		self.single(key)
+	}
+
+	pub fn f64s(&self, key: &str) -> Result<Vec<f64>, SnapshotError> {
+		self.parse(key)
+	}
+
+	pub fn u64(&self, key: &str) -> Result<u64, SnapshotError> {
+		self.single(key)
+	}
+
+	pub fn bool(&self, key: &str) -> Result<bool, SnapshotError> {
+		self.single(key)
+	}
+
+	pub fn vec2(&self, key: &str) -> Result<Vec2, SnapshotError> {
+		let values = self.vec2s(key)?;
+		if values.len() != 1 {
+			return Err(SnapshotError::BadValue(String::from(key)));
+		}
+		Ok(values[0])
+	}
+
+	pub fn vec2s(&self, key: &str) -> Result<Vec<Vec2>, SnapshotError> {
+		let values : Vec<f64> = self.parse(key)?;
+		let pairs = values.chunks_exact(2);
+		if !pairs.remainder().is_empty() {
+			return Err(SnapshotError::BadValue(String::from(key)));
+		}
+		Ok(pairs.map( |xy| Vec2{x: xy[0], y: xy[1]}).collect())
+	}
+}
+
+/// Report a snapshot error on the browser console.
+fn report( error: SnapshotError) {
+	let message = error.to_string();
+	js!{ @(no_return)
+		console.error(@{message});
+	};
+}
+
+/// Offer `text` to the user as a download named `filename`.
+pub fn download( filename: &str, text: &str) {
+	js!{ @(no_return)
+		var blob = new Blob([@{text}], {type: "text/plain"});
+		var url = URL.createObjectURL(blob);
+		var link = document.createElement("a");
+		link.href = url;
+		link.download = @{filename};
+		document.body.appendChild(link);
+		link.click();
+		document.body.removeChild(link);
+		URL.revokeObjectURL(url);
+	};
+}
+
+/// Controls for the snapshots of a `Simloop`: Save and Restore
+/// buttons that keep a snapshot in the loop, a Download button that
+/// saves the current state to a file, and an Upload file picker that
+/// loads a state from a file. Errors are reported on the console.
+#[derive(Debug, Clone)]
+pub struct SnapshotControls {
+	elem: HtmlElement,
+	save: Button,
+	restore: Button,
+	download: Button,
+	upload: InputElement,
+}
+
+impl SnapshotControls {
+	pub fn new<T>( name: &str, filename: &str, refstate: Rc<RefCell<Simloop<T>>>) -> SnapshotControls
+		where T: SimStep + Snapshot + 'static {
+		let elem : HtmlElement = document().create_element("span").unwrap().try_into().unwrap();
+		elem.class_list().add("control").unwrap();
+		elem.set_attribute("id", name).unwrap();
+		elem.set_attribute("data-param-type", "snapshot").unwrap();
+		let save = Button::new( &format!("{}_save",name), "Save");
+		let restore = Button::new( &format!("{}_restore",name), "Restore");
+		let download_button = Button::new( &format!("{}_download",name), "Download");
+		let upload : InputElement = document().create_element("input").unwrap().try_into().unwrap();
+		upload.class_list().add("control_upload").unwrap();
+		upload.set_attribute("type", "file").unwrap();
+		upload.set_attribute("id", &format!("{}_upload",name)).unwrap();
+		elem.append_child( save.elem());
+		elem.append_child( restore.elem());
+		elem.append_child( download_button.elem());
+		elem.append_child( &upload);
+
+		save.add_button_function({
+			let refstate = refstate.clone();
+			move | _:bool | {
+				refstate.borrow_mut().save_snapshot();
+			}
+		});
+		restore.add_button_function({
+			let refstate = refstate.clone();
+			move | _:bool | {
+				if let Err(error) = refstate.borrow_mut().restore_snapshot() {
+					report(error);
+				}
+			}
+		});
+		download_button.add_button_function({
+			let refstate = refstate.clone();
+			let filename = String::from(filename);
+			move | _:bool | {
+				let text = refstate.borrow().state.snapshot();
+				download( &filename, &text);
+			}
+		});
+		upload.add_event_listener({
+			let upload = upload.clone();
+			let refstate = refstate.clone();
+			move | _:ChangeEvent | {
+				let files : Option<FileList> = js!( return @{&upload}.files; ).try_into().ok();
+				let file = match files.and_then( |files| files.iter().next()) {
+					Some(file) => file,
+					None => return,
+				};
+				let reader = FileReader::new();
+				reader.add_event_listener({
+					let reader = reader.clone();
+					let refstate = refstate.clone();
+					move | _:LoadEndEvent | {
+						if let Some(FileReaderResult::String(text)) = reader.result() {
+							if let Err(error) = refstate.borrow_mut().load_snapshot(&text) {
+								report(error);
+							}
+						}
+					}
+				});
+				let _ = reader.read_as_text(&file);
+				// Clear the picker, so choosing the same file again
+				// still fires a change and loads it
+				upload.set_raw_value("");
+			}
+		});
+		SnapshotControls{ elem, save, restore, download: download_button, upload}
+	}
+
+	pub fn save_button(&self) -> &Button {
+		&self.save
+	}
+
+	pub fn restore_button(&self) -> &Button {
+		&self.restore
+	}
+
+	pub fn download_button(&self) -> &Button {
+		&self.download
+	}
+
+	pub fn upload_input(&self) -> &InputElement {
+		&self.upload
+	}
+}
+
+impl UiElement for SnapshotControls {
+	fn elem( &self) -> &HtmlElement { &self.elem }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Values come back out exactly as they went in
+	#[test]
+	fn test_round_trip() {
+		let points = [Vec2{x: 0.1, y: -2.5e-12}, Vec2{x: 1.0/3.0, y: 7.0}];
+		let text = SnapshotWriter::new()
+			.f64("t", 0.1+0.2)
+			.u64("n", 42)
+			.bool("on", true)
+			.vec2("r", Vec2{x: 1.5, y: -0.25})
+			.vec2s("points", &points)
+			.f64s("empty", &[])
+			.finish();
+		let reader = SnapshotReader::new(&text);
+		assert_eq!(reader.f64("t"), Ok(0.1+0.2));
+		assert_eq!(reader.u64("n"), Ok(42));
+		assert_eq!(reader.bool("on"), Ok(true));
+		assert_eq!(reader.vec2("r"), Ok(Vec2{x: 1.5, y: -0.25}));
+		assert_eq!(reader.vec2s("points"), Ok(points.to_vec()));
+		assert_eq!(reader.f64s("empty"), Ok(vec!()));
+		assert!(reader.has("t"));
+	}
+
+	/// Missing and malformed values are errors
+	#[test]
+	fn test_errors() {
+		let reader = SnapshotReader::new("t 1.0 2.0\nr 1.0\nx abc\n");
+		assert_eq!(reader.f64("y"), Err(SnapshotError::Missing(String::from("y"))));
+		assert_eq!(reader.f64("t"), Err(SnapshotError::BadValue(String::from("t"))));
+		assert_eq!(reader.vec2s("r"), Err(SnapshotError::BadValue(String::from("r"))));
+		assert_eq!(reader.f64("x"), Err(SnapshotError::BadValue(String::from("x"))));
+	}
+}
@@ -0,0 +1,341 @@
+/// Mass-spring networks
+/// ====================
+///
+/// A `SpringNetwork` is a set of point masses (`Node`s) joined by
+/// damped springs (`Spring`s), in a uniform gravitational field. Nodes
+/// can be pinned in place to hang a chain or a cloth from.
+///
+/// The network is an `integrate::OdeSystem` with the positions and
+/// velocities of the nodes as its state, so it can be stepped with any
+/// of the stock integrators, e.g. `network.step(&Rk4, dt)`. It draws
+/// itself with `extra::spring` for every spring and `Graphic::circle`
+/// for every node.
+///
+/// `chain` and `cloth` build the common networks.
+use ::diagnostics::Conserved;
+use ::extra;
+use ::gfx::Graphic;
+use ::integrate::{OdeSystem, Integrator};
+use ::simple_vec::Vec2;
+
+/// A point mass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Node {
+	pub r: Vec2,
+	pub v: Vec2,
+	pub mass: f64,
+	/// Pinned nodes don't move
+	pub pinned: bool,
+}
+
+/// A damped spring between nodes `i` and `j`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spring {
+	pub i: usize,
+	pub j: usize,
+	pub rest_length: f64,
+	/// Spring constant k, force per unit stretch
+	pub stiffness: f64,
+	/// Damping coefficient, force per unit rate of stretching
+	pub damping: f64,
+}
+
+/// Point masses joined by springs.
+#[derive(Debug, Clone)]
+pub struct SpringNetwork {
+	pub nodes: Vec<Node>,
+	pub springs: Vec<Spring>,
+	/// Acceleration due to gravity
+	pub gravity: Vec2,
+	pub time: f64,
+	/// Radius of the circles drawn for the nodes
+	pub node_radius: f64,
+	/// Loops in each drawn spring
+	pub loops: u32,
+}
+
+impl SpringNetwork {
+	/// An empty network.
+	pub fn new(gravity: Vec2) -> SpringNetwork {
+		SpringNetwork{
+			nodes: Vec::new(),
+			springs: Vec::new(),
+			gravity,
+			time: 0_f64,
+			node_radius: 0.05,
+			loops: 6,
+		}
+	}
+
+	/// A chain of `n` nodes of mass `mass` evenly spaced from `start`
+	/// to `end`, each joined to the next by a spring at its rest
+	/// length. The ends are pinned.
+	pub fn chain(start: Vec2, end: Vec2, n: usize, mass: f64, stiffness: f64, damping: f64) -> SpringNetwork {
+		assert!(n >= 2, "a chain needs at least two nodes");
+		let mut network = SpringNetwork::new(Vec2::zero());
+		for k in 0..n {
+			let s = k as f64/(n - 1) as f64;
+			network.add_node(start + s*(end - start), mass);
+		}
+		for k in 1..n {
+			network.connect(k - 1, k, stiffness, damping);
+		}
+		network.nodes[0].pinned = true;
+		network.nodes[n-1].pinned = true;
+		network
+	}
+
+	/// A `nx` by `ny` grid of nodes `spacing` apart, hanging down from
+	/// `top_left` with its top corners pinned, joined by springs to
+	/// their neighbors along the grid and across the diagonals.
+	pub fn cloth(
+		top_left: Vec2,
+		nx: usize,
+		ny: usize,
+		spacing: f64,
+		mass: f64,
+		stiffness: f64,
+		damping: f64) -> SpringNetwork {
+		assert!(nx >= 2 && ny >= 2, "a cloth needs at least two nodes each way");
+		let mut network = SpringNetwork::new(Vec2::zero());
+		for row in 0..ny {
+			for col in 0..nx {
+				network.add_node(top_left + Vec2{x: col as f64*spacing, y: -(row as f64)*spacing}, mass);
+			}
+		}
+		let index = |col: usize, row: usize| row*nx + col;
+		for row in 0..ny {
+			for col in 0..nx {
+				if col + 1 < nx {
+					network.connect(index(col, row), index(col + 1, row), stiffness, damping);
+				}
+				if row + 1 < ny {
+					network.connect(index(col, row), index(col, row + 1), stiffness, damping);
+				}
+				if col + 1 < nx && row + 1 < ny {
+					network.connect(index(col, row), index(col + 1, row + 1), stiffness, damping);
+					network.connect(index(col + 1, row), index(col, row + 1), stiffness, damping);
+				}
+			}
+		}
+		network.nodes[index(0, 0)].pinned = true;
+		network.nodes[index(nx - 1, 0)].pinned = true;
+		network
+	}
+
+	/// Add a node at rest at `r`, returning its index.
+	pub fn add_node(&mut self, r: Vec2, mass: f64) -> usize {
+		assert!(mass > 0_f64, "mass must be positive");
+		self.nodes.push(Node{r, v: Vec2::zero(), mass, pinned: false});
+		self.nodes.len() - 1
+	}
+
+	/// Add a pinned node at `r`, returning its index.
+	pub fn add_pinned(&mut self, r: Vec2) -> usize {
+		let i = self.add_node(r, 1_f64);
+		self.nodes[i].pinned = true;
+		i
+	}
+
+	/// Join nodes `i` and `j` with a spring at its rest length where
+	/// they are now, returning its index.
+	pub fn connect(&mut self, i: usize, j: usize, stiffness: f64, damping: f64) -> usize {
+		let rest_length = (self.nodes[j].r - self.nodes[i].r).norm();
+		self.springs.push(Spring{i, j, rest_length, stiffness, damping});
+		self.springs.len() - 1
+	}
+
+	/// The positions and velocities of the nodes.
+	pub fn state(&self) -> (Vec<Vec2>, Vec<Vec2>) {
+		(self.nodes.iter().map( |n| n.r).collect(), self.nodes.iter().map( |n| n.v).collect())
+	}
+
+	/// Set the positions and velocities of the nodes.
+	pub fn set_state(&mut self, state: &(Vec<Vec2>, Vec<Vec2>)) {
+		for ((node, &r), &v) in self.nodes.iter_mut().zip(state.0.iter()).zip(state.1.iter()) {
+			node.r = r;
+			node.v = v;
+		}
+	}
+
+	/// The forces on the nodes at positions `r` and velocities `v`,
+	/// from the springs and gravity.
+	pub fn forces(&self, r: &[Vec2], v: &[Vec2]) -> Vec<Vec2> {
+		let mut f : Vec<Vec2> = self.nodes.iter().map( |n| n.mass*self.gravity).collect();
+		for s in self.springs.iter() {
+			let d = r[s.j] - r[s.i];
+			let length = d.norm();
+			if length == 0_f64 {
+				continue;
+			}
+			let u = d/length;
+			let tension = s.stiffness*(length - s.rest_length) + s.damping*(v[s.j] - v[s.i]).dot(u);
+			f[s.i] = f[s.i] + tension*u;
+			f[s.j] = f[s.j] - tension*u;
+		}
+		f
+	}
+
+	/// The kinetic energy of the nodes.
+	pub fn kinetic_energy(&self) -> f64 {
+		self.nodes.iter().filter( |n| !n.pinned).map( |n| 0.5*n.mass*n.v.norm_squared()).sum()
+	}
+
+	/// The potential energy of the springs and of the nodes in gravity.
+	pub fn potential_energy(&self) -> f64 {
+		let springs : f64 = self.springs.iter().map( |s| {
+			let stretch = (self.nodes[s.j].r - self.nodes[s.i].r).norm() - s.rest_length;
+			0.5*s.stiffness*stretch*stretch
+		}).sum();
+		let gravity : f64 = self.nodes.iter().filter( |n| !n.pinned)
+			.map( |n| -n.mass*self.gravity.dot(n.r))
+			.sum();
+		springs + gravity
+	}
+
+	pub fn energy(&self) -> f64 {
+		self.kinetic_energy() + self.potential_energy()
+	}
+
+	/// Advance the network by a time `dt` with `integrator`.
+	pub fn step<I: Integrator>(&mut self, integrator: &I, dt: f64) {
+		let state = integrator.step(self, self.time, &self.state(), dt);
+		self.set_state(&state);
+		self.time += dt;
+	}
+
+	/// The network drawn as springs and circles.
+	pub fn graphic(&self) -> Graphic {
+		let mut graphic = Graphic::collection(&[]);
+		for s in self.springs.iter() {
+			let (p0, p1) = (self.nodes[s.i].r, self.nodes[s.j].r);
+			if p0 != p1 {
+				graphic.add(extra::spring(self.loops, s.rest_length, p0, p1));
+			}
+		}
+		for node in self.nodes.iter() {
+			graphic.add(Graphic::circle(node.r, self.node_radius));
+		}
+		graphic
+	}
+}
+
+impl OdeSystem for SpringNetwork {
+	type State = (Vec<Vec2>, Vec<Vec2>);
+
+	fn derivative(&self, _t: f64, y: &Self::State) -> Self::State {
+		let (ref r, ref v) = *y;
+		let f = self.forces(r, v);
+		let mut dr = v.clone();
+		let mut dv : Vec<Vec2> = f.iter().zip(self.nodes.iter()).map( |(&f, n)| f/n.mass).collect();
+		for (k, node) in self.nodes.iter().enumerate() {
+			if node.pinned {
+				dr[k] = Vec2::zero();
+				dv[k] = Vec2::zero();
+			}
+		}
+		(dr, dv)
+	}
+}
+
+impl Conserved for SpringNetwork {
+	fn kinetic_energy(&self) -> f64 {
+		SpringNetwork::kinetic_energy(self)
+	}
+
+	fn potential_energy(&self) -> f64 {
+		SpringNetwork::potential_energy(self)
+	}
+
+	fn momentum(&self) -> Vec2 {
+		self.nodes.iter().filter( |n| !n.pinned).fold(Vec2::zero(), |p, n| p + n.mass*n.v)
+	}
+
+	fn angular_momentum(&self) -> f64 {
+		self.nodes.iter().filter( |n| !n.pinned).map( |n| n.mass*n.r.cross(n.v)).sum()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ::integrate::Rk4;
+
+	/// Two masses on a spring oscillate with the reduced mass,
+	/// ω = sqrt(k/μ), keeping their energy
+	#[test]
+	fn test_pair() {
+		let mut network = SpringNetwork::new(Vec2::zero());
+		network.add_node(Vec2{x: 0.0, y: 0.0}, 1.0);
+		network.add_node(Vec2{x: 1.0, y: 0.0}, 3.0);
+		network.connect(0, 1, 12.0, 0.0);
+		network.nodes[1].r.x = 1.2;
+		let e0 = network.energy();
+		let mu = 3.0/4.0;
+		let period = 2.0*::std::f64::consts::PI*(mu/12.0_f64).sqrt();
+		let steps = 1000;
+		for _ in 0..steps {
+			network.step(&Rk4, period/steps as f64);
+		}
+		assert!((network.nodes[1].r.x - 1.2).abs() < 1e-6, "after a period at {}", network.nodes[1].r.x);
+		assert!((network.energy() - e0).abs() < 1e-9);
+	}
+
+	/// Damping takes energy out
+	#[test]
+	fn test_damping() {
+		let mut network = SpringNetwork::chain(Vec2::zero(), Vec2{x: 1.0, y: 0.0}, 5, 0.1, 50.0, 0.5);
+		network.gravity = Vec2{x: 0.0, y: -9.8};
+		let mut last = network.energy();
+		for _ in 0..20 {
+			for _ in 0..10 {
+				network.step(&Rk4, 0.001);
+			}
+			let energy = network.energy();
+			assert!(energy < last);
+			last = energy;
+		}
+		assert_eq!(network.nodes[0].r, Vec2::zero());
+		assert_eq!(network.nodes[4].r, Vec2{x: 1.0, y: 0.0});
+	}
+
+	/// A transverse normal mode of a taut chain of N moving masses
+	/// comes back to its shape after a period, with ω_n = 2 sqrt(T/(m a)) sin(nπ/2(N+1))
+	/// for tension T and spacing a
+	#[test]
+	fn test_normal_mode() {
+		let n_moving = 6;
+		let (m, k) = (0.2, 400.0);
+		// Stretch the chain to twice its rest length, for a tension k a/2
+		let mut network = SpringNetwork::chain(Vec2::zero(), Vec2{x: 7.0, y: 0.0}, n_moving + 2, m, k, 0.0);
+		for s in network.springs.iter_mut() {
+			s.rest_length *= 0.5;
+		}
+		let a = 1.0;
+		let tension = k*(a - 0.5*a);
+		let mode = 2;
+		let shape = |j: usize| 0.001*(mode as f64*::std::f64::consts::PI*j as f64/(n_moving + 1) as f64).sin();
+		for j in 1..=n_moving {
+			network.nodes[j].r.y = shape(j);
+		}
+		let arg = mode as f64*::std::f64::consts::PI/(2.0*(n_moving + 1) as f64);
+		let omega = 2.0*(tension/(m*a)).sqrt()*arg.sin();
+		let period = 2.0*::std::f64::consts::PI/omega;
+		let steps = 2000;
+		for _ in 0..steps {
+			network.step(&Rk4, period/steps as f64);
+		}
+		for j in 1..=n_moving {
+			assert!((network.nodes[j].r.y - shape(j)).abs() < 1e-5, "node {} at {}", j, network.nodes[j].r.y);
+		}
+	}
+
+	#[test]
+	fn test_cloth() {
+		let network = SpringNetwork::cloth(Vec2::zero(), 4, 3, 0.5, 0.1, 10.0, 0.1);
+		assert_eq!(network.nodes.len(), 12);
+		// 3 per row across × 3 rows, 2 per column down × 4 columns, 2 diagonals × 6 squares
+		assert_eq!(network.springs.len(), 3*3 + 4*2 + 2*3*2);
+		assert!(network.nodes[0].pinned && network.nodes[3].pinned && !network.nodes[1].pinned);
+	}
+}
@@ -0,0 +1,234 @@
+/// Symplectic integrators
+/// ======================
+///
+/// Integrators for systems with a separable Hamiltonian,
+/// H(q, p) = T(p) + V(q), where the positions `q` and momenta `p` of
+/// the particles are `Vec2`s. Each integrator is a sequence of drifts
+/// (moving the positions with the velocities dT/dp) and kicks
+/// (changing the momenta with the forces -dV/dq). Compositions like
+/// this are symplectic, so the energy error stays bounded over very
+/// long runs instead of drifting as it does with Euler or RK4.
+///
+/// - `StormerVerlet`, second order, two force evaluations per step
+/// - `Yoshida4`, fourth order, four force evaluations per step
+/// - `ForestRuth`, fourth order, three force evaluations per step
+use ::simple_vec::Vec2;
+
+/// A system with a separable Hamiltonian H(q, p) = T(p) + V(q).
+pub trait SeparableHamiltonian {
+	/// The kinetic energy T(p).
+	fn kinetic(&self, p: &[Vec2]) -> f64;
+
+	/// The potential energy V(q).
+	fn potential(&self, q: &[Vec2]) -> f64;
+
+	/// The velocities dT/dp, written into `dq`.
+	fn velocity(&self, p: &[Vec2], dq: &mut [Vec2]);
+
+	/// The forces -dV/dq, written into `dp`.
+	fn force(&self, q: &[Vec2], dp: &mut [Vec2]);
+
+	/// The total energy H(q, p).
+	fn energy(&self, q: &[Vec2], p: &[Vec2]) -> f64 {
+		self.kinetic(p) + self.potential(q)
+	}
+}
+
+/// One part of a splitting scheme, with its fraction of the time step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Substep {
+	/// Move the positions
+	Drift(f64),
+	/// Change the momenta
+	Kick(f64),
+}
+
+/// Run the substeps of a splitting scheme for a time step `dt`.
+fn split<H: SeparableHamiltonian>(
+	substeps: &[Substep],
+	system: &H,
+	q: &mut [Vec2],
+	p: &mut [Vec2],
+	dt: f64) {
+	assert_eq!(q.len(), p.len(), "positions and momenta differ in length");
+	let mut rate = vec!(Vec2::zero(); q.len());
+	for substep in substeps.iter() {
+		match *substep {
+			Substep::Drift(c) => {
+				system.velocity(p, &mut rate);
+				for (q, dq) in q.iter_mut().zip(rate.iter()) {
+					*q = *q + (c*dt)*(*dq);
+				}
+			},
+			Substep::Kick(d) => {
+				system.force(q, &mut rate);
+				for (p, dp) in p.iter_mut().zip(rate.iter()) {
+					*p = *p + (d*dt)*(*dp);
+				}
+			},
+		}
+	}
+}
+
+/// The weights of Yoshida's triple jump, (w1, w0, w1), which turn a
+/// symmetric second order step into a fourth order one.
+fn triple_jump() -> (f64, f64) {
+	let cbrt2 = 2_f64.cbrt();
+	let w1 = 1_f64/(2_f64 - cbrt2);
+	let w0 = -cbrt2*w1;
+	(w1, w0)
+}
+
+/// A scheme for advancing a `SeparableHamiltonian` by one time step.
+pub trait SymplecticIntegrator {
+	/// Advance the positions `q` and momenta `p` by a time `dt`.
+	fn step<H: SeparableHamiltonian>(&self, system: &H, q: &mut [Vec2], p: &mut [Vec2], dt: f64);
+}
+
+/// Störmer-Verlet (kick-drift-kick, the same as velocity Verlet),
+/// second order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StormerVerlet;
+
+impl SymplecticIntegrator for StormerVerlet {
+	fn step<H: SeparableHamiltonian>(&self, system: &H, q: &mut [Vec2], p: &mut [Vec2], dt: f64) {
+		let substeps = [Substep::Kick(0.5), Substep::Drift(1.0), Substep::Kick(0.5)];
+		split(&substeps, system, q, p, dt);
+	}
+}
+
+/// Yoshida's fourth order scheme: three Störmer-Verlet steps of
+/// sizes w1, w0 and w1 times the time step, with the neighbouring
+/// kicks merged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Yoshida4;
+
+impl SymplecticIntegrator for Yoshida4 {
+	fn step<H: SeparableHamiltonian>(&self, system: &H, q: &mut [Vec2], p: &mut [Vec2], dt: f64) {
+		let (w1, w0) = triple_jump();
+		let substeps = [
+			Substep::Kick(0.5*w1),
+			Substep::Drift(w1),
+			Substep::Kick(0.5*(w1 + w0)),
+			Substep::Drift(w0),
+			Substep::Kick(0.5*(w0 + w1)),
+			Substep::Drift(w1),
+			Substep::Kick(0.5*w1),
+		];
+		split(&substeps, system, q, p, dt);
+	}
+}
+
+/// The Forest-Ruth fourth order scheme, the same triple jump built
+/// from drift-kick-drift steps, so it starts and ends with a drift.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ForestRuth;
+
+impl SymplecticIntegrator for ForestRuth {
+	fn step<H: SeparableHamiltonian>(&self, system: &H, q: &mut [Vec2], p: &mut [Vec2], dt: f64) {
+		let (theta, _) = triple_jump();
+		let substeps = [
+			Substep::Drift(0.5*theta),
+			Substep::Kick(theta),
+			Substep::Drift(0.5*(1_f64 - theta)),
+			Substep::Kick(1_f64 - 2_f64*theta),
+			Substep::Drift(0.5*(1_f64 - theta)),
+			Substep::Kick(theta),
+			Substep::Drift(0.5*theta),
+		];
+		split(&substeps, system, q, p, dt);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The mass on a spring from the sho example: a spring with rest
+	/// length `L0` hanging from the origin, in gravity
+	struct Sho {
+		m: f64,
+		k: f64,
+		g: f64,
+	}
+
+	const L0 : f64 = 0.20;
+
+	impl SeparableHamiltonian for Sho {
+		fn kinetic(&self, p: &[Vec2]) -> f64 {
+			p[0].norm_squared()/(2_f64*self.m)
+		}
+
+		fn potential(&self, q: &[Vec2]) -> f64 {
+			let stretch = q[0].norm() - L0;
+			0.5*self.k*stretch*stretch + self.m*self.g*q[0].y
+		}
+
+		fn velocity(&self, p: &[Vec2], dq: &mut [Vec2]) {
+			dq[0] = p[0]/self.m;
+		}
+
+		fn force(&self, q: &[Vec2], dp: &mut [Vec2]) {
+			let r = q[0].norm();
+			dp[0] = -(self.k*(r - L0)/r)*q[0] + Vec2{x: 0.0, y: -self.m*self.g};
+		}
+	}
+
+	/// The largest energy errors over the first and the last tenth of
+	/// a million steps of the sho.
+	fn energy_errors<I: SymplecticIntegrator>(integrator: I, dt: f64) -> (f64, f64) {
+		let sho = Sho{m: 0.25, k: 10.0, g: 9.8};
+		let mut q = [Vec2{x: 0.05, y: -0.3}];
+		let mut p = [Vec2::zero()];
+		let e0 = sho.energy(&q, &p);
+		let steps = 1_000_000;
+		let mut first = 0_f64;
+		let mut last = 0_f64;
+		for i in 0..steps {
+			integrator.step(&sho, &mut q, &mut p, dt);
+			let error = ((sho.energy(&q, &p) - e0)/e0).abs();
+			if i < steps/10 {
+				first = first.max(error);
+			} else if i >= steps - steps/10 {
+				last = last.max(error);
+			}
+		}
+		(first, last)
+	}
+
+	/// The energy error stays small and doesn't grow over a million
+	/// steps, about a thousand periods
+	#[test]
+	fn test_bounded_energy_error() {
+		let (first, last) = energy_errors(StormerVerlet, 1.0e-3);
+		assert!(first < 1.0e-3 && last < 1.5*first, "verlet: {} then {}", first, last);
+		let (first, last) = energy_errors(Yoshida4, 1.0e-3);
+		assert!(first < 1.0e-7 && last < 1.5*first, "yoshida: {} then {}", first, last);
+		let (first, last) = energy_errors(ForestRuth, 1.0e-3);
+		assert!(first < 1.0e-7 && last < 1.5*first, "forest-ruth: {} then {}", first, last);
+	}
+
+	/// Halving the step cuts the energy error by 2^order
+	#[test]
+	fn test_order() {
+		fn error<I: SymplecticIntegrator>(integrator: I, steps: u32) -> f64 {
+			let sho = Sho{m: 1.0, k: 1.0, g: 0.0};
+			let mut q = [Vec2{x: 1.0, y: 0.0}];
+			let mut p = [Vec2::zero()];
+			let e0 = sho.energy(&q, &p);
+			let dt = 1_f64/steps as f64;
+			let mut error = 0_f64;
+			for _ in 0..steps {
+				integrator.step(&sho, &mut q, &mut p, dt);
+				error = error.max((sho.energy(&q, &p) - e0).abs());
+			}
+			error
+		}
+		let verlet = (error(StormerVerlet, 50)/error(StormerVerlet, 100)).log2();
+		let yoshida = (error(Yoshida4, 10)/error(Yoshida4, 20)).log2();
+		let forest_ruth = (error(ForestRuth, 10)/error(ForestRuth, 20)).log2();
+		assert!((verlet - 2_f64).abs() < 0.2, "verlet order {}", verlet);
+		assert!((yoshida - 4_f64).abs() < 0.3, "yoshida order {}", yoshida);
+		assert!((forest_ruth - 4_f64).abs() < 0.3, "forest-ruth order {}", forest_ruth);
+	}
+}
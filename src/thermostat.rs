@@ -0,0 +1,376 @@
+/// Thermostats and barostats
+/// ==========================
+///
+/// Thermostats hold a set of particles at a temperature by acting on
+/// their velocities between the steps of an integrator. Each works on
+/// a slice of `Vec2` velocities with a matching slice of masses, and
+/// temperatures are given as the thermal energy kT in the same units
+/// as m v².
+///
+/// - `VelocityRescale` scales the velocities to the exact temperature
+///   every time, which fixes the kinetic energy rather than sampling
+///   any ensemble
+/// - `Berendsen` relaxes the temperature towards the target over a
+///   time `tau`, smoothly, but suppressing its fluctuations
+/// - `Andersen` gives random particles fresh velocities from the
+///   Maxwell-Boltzmann distribution, sampling the canonical ensemble
+/// - `NoseHoover` adds a friction variable with its own dynamics,
+///   deterministic and time reversible, with a conserved energy
+/// - `Langevin` adds friction and random kicks to every particle, the
+///   exact velocity update from the BAOAB scheme in `sde`
+///
+/// The kinetic temperature counts two degrees of freedom for each
+/// particle.
+///
+/// A barostat holds the pressure instead, by changing the size of the
+/// box. `pressure` finds it from the virial theorem, and
+/// `BerendsenBarostat` rescales the box and the positions to relax it
+/// towards a target, the pressure analogue of `Berendsen`.
+use ::boundary::Boundary;
+use ::simple_rng::NormalDist;
+use ::simple_vec::Vec2;
+
+/// The total kinetic energy, the sum of m v²/2.
+pub fn kinetic_energy(v: &[Vec2], m: &[f64]) -> f64 {
+	assert_eq!(v.len(), m.len(), "velocities and masses differ in length");
+	v.iter().zip(m.iter()).map( |(v, &m)| 0.5*m*v.norm_squared()).sum()
+}
+
+/// The kinetic temperature kT, from equipartition over two degrees of
+/// freedom per particle. Zero with no particles.
+pub fn kinetic_temperature(v: &[Vec2], m: &[f64]) -> f64 {
+	if v.is_empty() {
+		0_f64
+	} else {
+		kinetic_energy(v, m)/v.len() as f64
+	}
+}
+
+/// A velocity drawn from the Maxwell-Boltzmann distribution for a
+/// particle of mass `m` at a temperature `kt`.
+fn maxwell(rng: &mut NormalDist, kt: f64, m: f64) -> Vec2 {
+	(kt/m).sqrt()*Vec2{x: rng.standard(), y: rng.standard()}
+}
+
+/// Scale all the velocities by `scale`.
+fn rescale(v: &mut [Vec2], scale: f64) {
+	for v in v.iter_mut() {
+		*v = scale*(*v);
+	}
+}
+
+/// A way of holding particles at a temperature.
+pub trait Thermostat {
+	/// Couple the velocities `v` of particles with masses `m` to the
+	/// heat bath for a time `dt`, usually once after every step.
+	fn apply(&mut self, v: &mut [Vec2], m: &[f64], dt: f64, rng: &mut NormalDist);
+}
+
+/// Rescale the velocities to the target temperature every time.
+#[derive(Debug, Clone, Copy)]
+pub struct VelocityRescale {
+	/// Target thermal energy kT
+	pub kt: f64,
+}
+
+impl Thermostat for VelocityRescale {
+	fn apply(&mut self, v: &mut [Vec2], m: &[f64], _dt: f64, _rng: &mut NormalDist) {
+		let current = kinetic_temperature(v, m);
+		if current > 0_f64 {
+			rescale(v, (self.kt/current).sqrt());
+		}
+	}
+}
+
+/// The Berendsen weak coupling thermostat: the temperature relaxes
+/// exponentially towards the target with a time constant `tau`.
+#[derive(Debug, Clone, Copy)]
+pub struct Berendsen {
+	/// Target thermal energy kT
+	pub kt: f64,
+	/// Relaxation time
+	pub tau: f64,
+}
+
+impl Thermostat for Berendsen {
+	fn apply(&mut self, v: &mut [Vec2], m: &[f64], dt: f64, _rng: &mut NormalDist) {
+		let current = kinetic_temperature(v, m);
+		if current > 0_f64 {
+			let scale2 = 1_f64 + (dt/self.tau)*(self.kt/current - 1_f64);
+			rescale(v, scale2.max(0_f64).sqrt());
+		}
+	}
+}
+
+/// The Andersen thermostat: each particle collides with the heat bath
+/// at a `rate`, getting a new velocity from the Maxwell-Boltzmann
+/// distribution.
+#[derive(Debug, Clone, Copy)]
+pub struct Andersen {
+	/// Target thermal energy kT
+	pub kt: f64,
+	/// Collisions per particle per unit time
+	pub rate: f64,
+}
+
+impl Thermostat for Andersen {
+	fn apply(&mut self, v: &mut [Vec2], m: &[f64], dt: f64, rng: &mut NormalDist) {
+		assert_eq!(v.len(), m.len(), "velocities and masses differ in length");
+		let chance = 1_f64 - (-self.rate*dt).exp();
+		for (v, &m) in v.iter_mut().zip(m.iter()) {
+			if rng.uniform() < chance {
+				*v = maxwell(rng, self.kt, m);
+			}
+		}
+	}
+}
+
+/// The Nosé-Hoover thermostat: a friction ξ acts on the velocities,
+/// growing while the particles are hotter than the target and going
+/// negative while they are colder. Its inertia is set by a relaxation
+/// time `tau`, the period of its oscillations.
+///
+/// Each call to `apply` advances ξ and the velocities exactly for a
+/// time `dt`, in a symmetric splitting. The energy of the particles
+/// plus `energy()` is conserved.
+#[derive(Debug, Clone, Copy)]
+pub struct NoseHoover {
+	/// Target thermal energy kT
+	pub kt: f64,
+	/// Relaxation time
+	pub tau: f64,
+	/// The friction ξ
+	xi: f64,
+	/// The time integral of ξ
+	eta: f64,
+	/// Degrees of freedom at the last call
+	dof: f64,
+}
+
+impl NoseHoover {
+	pub fn new(kt: f64, tau: f64) -> NoseHoover {
+		assert!(kt > 0_f64 && tau > 0_f64, "temperature and relaxation time must be positive");
+		NoseHoover{kt, tau, xi: 0_f64, eta: 0_f64, dof: 0_f64}
+	}
+
+	/// The current friction ξ.
+	pub fn friction(&self) -> f64 {
+		self.xi
+	}
+
+	/// The thermostat mass Q = g kT τ² for `dof` degrees of freedom.
+	fn mass(&self, dof: f64) -> f64 {
+		dof*self.kt*self.tau*self.tau
+	}
+
+	/// The energy held by the thermostat, Q ξ²/2 + g kT η.
+	pub fn energy(&self) -> f64 {
+		0.5*self.mass(self.dof)*self.xi*self.xi + self.dof*self.kt*self.eta
+	}
+
+	/// Set the friction and its energy back to zero.
+	pub fn reset(&mut self) {
+		self.xi = 0_f64;
+		self.eta = 0_f64;
+	}
+}
+
+impl Thermostat for NoseHoover {
+	fn apply(&mut self, v: &mut [Vec2], m: &[f64], dt: f64, _rng: &mut NormalDist) {
+		if v.is_empty() {
+			return;
+		}
+		self.dof = 2_f64*v.len() as f64;
+		let q = self.mass(self.dof);
+		let mut twice_ke = 2_f64*kinetic_energy(v, m);
+		self.xi += 0.5*dt*(twice_ke - self.dof*self.kt)/q;
+		let scale = (-self.xi*dt).exp();
+		rescale(v, scale);
+		twice_ke *= scale*scale;
+		self.eta += self.xi*dt;
+		self.xi += 0.5*dt*(twice_ke - self.dof*self.kt)/q;
+	}
+}
+
+/// The Langevin thermostat: every particle feels a friction `friction`
+/// and random kicks from the heat bath, with the exact
+/// Ornstein-Uhlenbeck update of the velocities.
+#[derive(Debug, Clone, Copy)]
+pub struct Langevin {
+	/// Target thermal energy kT
+	pub kt: f64,
+	/// Friction (collision) rate γ, per unit time
+	pub friction: f64,
+}
+
+impl Thermostat for Langevin {
+	fn apply(&mut self, v: &mut [Vec2], m: &[f64], dt: f64, rng: &mut NormalDist) {
+		assert_eq!(v.len(), m.len(), "velocities and masses differ in length");
+		let c1 = (-self.friction*dt).exp();
+		let c2 = (1_f64 - c1*c1).sqrt();
+		for (v, &m) in v.iter_mut().zip(m.iter()) {
+			*v = c1*(*v) + c2*maxwell(rng, self.kt, m);
+		}
+	}
+}
+
+/// The pressure of particles with velocities `v` and masses `m` in a
+/// box of area `area`, from the virial theorem in two dimensions,
+/// P A = K + W/2. K is the kinetic energy and `virial` is
+/// W = Σ r_ij·f_ij over pairs of particles, with r_ij = r_i - r_j and
+/// f_ij the force on i from j, so repulsion raises the pressure.
+pub fn pressure(v: &[Vec2], m: &[f64], virial: f64, area: f64) -> f64 {
+	(kinetic_energy(v, m) + 0.5*virial)/area
+}
+
+/// Berendsen pressure coupling: the box and the positions in it are
+/// scaled by μ = [1 - κ dt/τ (P₀ - P)]^(1/2) each time, relaxing the
+/// pressure P towards P₀ over a time `tau`. Like the Berendsen
+/// thermostat it is smooth but suppresses the fluctuations of the
+/// volume.
+#[derive(Debug, Clone, Copy)]
+pub struct BerendsenBarostat {
+	/// Target pressure P₀
+	pub pressure: f64,
+	/// Relaxation time τ
+	pub tau: f64,
+	/// Isothermal compressibility κ, the relative change in area per
+	/// unit of pressure
+	pub compressibility: f64,
+}
+
+impl BerendsenBarostat {
+	/// The factor μ to scale lengths by, at a pressure `pressure`, for
+	/// a time `dt`. The area changes by at most a factor of two at a
+	/// time.
+	pub fn scale(&self, pressure: f64, dt: f64) -> f64 {
+		let area = 1_f64 - self.compressibility*dt/self.tau*(self.pressure - pressure);
+		area.clamp(0.5, 2_f64).sqrt()
+	}
+
+	/// Couple the box `boundary` holding particles at positions `r` to
+	/// the pressure bath for a time `dt`, at the current pressure
+	/// `pressure`.
+	pub fn apply(&self, pressure: f64, dt: f64, boundary: &mut Boundary, r: &mut [Vec2]) {
+		let mu = self.scale(pressure, dt);
+		boundary.set_size(mu*boundary.size());
+		for r in r.iter_mut() {
+			*r = mu*(*r);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const N : usize = 200;
+
+	fn rng() -> NormalDist {
+		let mut rng = NormalDist::new(0.0, 1.0);
+		rng.seed(24680, 13579);
+		rng
+	}
+
+	/// Particles with a spread of masses in a harmonic well, V = x²/2,
+	/// started at rest away from the center, so they begin with a
+	/// potential energy of kT = 2 per particle.
+	fn particles() -> (Vec<Vec2>, Vec<Vec2>, Vec<f64>) {
+		let mut rng = rng();
+		let x = (0..N).map( |_| maxwell(&mut rng, 2.0, 1.0)).collect();
+		let v = vec!(Vec2::zero(); N);
+		let m = (0..N).map( |i| 1.0 + (i % 5) as f64).collect();
+		(x, v, m)
+	}
+
+	/// Run velocity Verlet steps with half a thermostat step on either
+	/// side, returning the time averaged temperature over the second
+	/// half of the run and the largest change in the total energy
+	/// including `extra`.
+	fn run<T: Thermostat, E: Fn(&T) -> f64>(thermostat: &mut T, extra: E) -> (f64, f64) {
+		let (mut x, mut v, m) = particles();
+		let mut rng = rng();
+		let dt = 0.01;
+		let steps = 10000;
+		let energy = |x: &[Vec2], v: &[Vec2], t: &T| {
+			kinetic_energy(v, &m) + 0.5*x.iter().map( |x| x.norm_squared()).sum::<f64>() + extra(t)
+		};
+		let e0 = energy(&x, &v, thermostat);
+		let mut drift = 0_f64;
+		let mut total = 0_f64;
+		for step in 0..steps {
+			thermostat.apply(&mut v, &m, 0.5*dt, &mut rng);
+			for ((x, v), &m) in x.iter_mut().zip(v.iter_mut()).zip(m.iter()) {
+				*v = *v - (0.5*dt/m)*(*x);
+				*x = *x + dt*(*v);
+				*v = *v - (0.5*dt/m)*(*x);
+			}
+			thermostat.apply(&mut v, &m, 0.5*dt, &mut rng);
+			drift = drift.max((energy(&x, &v, thermostat) - e0).abs()/e0);
+			if step >= steps/2 {
+				total += kinetic_temperature(&v, &m);
+			}
+		}
+		(total/(steps/2) as f64, drift)
+	}
+
+	#[test]
+	fn test_kinetic_temperature() {
+		let v = [Vec2{x: 1.0, y: 0.0}, Vec2{x: 0.0, y: 2.0}];
+		let m = [2.0, 1.0];
+		assert_eq!(kinetic_energy(&v, &m), 3.0);
+		assert_eq!(kinetic_temperature(&v, &m), 1.5);
+		assert_eq!(kinetic_temperature(&[], &[]), 0.0);
+	}
+
+	/// Every thermostat brings the particles to the target temperature
+	#[test]
+	fn test_reach_temperature() {
+		let kt = 0.5;
+		let (t, _) = run(&mut VelocityRescale{kt}, |_| 0.0);
+		assert!((t/kt - 1.0).abs() < 1e-9, "velocity rescale {}", t);
+		let (t, _) = run(&mut Berendsen{kt, tau: 0.5}, |_| 0.0);
+		assert!((t/kt - 1.0).abs() < 0.02, "berendsen {}", t);
+		let (t, _) = run(&mut Andersen{kt, rate: 1.0}, |_| 0.0);
+		assert!((t/kt - 1.0).abs() < 0.05, "andersen {}", t);
+		let (t, _) = run(&mut Langevin{kt, friction: 1.0}, |_| 0.0);
+		assert!((t/kt - 1.0).abs() < 0.05, "langevin {}", t);
+		let (t, _) = run(&mut NoseHoover::new(kt, 0.5), |_| 0.0);
+		assert!((t/kt - 1.0).abs() < 0.05, "nose-hoover {}", t);
+	}
+
+	/// The particles and the Nosé-Hoover thermostat together conserve
+	/// energy
+	#[test]
+	fn test_nose_hoover_energy() {
+		let mut thermostat = NoseHoover::new(0.5, 0.5);
+		let (_, drift) = run(&mut thermostat, |t| t.energy());
+		assert!(drift < 1e-3, "energy drift {}", drift);
+		assert!(thermostat.friction() != 0.0);
+	}
+
+	/// An ideal gas has P A = K, and the barostat squeezes or expands
+	/// the box until the area is K/P₀, carrying the particles with it
+	#[test]
+	fn test_berendsen_barostat() {
+		let mut rng = rng();
+		let m = vec!(1.0; N);
+		let v : Vec<Vec2> = (0..N).map( |_| maxwell(&mut rng, 0.5, 1.0)).collect();
+		let k = kinetic_energy(&v, &m);
+		let mut boundary = Boundary::periodic(Vec2{x: 20.0, y: 10.0});
+		let mut r : Vec<Vec2> = (0..N).map( |i| Vec2{x: 0.1*i as f64, y: 0.05*i as f64}).collect();
+		let barostat = BerendsenBarostat{pressure: 2.0, tau: 1.0, compressibility: 0.5};
+		for _ in 0..1000 {
+			let size = boundary.size();
+			let p = pressure(&v, &m, 0.0, size.x*size.y);
+			barostat.apply(p, 0.05, &mut boundary, &mut r);
+		}
+		let size = boundary.size();
+		assert!((size.x*size.y/(k/2.0) - 1.0).abs() < 1e-6, "area {}", size.x*size.y);
+		assert!((size.x/size.y - 2.0).abs() < 1e-9);
+		assert!(r.iter().all( |&r| boundary.contains(r)));
+		assert!((r[N-1].x/size.x - 0.1*(N-1) as f64/20.0).abs() < 1e-9);
+		// Repulsion raises the pressure
+		assert!(pressure(&v, &m, 10.0, 100.0) > pressure(&v, &m, 0.0, 100.0));
+	}
+}
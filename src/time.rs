@@ -0,0 +1,152 @@
+/// Clocks and seeds
+/// ================
+///
+/// A high-resolution monotonic clock, and a source of entropy for
+/// seeding the random number generators. In the browser these use
+/// `performance.now()` and `crypto.getRandomValues()`; when running
+/// natively they use the standard library and the operating system.
+/// Neither panics if the browser gives back something unexpected.
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{
+	Instant,
+	SystemTime,
+	UNIX_EPOCH,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::hash_map::RandomState;
+#[cfg(not(target_arch = "wasm32"))]
+use std::hash::{
+	BuildHasher,
+	Hasher,
+};
+
+#[cfg(target_arch = "wasm32")]
+use stdweb::unstable::TryInto;
+
+/// Milliseconds since some fixed point in the past, from a clock that
+/// never runs backwards. Only differences between two calls are
+/// meaningful.
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> f64 {
+	let value = js! {
+		if (typeof performance !== "undefined" && performance.now) {
+			return performance.now();
+		}
+		return Date.now();
+	};
+	value.try_into().unwrap_or(0_f64)
+}
+
+/// Milliseconds since some fixed point in the past, from a clock that
+/// never runs backwards. Only differences between two calls are
+/// meaningful.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> f64 {
+	thread_local! {
+		static START: Instant = Instant::now();
+	}
+	START.with( |start| {
+		let elapsed = start.elapsed();
+		elapsed.as_secs() as f64*1000_f64 + elapsed.subsec_nanos() as f64*1.0e-6_f64
+	})
+}
+
+/// Milliseconds since the unix epoch from the system clock, or zero if
+/// the system clock is set before the epoch.
+#[cfg(not(target_arch = "wasm32"))]
+fn system_time() -> f64 {
+	match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(elapsed) => elapsed.as_secs() as f64*1000_f64 + elapsed.subsec_nanos() as f64*1.0e-6_f64,
+		Err(_) => 0_f64,
+	}
+}
+
+/// Scrambles a `u64` (the SplitMix64 finalizer), used to turn weak
+/// entropy like a time stamp into well mixed bits.
+fn mix(mut z: u64) -> u64 {
+	z = z.wrapping_add(0x9e3779b97f4a7c15_u64);
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9_u64);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb_u64);
+	z ^ (z >> 31)
+}
+
+/// Entropy from the operating system. The standard library seeds each
+/// `RandomState` from the OS, so hashing with a fresh one gives
+/// unpredictable bits without any extra dependencies.
+#[cfg(not(target_arch = "wasm32"))]
+fn os_entropy(salt: u64) -> u64 {
+	let mut hasher = RandomState::new().build_hasher();
+	hasher.write_u64(salt);
+	hasher.write_u64(system_time().to_bits());
+	hasher.finish()
+}
+
+/// Milliseconds since the unix epoch from `Date.now()`.
+#[cfg(target_arch = "wasm32")]
+fn date_now() -> f64 {
+	let value = js! {
+		return Date.now();
+	};
+	value.try_into().unwrap_or(0_f64)
+}
+
+/// Fallback entropy from the clocks, for when nothing better is
+/// available.
+#[cfg(target_arch = "wasm32")]
+fn clock_entropy() -> (u64, u64) {
+	let s0 = mix(date_now().to_bits());
+	let s1 = mix(now().to_bits() ^ s0);
+	(s0, s1)
+}
+
+/// A pair of random `u64` seeds taken from `crypto.getRandomValues()`.
+#[cfg(target_arch = "wasm32")]
+pub fn entropy_seed() -> (u64, u64) {
+	let value = js! {
+		var cr = (typeof crypto !== "undefined") ? crypto : null;
+		if (cr === null || !cr.getRandomValues) {
+			return null;
+		}
+		var words = new Uint32Array(4);
+		cr.getRandomValues(words);
+		return Array.prototype.slice.call(words);
+	};
+	let words : Result<Vec<u32>,_> = value.try_into();
+	match words {
+		Ok(ref words) if words.len() == 4 => {
+			let s0 = (words[0] as u64) << 32 | words[1] as u64;
+			let s1 = (words[2] as u64) << 32 | words[3] as u64;
+			(s0, s1)
+		},
+		_ => clock_entropy(),
+	}
+}
+
+/// A pair of random `u64` seeds taken from the operating system.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn entropy_seed() -> (u64, u64) {
+	let s0 = os_entropy(0);
+	let s1 = os_entropy(mix(s0));
+	(s0, s1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_now() {
+		let t0 = now();
+		let t1 = now();
+		assert!(t0 >= 0.0);
+		assert!(t1 >= t0);
+	}
+
+	#[test]
+	fn test_entropy_seed() {
+		let (a0, a1) = entropy_seed();
+		let (b0, b1) = entropy_seed();
+		assert!(a0 != a1);
+		assert!((a0, a1) != (b0, b1));
+	}
+}